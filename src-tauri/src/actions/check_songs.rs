@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::gui_logger::GuiLogger;
+use crate::song_file::SongFile;
+use crate::uploader_client::MusicUploaderClientError;
+use crate::{GuiState, Song};
+
+// keeps a request for a huge album from being bigger than the server likes
+const MAX_HASHES_PER_CHECK: usize = 200;
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerStatus {
+    Present,
+    Missing,
+    // the song could not be read or the server could not be asked
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct SongCheck {
+    key: String,
+    status: ServerStatus,
+}
+
+#[derive(Serialize)]
+pub struct ServerCheck {
+    // false for servers from before they could be asked, every song is unknown then
+    supported: bool,
+    songs: Vec<SongCheck>,
+}
+
+/// Hashes every song and asks the server which ones it already has, so the
+/// user can see what an upload would actually send before sending it.
+#[tauri::command]
+pub async fn check_songs_on_server(
+    state: State<'_, GuiState>,
+    songs: Vec<Song>,
+    profile_id: Option<String>,
+) -> Result<ServerCheck, String> {
+    let run_state = state
+        .run_state
+        .as_ref()
+        .ok_or("Client did not succesfully boot".to_string())?;
+    let logger = GuiLogger::new(state.app_handle.clone());
+    let song_hashes = run_state.song_hashes.clone();
+    let songs_to_hash = songs.clone();
+    // reading every song would hold up the async runtime
+    let hashes: Vec<Option<String>> = tauri::async_runtime::spawn_blocking(move || {
+        songs_to_hash
+            .iter()
+            .map(|song| SongFile::open(song).and_then(|file| song_hashes.hash(&file)))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .into_iter()
+    .map(|hash| hash.map_err(|e| logger.log(e.to_string())).ok())
+    .collect();
+    let known_hashes: Vec<String> = hashes.iter().flatten().cloned().collect();
+    let config = run_state
+        .get_profile_config(profile_id.as_ref())
+        .map_err(|e| e.to_string())?;
+    let mut present = HashSet::new();
+    let mut server_answered = true;
+    let mut supported = true;
+    for chunk in known_hashes.chunks(MAX_HASHES_PER_CHECK) {
+        match run_state.client.check_hashes(&config, chunk).await {
+            Ok(present_hashes) => present.extend(present_hashes),
+            // older servers do not have the route at all
+            Err(MusicUploaderClientError::UnhappyResponse(404 | 405, _)) => {
+                server_answered = false;
+                supported = false;
+                break;
+            }
+            Err(e) => {
+                logger.log(format!("Could not ask the server which songs it has: {e}"));
+                server_answered = false;
+                break;
+            }
+        }
+    }
+    let checks = songs
+        .iter()
+        .zip(hashes)
+        .map(|(song, hash)| SongCheck {
+            key: song.key(),
+            status: match hash {
+                Some(hash) if server_answered => match present.contains(&hash) {
+                    true => ServerStatus::Present,
+                    false => ServerStatus::Missing,
+                },
+                _ => ServerStatus::Unknown,
+            },
+        })
+        .collect();
+    Ok(ServerCheck {
+        supported,
+        songs: checks,
+    })
+}
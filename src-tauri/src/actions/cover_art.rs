@@ -0,0 +1,11 @@
+use crate::cover_art::{self, CoverArtCandidate};
+use crate::Song;
+
+/// every image that could be the album's cover, most likely first.
+#[tauri::command]
+pub async fn find_cover_art(songs: Vec<Song>) -> Result<Vec<CoverArtCandidate>, String> {
+    // reading every image and embedded picture would hold up the async runtime
+    tauri::async_runtime::spawn_blocking(move || cover_art::find_cover_art(&songs))
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,25 @@
+use tauri::State;
+
+use crate::file_names::{upload_names, UploadNames};
+use crate::{GuiState, Song};
+
+/// the album, artist and song names an upload of the album would send.
+#[tauri::command]
+pub async fn preview_upload_names(
+    state: State<'_, GuiState>,
+    album_name: String,
+    artist: String,
+    songs: Vec<Song>,
+) -> Result<UploadNames, String> {
+    let tag_names = state
+        .run_state
+        .as_ref()
+        .filter(|run_state| run_state.settings.read().unwrap().name_songs_from_tags())
+        .map(|run_state| run_state.tag_names.clone());
+    // reading the tags of songs that are not cached yet would hold up the async runtime
+    tauri::async_runtime::spawn_blocking(move || {
+        upload_names(&album_name, &artist, &songs, tag_names.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
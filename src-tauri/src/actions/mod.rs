@@ -1 +1,9 @@
+pub(crate) mod check_songs;
+pub(crate) mod cover_art;
+pub(crate) mod file_names;
+pub(crate) mod profiles;
+pub(crate) mod scan_directory;
+pub(crate) mod song_metadata;
 pub(crate) mod upload_album;
+pub(crate) mod upload_queue;
+pub(crate) mod validate_album;
@@ -0,0 +1,72 @@
+use tauri::State;
+
+use crate::settings::{ProfileList, Settings};
+use crate::{GuiState, RunState};
+
+#[tauri::command]
+pub fn list_profiles(state: State<'_, GuiState>) -> Result<ProfileList, String> {
+    let run_state = get_run_state(&state)?;
+    let profiles = run_state.settings.read().unwrap().list_profiles();
+    Ok(profiles)
+}
+
+/// adds an empty profile, it is filled in by switching to it and saving the settings.
+#[tauri::command]
+pub fn create_profile(state: State<'_, GuiState>, name: &str) -> Result<ProfileList, String> {
+    change_profiles(&state, |settings| settings.create_profile(name).map(|_| ()))
+}
+
+#[tauri::command]
+pub fn rename_profile(
+    state: State<'_, GuiState>,
+    profile_id: &str,
+    name: &str,
+) -> Result<ProfileList, String> {
+    change_profiles(&state, |settings| settings.rename_profile(profile_id, name))
+}
+
+#[tauri::command]
+pub fn delete_profile(state: State<'_, GuiState>, profile_id: &str) -> Result<ProfileList, String> {
+    let mut deleted = None;
+    let profiles = change_profiles(&state, |settings| {
+        deleted = Some(settings.delete_profile(profile_id)?);
+        Ok(())
+    })?;
+    // the profile is already gone from the settings, secrets left behind are only clutter
+    if let Some(profile) = deleted {
+        if let Err(e) = profile.forget_secrets(state.secret_store.as_ref()) {
+            println!("failed to forget the secrets of {}: {e}", profile.name);
+        }
+    }
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub fn switch_profile(state: State<'_, GuiState>, profile_id: &str) -> Result<ProfileList, String> {
+    change_profiles(&state, |settings| settings.switch_profile(profile_id))
+}
+
+/// applies `change` and saves the settings, the settings in memory are only
+/// changed when the change itself succeeds.
+fn change_profiles(
+    state: &State<'_, GuiState>,
+    change: impl FnOnce(&mut Settings) -> Result<(), String>,
+) -> Result<ProfileList, String> {
+    let run_state = get_run_state(state)?;
+    let to_save = {
+        let mut settings = run_state.settings.write().unwrap();
+        let mut changed = settings.clone();
+        change(&mut changed)?;
+        *settings = changed.clone();
+        changed
+    };
+    to_save.save_settings(&state.app_handle, state.secret_store.as_ref())?;
+    Ok(to_save.list_profiles())
+}
+
+fn get_run_state<'a>(state: &'a State<'_, GuiState>) -> Result<&'a RunState, String> {
+    state
+        .run_state
+        .as_ref()
+        .ok_or("Client did not succesfully boot".to_string())
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::metadata::{read_metadata, SongMetadata};
+use crate::song_file::SongFile;
+use crate::zip_entry::ZipEntry;
+use crate::{GuiState, Song};
+use serde::Serialize;
+use tauri::State;
+
+/// An album made up from a scanned directory, for the user to check before uploading.
+#[derive(Serialize)]
+pub struct ProposedAlbum {
+    album_name: String,
+    // empty when neither the tags nor the folders say who it is by
+    artist: String,
+    songs: Vec<Song>,
+}
+
+#[derive(Serialize)]
+pub struct DirectoryScan {
+    albums: Vec<ProposedAlbum>,
+    // folders and zips that could not be read, the rest of the scan goes on without them
+    warnings: Vec<String>,
+}
+
+struct ScannedSong {
+    path: PathBuf,
+    // name of the song inside the zip at `path`
+    entry: Option<String>,
+    metadata: Option<SongMetadata>,
+}
+
+/// Songs are grouped by their album tag, so an album split into disc folders
+/// still comes back as one album and a folder of loose songs from different
+/// albums comes back as several. Untagged songs are grouped by folder, or by
+/// the zip they came out of.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum AlbumKey {
+    Tagged {
+        album: String,
+        artist: String,
+        // only set when no artist is tagged, see `album_key`
+        container: Option<PathBuf>,
+    },
+    Folder(PathBuf),
+    Archive(PathBuf),
+}
+
+#[tauri::command]
+pub async fn scan_directory(
+    state: State<'_, GuiState>,
+    path: String,
+) -> Result<DirectoryScan, String> {
+    let valid_extensions = state
+        .run_state
+        .as_ref()
+        .map(|s| s.settings.read().unwrap().valid_extensions.clone())
+        .ok_or("Client did not succesfully boot".to_string())?;
+    // walking the tree and reading every song's tags would hold up the async runtime
+    tauri::async_runtime::spawn_blocking(move || scan(&path, &valid_extensions))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn scan(path: &str, valid_extensions: &[String]) -> Result<DirectoryScan, String> {
+    let root = PathBuf::from(path);
+    let mut warnings = Vec::new();
+    let songs = if root.is_dir() {
+        find_songs(&root, valid_extensions, &mut warnings)?
+    } else if is_zip(&root) {
+        find_songs_in_zip(&root, valid_extensions)?
+    } else {
+        return Err(format!("{path} is not a directory or zip archive"));
+    };
+
+    let mut albums: BTreeMap<AlbumKey, Vec<ScannedSong>> = BTreeMap::new();
+    for (song_path, entry) in songs {
+        let mut song = ScannedSong {
+            path: song_path,
+            entry,
+            metadata: None,
+        };
+        song.metadata = SongFile::open(&to_song(&song))
+            .ok()
+            .and_then(|file| read_metadata(&file).ok());
+        albums.entry(album_key(&song)).or_default().push(song);
+    }
+    let albums = albums
+        .into_iter()
+        .map(|(key, songs)| propose_album(key, songs))
+        .collect();
+    Ok(DirectoryScan { albums, warnings })
+}
+
+/// every song below `root`, along with the songs in any zip found on the way.
+/// Symlinked folders are not followed so a link back up the tree cannot send
+/// us round in circles. Only `root` itself has to be readable, anything below
+/// it that is not gets a warning instead.
+fn find_songs(
+    root: &Path,
+    valid_extensions: &[String],
+    warnings: &mut Vec<String>,
+) -> Result<Vec<(PathBuf, Option<String>)>, String> {
+    let mut songs = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let entries = match fs::read_dir(&folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                let message = format!("Failed to read {}: {e}", folder.to_string_lossy());
+                match folder == root {
+                    true => return Err(message),
+                    false => {
+                        warnings.push(message);
+                        continue;
+                    }
+                }
+            }
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                folders.push(path);
+            } else if is_zip(&path) {
+                match find_songs_in_zip(&path, valid_extensions) {
+                    Ok(zip_songs) => songs.extend(zip_songs),
+                    Err(e) => warnings.push(e),
+                }
+            } else if has_valid_extension(&path, valid_extensions) {
+                songs.push((path, None));
+            }
+        }
+    }
+    Ok(songs)
+}
+
+fn find_songs_in_zip(
+    archive: &Path,
+    valid_extensions: &[String],
+) -> Result<Vec<(PathBuf, Option<String>)>, String> {
+    let archive_path = archive.to_string_lossy().to_string();
+    let entries =
+        ZipEntry::list(&archive_path).map_err(|e| format!("Failed to read {archive_path}: {e}"))?;
+    Ok(entries
+        .into_iter()
+        // zips made on a mac carry a shadow copy of every file's attributes in here
+        .filter(|entry| !entry.starts_with("__MACOSX/"))
+        .filter(|entry| has_valid_extension(Path::new(entry), valid_extensions))
+        .map(|entry| (archive.to_path_buf(), Some(entry)))
+        .collect())
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.is_file() && has_valid_extension(path, &["zip".to_string()])
+}
+
+fn has_valid_extension(path: &Path, valid_extensions: &[String]) -> bool {
+    let Some(extension) = path.extension() else {
+        return false;
+    };
+    let extension = extension.to_string_lossy();
+    valid_extensions
+        .iter()
+        .any(|valid| valid.eq_ignore_ascii_case(&extension))
+}
+
+fn album_key(song: &ScannedSong) -> AlbumKey {
+    let fallback = || match &song.entry {
+        Some(_) => AlbumKey::Archive(song.path.clone()),
+        None => AlbumKey::Folder(container(song)),
+    };
+    let Some(metadata) = &song.metadata else {
+        return fallback();
+    };
+    let Some(album) = &metadata.album else {
+        return fallback();
+    };
+    // track artists can differ on a compilation, the album artist should not. without
+    // one, the track artist keeps every "Greatest Hits" from becoming one album
+    let artist = [&metadata.album_artist, &metadata.artist]
+        .into_iter()
+        .flatten()
+        .find(|artist| !artist.trim().is_empty());
+    AlbumKey::Tagged {
+        album: album.clone(),
+        artist: artist.cloned().unwrap_or_default(),
+        // and with no artist at all, where the songs are is all that is left to go on
+        container: match artist {
+            Some(_) => None,
+            None => Some(container(song)),
+        },
+    }
+}
+
+/// the folder a song is in, or the zip for a song in one.
+fn container(song: &ScannedSong) -> PathBuf {
+    match &song.entry {
+        Some(_) => song.path.clone(),
+        None => song.path.parent().unwrap_or(&song.path).to_path_buf(),
+    }
+}
+
+fn propose_album(key: AlbumKey, mut songs: Vec<ScannedSong>) -> ProposedAlbum {
+    songs.sort_by_key(|song| {
+        let metadata = song.metadata.as_ref();
+        (
+            metadata.and_then(|m| m.disc_number).unwrap_or(0),
+            metadata.and_then(|m| m.track_number).unwrap_or(u32::MAX),
+            song.path.clone(),
+            song.entry.clone(),
+        )
+    });
+    let album_name = match &key {
+        AlbumKey::Tagged { album, .. } => album.clone(),
+        AlbumKey::Folder(folder) => file_name(folder),
+        AlbumKey::Archive(archive) => archive
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    let artist = match &key {
+        AlbumKey::Tagged { artist, .. } if !artist.is_empty() => artist.clone(),
+        _ => most_common_artist(&songs).unwrap_or_default(),
+    };
+    ProposedAlbum {
+        album_name,
+        artist,
+        songs: songs.iter().map(to_song).collect(),
+    }
+}
+
+fn most_common_artist(songs: &[ScannedSong]) -> Option<String> {
+    let mut counts: BTreeMap<&String, usize> = BTreeMap::new();
+    for song in songs {
+        let metadata = song.metadata.as_ref();
+        if let Some(artist) = metadata.and_then(|m| m.album_artist.as_ref().or(m.artist.as_ref())) {
+            *counts.entry(artist).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(artist, _)| artist.clone())
+}
+
+// songs are named after their title tag the same way the gui names dropped files
+fn to_song(song: &ScannedSong) -> Song {
+    // the entry name can include the folders it was in inside the zip
+    let file_path = match &song.entry {
+        Some(entry) => Path::new(entry),
+        None => song.path.as_path(),
+    };
+    let title = song.metadata.as_ref().and_then(|m| m.title.as_ref());
+    let song_name = match (title, file_path.extension()) {
+        (Some(title), Some(extension)) => format!("{title}.{}", extension.to_string_lossy()),
+        _ => file_name(file_path),
+    };
+    Song {
+        song_name,
+        path: song.path.to_string_lossy().to_string(),
+        entry: song.entry.clone(),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(path: &str, album_artist: Option<&str>, artist: Option<&str>) -> ScannedSong {
+        ScannedSong {
+            path: PathBuf::from(path),
+            entry: None,
+            metadata: Some(SongMetadata {
+                album: Some("Greatest Hits".to_string()),
+                album_artist: album_artist.map(str::to_string),
+                artist: artist.map(str::to_string),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn album_artist_keeps_discs_and_compilations_together() {
+        assert!(
+            album_key(&song("/music/cd1/1.flac", Some("Various"), Some("A")))
+                == album_key(&song("/music/cd2/1.flac", Some("Various"), Some("B")))
+        );
+    }
+
+    #[test]
+    fn albums_without_album_artist_are_not_merged() {
+        assert!(
+            album_key(&song("/music/1.flac", Some(""), Some("A")))
+                != album_key(&song("/music/2.flac", None, Some("B")))
+        );
+        assert!(
+            album_key(&song("/music/a/1.flac", None, None))
+                != album_key(&song("/music/b/1.flac", None, None))
+        );
+        assert!(
+            album_key(&song("/music/a/1.flac", None, None))
+                == album_key(&song("/music/a/2.flac", None, None))
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use crate::metadata::{read_metadata, SongMetadata};
+use crate::song_file::SongFile;
+use crate::Song;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct SongMetadataResult {
+    path: String,
+    entry: Option<String>,
+    metadata: Option<SongMetadata>,
+    error: Option<String>,
+}
+
+/// reads the tags of every song, a file that cannot be read does not stop the others.
+#[tauri::command]
+pub async fn read_song_metadata(songs: Vec<Song>) -> Result<Vec<SongMetadataResult>, String> {
+    // reading every song's tags would hold up the async runtime
+    tauri::async_runtime::spawn_blocking(move || songs.into_iter().map(read_song).collect())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn read_song(song: Song) -> SongMetadataResult {
+    let metadata = SongFile::open(&song)
+        .map_err(|e| e.to_string())
+        .and_then(|file| read_metadata(&file).map_err(|e| e.to_string()));
+    let (metadata, error) = match metadata {
+        Ok(metadata) => (Some(metadata), None),
+        Err(e) => (None, Some(e)),
+    };
+    SongMetadataResult {
+        path: song.path,
+        entry: song.entry,
+        metadata,
+        error,
+    }
+}
@@ -1,35 +1,112 @@
 mod upload_logic;
 
+use crate::cover_art::{load_cover_art, CoverArt};
+use crate::file_names::upload_names;
 use crate::gui_logger::GuiLogger;
+use crate::upload_journal::JournalAlbum;
+use crate::upload_queue::QueuedAlbum;
 use crate::uploader_client::MusicUploaderClientError;
-use crate::{result_to_string, GuiState, Song};
+use crate::{result_to_string, GuiState, RunState, Song};
+use futures::{stream, StreamExt};
 use tauri::{AppHandle, State};
-use upload_logic::upload_song;
+use tokio_util::sync::CancellationToken;
+use upload_logic::{song_stamp, upload_song};
 
 #[tauri::command]
 pub async fn upload_album(
     app: AppHandle,
     state: State<'_, GuiState>,
-    album_name: &str,
+    album: QueuedAlbum,
+) -> Result<String, String> {
+    result_to_string(upload_album_inner(app, state, album).await)
+}
+
+#[tauri::command]
+pub fn list_resumable_uploads(state: State<'_, GuiState>) -> Vec<JournalAlbum> {
+    state
+        .run_state
+        .as_ref()
+        .map(|run_state| run_state.journal.lock().unwrap().list())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub async fn resume_upload(
+    app: AppHandle,
+    state: State<'_, GuiState>,
+    journal_id: &str,
     album_id: &str,
-    artist: &str,
-    songs: Vec<Song>,
 ) -> Result<String, String> {
-    result_to_string(upload_album_inner(app, state, album_name, album_id, artist, songs).await)
+    result_to_string(resume_upload_inner(app, state, journal_id, album_id).await)
 }
 
-async fn upload_album_inner(
+async fn resume_upload_inner(
     app: AppHandle,
     state: State<'_, GuiState>,
-    album_name: &str,
+    journal_id: &str,
     album_id: &str,
-    artist: &str,
-    songs: Vec<Song>,
+) -> Result<String, MusicUploaderClientError> {
+    let journal_album = state
+        .run_state
+        .as_ref()
+        .and_then(|run_state| run_state.journal.lock().unwrap().get(journal_id))
+        .ok_or(MusicUploaderClientError::AlbumUploadFailure(format!(
+            "There is no resumable upload with id {journal_id}"
+        )))?;
+    let songs = journal_album
+        .songs
+        .into_iter()
+        .map(|song| Song {
+            song_name: song.song_name,
+            path: song.path,
+            entry: song.entry,
+        })
+        .collect();
+    let album = QueuedAlbum {
+        album_id: album_id.to_string(),
+        album_name: journal_album.album_name,
+        artist: journal_album.artist,
+        songs,
+        journal_id: Some(journal_album.journal_id),
+        cover_art: journal_album.cover_art,
+        profile_id: journal_album.profile_id,
+    };
+    upload_album_inner(app, state, album).await
+}
+
+#[tauri::command]
+pub fn cancel_upload(state: State<'_, GuiState>, album_id: &str) -> Result<String, String> {
+    let run_state = state
+        .run_state
+        .as_ref()
+        .ok_or("Client did not succesfully boot".to_string())?;
+    if run_state.upload_queue.lock().unwrap().remove(album_id) {
+        let logger = GuiLogger::new(state.app_handle.clone());
+        logger.queue_changed(&run_state.upload_queue.lock().unwrap().snapshot());
+        logger.album_cancelled(&album_id.to_string());
+        return Ok(format!("Removed album {album_id} from the upload queue"));
+    }
+    match run_state.cancel_upload(album_id) {
+        true => Ok(format!("Cancelling upload of album {album_id}")),
+        false => Err(format!("Album {album_id} is not uploading")),
+    }
+}
+
+pub(crate) async fn upload_album_inner(
+    app: AppHandle,
+    state: State<'_, GuiState>,
+    album: QueuedAlbum,
 ) -> Result<String, MusicUploaderClientError> {
     let logger = GuiLogger::new(app);
-    let album_id = album_id.to_string();
-    let album_name = album_name.to_string();
-    let artist = artist.to_string();
+    let QueuedAlbum {
+        album_id,
+        album_name,
+        artist,
+        songs,
+        journal_id,
+        cover_art: cover_art_source,
+        profile_id,
+    } = album;
     logger.log("gui backend received album upload request".to_string());
     logger.album_is_uploading(&album_id);
     let run_state = state
@@ -38,37 +115,201 @@ async fn upload_album_inner(
         .ok_or(MusicUploaderClientError::BadConfig(
             "Client did not succesfully boot".to_string(),
         ))?;
-    let mut results: Vec<Result<String, MusicUploaderClientError>> = Vec::new();
-    for song in songs.iter() {
-        logger.file_is_uploading(&album_id, &song.path);
-        let result = upload_song(run_state, &logger, &album_name, &artist, song).await;
-        logger.file_report(
-            &album_id,
-            &song.path,
+    // a profile deleted while the album waited should stop the upload before anything is sent
+    let config = match run_state.get_profile_config(profile_id.as_ref()) {
+        Ok(config) => config,
+        Err(e) => {
+            logger.album_report(&album_id, false, e.to_string());
+            return Err(e);
+        }
+    };
+    // a bad cover should stop the upload before any songs are sent rather than after
+    let cover_art = match cover_art_source.as_ref().map(load_cover_art).transpose() {
+        Ok(cover_art) => cover_art,
+        Err(e) => {
+            logger.album_report(&album_id, false, e.to_string());
+            return Err(e);
+        }
+    };
+    let journal_id = journal_id.unwrap_or_else(|| {
+        run_state.journal.lock().unwrap().start_album(
+            &album_name,
+            &artist,
+            &songs,
+            &cover_art_source,
+            &profile_id,
+        )
+    });
+    // the journal keeps the names as typed, so resuming cleans them up the same way again
+    let from_tags = run_state.settings.read().unwrap().name_songs_from_tags();
+    let tag_names = from_tags.then_some(run_state.tag_names.as_ref());
+    let names = upload_names(&album_name, &artist, &songs, tag_names);
+    let album = AlbumUpload {
+        album_id,
+        journal_id,
+        album_name: names.album,
+        artist: names.artist,
+        profile_id,
+    };
+    let album_id = &album.album_id;
+    let song_concurrency = config.song_concurrency;
+    let cancel_token = run_state.start_upload(album_id);
+    // iterating indices rather than `songs.iter()` keeps the closure free of a borrowed
+    // argument, otherwise the compiler cannot prove the resulting future is Send.
+    let song_uploads = (0..songs.len()).map(|index| {
+        upload_and_report_song(
+            run_state,
+            &logger,
+            &cancel_token,
+            &album,
+            &songs[index],
+            &names.songs[index].name,
+        )
+    });
+    let results: Vec<Result<String, MusicUploaderClientError>> = stream::iter(song_uploads)
+        .buffer_unordered(song_concurrency)
+        .collect()
+        .await;
+    run_state.finish_upload(album_id);
+    let mut total_result = get_album_upload_result(results);
+    if let (Ok(_), Some(cover_art)) = (&total_result, &cover_art) {
+        match upload_cover_art(run_state, &logger, &album, cover_art).await {
+            Ok(_) => {}
+            // servers from before cover art uploads have no route for it, the songs are still fine
+            Err(MusicUploaderClientError::UnhappyResponse(404 | 405, _)) => {
+                let warning = "the server does not support cover art, so the album has none";
+                logger.log(format!("Cover art was not uploaded: {warning}"));
+                total_result = total_result.map(|message| format!("{message}, but {warning}"));
+            }
+            // left in the journal on failure, so uploading again only retries the cover
+            Err(e) => {
+                total_result = Err(MusicUploaderClientError::AlbumUploadFailure(format!(
+                    "The songs were uploaded but the cover art was not: {e}"
+                )))
+            }
+        }
+    }
+    if total_result.is_ok() {
+        run_state
+            .journal
+            .lock()
+            .unwrap()
+            .finish_album(&album.journal_id);
+    }
+    match &total_result {
+        Err(MusicUploaderClientError::Cancelled) => logger.album_cancelled(album_id),
+        _ => logger.album_report(
+            album_id,
+            total_result.is_ok(),
+            match &total_result {
+                Ok(message) => message.to_string(),
+                Err(e) => e.to_string(),
+            },
+        ),
+    }
+    total_result?;
+    trigger_scan_inner(state, album.profile_id.as_ref()).await
+}
+
+async fn upload_cover_art(
+    run_state: &RunState,
+    logger: &GuiLogger,
+    album: &AlbumUpload,
+    cover_art: &CoverArt,
+) -> Result<String, MusicUploaderClientError> {
+    logger.log(format!(
+        "uploading {}x{} cover art",
+        cover_art.width, cover_art.height
+    ));
+    run_state
+        .client
+        .upload_cover_art(
+            &run_state.get_profile_config(album.profile_id.as_ref())?,
+            cover_art,
+            &album.artist,
+            &album.album_name,
+        )
+        .await
+}
+
+/// everything about an album upload that the individual song uploads need to know.
+struct AlbumUpload {
+    // id the gui knows the album by
+    album_id: String,
+    // id the album is tracked under in the upload journal
+    journal_id: String,
+    // both already cleaned up for the server
+    album_name: String,
+    artist: String,
+    // None uploads to whichever profile is active
+    profile_id: Option<String>,
+}
+
+async fn upload_and_report_song(
+    run_state: &RunState,
+    logger: &GuiLogger,
+    cancel_token: &CancellationToken,
+    album: &AlbumUpload,
+    song: &Song,
+    file_name: &String,
+) -> Result<String, MusicUploaderClientError> {
+    let album_id = &album.album_id;
+    let song_key = &song.key();
+    // a song that changed since it was uploaded is uploaded again
+    let stamp = song_stamp(song).await.ok();
+    let is_complete = stamp.is_some_and(|stamp| {
+        run_state
+            .journal
+            .lock()
+            .unwrap()
+            .is_song_complete(&album.journal_id, song_key, stamp)
+    });
+    if is_complete {
+        let message = format!("{} was already uploaded", song.song_name);
+        logger.file_report(album_id, song_key, true, message.clone());
+        return Ok(message);
+    }
+    if cancel_token.is_cancelled() {
+        logger.file_cancelled(album_id, song_key);
+        return Err(MusicUploaderClientError::Cancelled);
+    }
+    logger.file_is_uploading(album_id, song_key);
+    // dropping the upload future on cancel also drops any request that is in flight.
+    let result = cancel_token
+        .run_until_cancelled(upload_song(run_state, logger, album, song, file_name))
+        .await
+        .unwrap_or(Err(MusicUploaderClientError::Cancelled));
+    if let (Ok(_), Some(stamp)) = (&result, stamp) {
+        run_state
+            .journal
+            .lock()
+            .unwrap()
+            .record_song_complete(&album.journal_id, song_key, stamp);
+    }
+    match &result {
+        Err(MusicUploaderClientError::Cancelled) => logger.file_cancelled(album_id, song_key),
+        _ => logger.file_report(
+            album_id,
+            song_key,
             result.is_ok(),
             match &result {
                 Ok(message) => message.to_string(),
                 Err(e) => e.to_string(),
             },
-        );
-        results.push(result);
+        ),
     }
-    let total_result = get_album_upload_result(results);
-    logger.album_report(
-        &album_id,
-        total_result.is_ok(),
-        match &total_result {
-            Ok(message) => message.to_string(),
-            Err(e) => e.to_string(),
-        },
-    );
-    total_result?;
-    trigger_scan_inner(state).await
+    result
 }
 
 fn get_album_upload_result(
     upload_results: Vec<Result<String, MusicUploaderClientError>>,
 ) -> Result<String, MusicUploaderClientError> {
+    if upload_results
+        .iter()
+        .any(|result| matches!(result, Err(MusicUploaderClientError::Cancelled)))
+    {
+        return Err(MusicUploaderClientError::Cancelled);
+    }
     for result in upload_results {
         match result {
             Ok(_) => continue,
@@ -85,6 +326,7 @@ fn get_album_upload_result(
 
 async fn trigger_scan_inner(
     state: State<'_, GuiState>,
+    profile_id: Option<&String>,
 ) -> Result<String, MusicUploaderClientError> {
     let run_state = state
         .run_state
@@ -93,7 +335,8 @@ async fn trigger_scan_inner(
             "Client did not succesfully boot".to_string(),
         ))?;
     println!("stargin trigger scan");
-    let result = run_state.client.trigger_scan(&run_state.get_config()).await;
+    let config = run_state.get_profile_config(profile_id)?;
+    let result = run_state.client.trigger_scan(&config).await;
     println!("finished triggering scan: {:?}", result);
     result
 }
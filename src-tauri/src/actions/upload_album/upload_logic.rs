@@ -1,96 +1,182 @@
-use std::{collections::HashSet, fs};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
-use music_uploader_server::model::DeclareUploadResponse;
+use futures::{stream, StreamExt};
 
-use crate::{gui_logger::GuiLogger, uploader_client::{MusicUploaderClient, MusicUploaderClientConfig, MusicUploaderClientError}, RunState, Song};
+use crate::{
+    audio_format::check_audio_file,
+    gui_logger::GuiLogger,
+    song_file::{FileStamp, SongFile, SongHashes},
+    upload_journal::UploadJournal,
+    upload_progress::UploadProgress,
+    uploader_client::{
+        DeclaredUpload, MusicUploaderClient, MusicUploaderClientConfig, MusicUploaderClientError,
+    },
+    RunState, Song,
+};
+
+use super::AlbumUpload;
 
 pub async fn upload_song(
     run_state: &RunState,
     logger: &GuiLogger,
-    album: &String,
-    artist: &String,
+    album: &AlbumUpload,
     song: &Song,
+    file_name: &String,
 ) -> Result<String, MusicUploaderClientError> {
-    let uploader = UploadState::new(run_state, logger, album, artist, song)?;
-    match uploader.should_upload_in_parts() {
+    let uploader = UploadState::new(run_state, logger, album, song, file_name).await?;
+    let progress = uploader.progress.clone();
+    let result = match uploader.should_upload_in_parts() {
         true => uploader.send_song_in_parts().await,
         false => uploader.send_song().await,
+    };
+    if result.is_ok() {
+        progress.finish();
     }
+    result
+}
+
+/// what the song is like on disk right now, to tell whether it changed since the journal saw it.
+pub async fn song_stamp(song: &Song) -> Result<FileStamp, MusicUploaderClientError> {
+    let song = song.clone();
+    run_blocking(move || SongFile::open(&song).map(|file| file.stamp())).await
 }
 
 const MAX_MULTIPART_UPLOAD_ATTEMPT: u8 = 2;
+// servers that predate large file support track part indices as a u8 and sizes as a u32.
+const LEGACY_MAX_PARTS: u64 = u8::MAX as u64;
+const LEGACY_MAX_SIZE_BYTES: u64 = u32::MAX as u64;
 
 struct UploadState<'a> {
     client: &'a MusicUploaderClient,
     config: MusicUploaderClientConfig,
     logger: &'a GuiLogger,
-    album: &'a String,
-    artist: &'a String,
+    journal: &'a Mutex<UploadJournal>,
+    song_hashes: Arc<SongHashes>,
+    album: &'a AlbumUpload,
     song: &'a Song,
-    data: Vec<u8>,
+    // what the song is called on the server
+    file_name: &'a String,
+    file: Arc<SongFile>,
+    progress: Arc<UploadProgress>,
 }
 
 impl<'a> UploadState<'a> {
-    fn new(
+    async fn new(
         run_state: &'a RunState,
         logger: &'a GuiLogger,
-        album: &'a String,
-        artist: &'a String,
+        album: &'a AlbumUpload,
         song: &'a Song,
+        file_name: &'a String,
     ) -> Result<Self, MusicUploaderClientError> {
-        let data = fs::read(&song.path).map_err(|e| {
-            MusicUploaderClientError::FileReadError(song.path.to_string(), Box::new(e))
-        })?;
+        let song_to_open = song.clone();
+        let file = run_blocking(move || {
+            let file = SongFile::open(&song_to_open)?;
+            // a broken file would only fail on the server, after it has been sent
+            check_audio_file(&file)?;
+            Ok(Arc::new(file))
+        })
+        .await?;
+        let progress = UploadProgress::new(
+            logger.clone(),
+            &album.album_id,
+            &song.key(),
+            file.size_bytes(),
+        );
         let client = &run_state.client;
-        let config = run_state.get_config();
+        let config = run_state.get_profile_config(album.profile_id.as_ref())?;
         Ok(Self {
             client,
             config,
             logger,
+            journal: &run_state.journal,
+            song_hashes: run_state.song_hashes.clone(),
             album,
-            artist,
             song,
-            data,
+            file_name,
+            file,
+            progress,
         })
     }
 
     fn should_upload_in_parts(&self) -> bool {
-        let data_bytes = self.data.len() as u32;
-        data_bytes > self.config.max_upload_part_size
+        self.file.size_bytes() > self.config.max_upload_part_size as u64
     }
 
     async fn send_song(self) -> Result<String, MusicUploaderClientError> {
+        // only called for songs no larger than a single part, so reading it whole is fine.
+        let file = self.file.clone();
+        let data = run_blocking(move || file.read_all()).await?;
         self.client
             .send_song(
                 &self.config,
-                self.data,
-                self.artist,
-                self.album,
-                &self.song.song_name,
+                data,
+                &self.album.artist,
+                &self.album.album_name,
+                self.file_name,
+                &self.progress,
             )
             .await
     }
 
     async fn send_song_in_parts(self) -> Result<String, MusicUploaderClientError> {
         self.logger.log("Starting multipart upload".to_string());
-        let hash = sha256::digest(&self.data);
-        let declared_size_bytes = self.data.len() as u32;
+        let hash = self.get_hash().await?;
+        let declared_size_bytes = self.file.size_bytes();
+        let mut part_size_bytes = self.config.max_upload_part_size;
         for attempt in 0..MAX_MULTIPART_UPLOAD_ATTEMPT {
-            match self.declare_upload(
-                &hash, 
-                self.config.max_upload_part_size,
-                declared_size_bytes,
-            ).await? {
-                DeclareUploadResponse::Complete => return Ok(match attempt {
-                    0 => "Song already present".to_string(),
-                    n => format!("Succeeded multipart upload on {n} attempt"),
-                }),
-                DeclareUploadResponse::Incomplete {
+            let response = match self
+                .declare_upload(&hash, part_size_bytes, declared_size_bytes)
+                .await
+            {
+                // fewer parts cannot help, the size itself does not fit
+                Err(e)
+                    if is_rejected_declaration(&e)
+                        && declared_size_bytes > LEGACY_MAX_SIZE_BYTES =>
+                {
+                    return Err(too_old_for_large_files(&e));
+                }
+                Err(e) if self.should_retry_with_legacy_parts(&e, part_size_bytes) => {
+                    part_size_bytes = self.legacy_part_size()?;
+                    self.logger.log(format!(
+                        "Server rejected the upload declaration ({e}), retrying with {part_size_bytes} byte parts"
+                    ));
+                    self.declare_upload(&hash, part_size_bytes, declared_size_bytes)
+                        .await?
+                }
+                result => result?,
+            };
+            match response {
+                DeclaredUpload::Complete => {
+                    return Ok(match attempt {
+                        0 => "Song already present".to_string(),
+                        n => format!("Succeeded multipart upload on {n} attempt"),
+                    })
+                }
+                DeclaredUpload::Incomplete {
                     key,
-                    declared_size: _,
+                    declared_size,
                     part_size,
-                    received_parts
-                } => self.upload_remaining_parts(key, part_size, received_parts).await?,
+                    received_parts,
+                } => {
+                    if declared_size != declared_size_bytes {
+                        return Err(MusicUploaderClientError::AlbumUploadFailure(format!(
+                            "The server is too old for files over 4 GB, it recorded {declared_size} bytes for a {declared_size_bytes} byte file"
+                        )));
+                    }
+                    self.journal.lock().unwrap().record_declared(
+                        &self.album.journal_id,
+                        &self.song.key(),
+                        self.file.stamp(),
+                        &key,
+                        part_size,
+                        &received_parts,
+                    );
+                    self.upload_remaining_parts(key, part_size, received_parts)
+                        .await?
+                }
             }
         }
         Err(MusicUploaderClientError::AlbumUploadFailure(format!(
@@ -99,52 +185,130 @@ impl<'a> UploadState<'a> {
     }
 
     async fn upload_remaining_parts(
-        &self, key: String, part_size: u32, received_parts: Vec<u8>
+        &self,
+        key: String,
+        part_size: u32,
+        received_parts: Vec<u32>,
     ) -> Result<(), MusicUploaderClientError> {
         let received_parts = received_parts.into_iter().collect::<HashSet<_>>();
         let num_parts = self.calculate_num_parts(part_size)?;
+        let mut remaining_parts = Vec::new();
         for index in 0..num_parts {
             if received_parts.contains(&index) {
                 self.logger.log(format!(
                     "Skipping part {index} because it has already been uploaded"
                 ));
+                self.progress
+                    .add_already_uploaded(self.part_len(index, part_size));
                 continue;
             }
-            let result = self.upload_part(&key, index, part_size as usize).await?;
-            self.logger.log(format!("Upload part result: {result}"));
+            remaining_parts.push(index);
+        }
+        let num_remaining = remaining_parts.len();
+        let key = &key;
+        // parts finish in any order. returning early drops the stream, which cancels
+        // whatever parts are still in flight.
+        let mut uploads = stream::iter(remaining_parts)
+            .map(|index| async move {
+                (
+                    index,
+                    self.upload_part(key, index, part_size as usize).await,
+                )
+            })
+            .buffer_unordered(self.config.part_concurrency);
+        let mut num_uploaded = 0;
+        while let Some((index, result)) = uploads.next().await {
+            let result = result.inspect_err(|e| {
+                self.logger
+                    .log(format!("Upload of part {index} failed: {e}"));
+            })?;
+            num_uploaded += 1;
+            self.journal.lock().unwrap().record_part(
+                &self.album.journal_id,
+                &self.song.key(),
+                self.file.stamp(),
+                index,
+            );
+            self.logger.log(format!(
+                "Upload part {index} result ({num_uploaded}/{num_remaining}): {result}"
+            ));
         }
         Ok(())
     }
 
-    fn calculate_num_parts(&self, part_size: u32) -> Result<u8, MusicUploaderClientError> {
-        let data_len = self.data.len() as u32;
-        let num_whole_parts = data_len / part_size;
-        let num_parts = if num_whole_parts * part_size < data_len {
-            num_whole_parts + 1
-        } else {
-            num_whole_parts
-        };
-        if num_parts > u8::MAX as u32 {
-            return Err(MusicUploaderClientError::AlbumUploadFailure(
-                "Cannot upload a file with this many parts!!".to_string(),
-            ));
+    // hashing a large file takes a while, so reuse the hash from an earlier attempt if we have one.
+    async fn get_hash(&self) -> Result<String, MusicUploaderClientError> {
+        let journal_id = &self.album.journal_id;
+        let stamp = self.file.stamp();
+        let known_hash = self
+            .journal
+            .lock()
+            .unwrap()
+            .get_hash(journal_id, &self.song.key(), stamp);
+        if let Some(hash) = known_hash {
+            return Ok(hash);
         }
-        Ok(num_parts as u8)
+        // most likely already hashed when the album was checked for duplicates
+        let (song_hashes, file) = (self.song_hashes.clone(), self.file.clone());
+        let hash = run_blocking(move || song_hashes.hash(&file)).await?;
+        self.journal
+            .lock()
+            .unwrap()
+            .record_hash(journal_id, &self.song.key(), stamp, &hash);
+        Ok(hash)
+    }
+
+    fn calculate_num_parts(&self, part_size: u32) -> Result<u32, MusicUploaderClientError> {
+        let num_parts = self.file.size_bytes().div_ceil(part_size as u64);
+        u32::try_from(num_parts).map_err(|_| {
+            MusicUploaderClientError::AlbumUploadFailure(
+                "Cannot upload a file with this many parts!!".to_string(),
+            )
+        })
+    }
+
+    fn part_len(&self, index: u32, part_size: u32) -> u64 {
+        let start = index as u64 * part_size as u64;
+        u64::min(
+            part_size as u64,
+            self.file.size_bytes().saturating_sub(start),
+        )
+    }
+
+    // a server that does not understand large uploads rejects the declaration outright,
+    // in which case we can still get through by using fewer, bigger parts.
+    fn should_retry_with_legacy_parts(
+        &self,
+        error: &MusicUploaderClientError,
+        part_size_bytes: u32,
+    ) -> bool {
+        let num_parts = self.file.size_bytes().div_ceil(part_size_bytes as u64);
+        is_rejected_declaration(error) && num_parts > LEGACY_MAX_PARTS
+    }
+
+    // only used for files under 4 GB, so the part size always fits
+    fn legacy_part_size(&self) -> Result<u32, MusicUploaderClientError> {
+        let part_size = self.file.size_bytes().div_ceil(LEGACY_MAX_PARTS);
+        u32::try_from(part_size).map_err(|_| {
+            MusicUploaderClientError::AlbumUploadFailure(
+                "File is too large for this server to accept".to_string(),
+            )
+        })
     }
 
     async fn declare_upload(
         &self,
         hash: &String,
         part_size_bytes: u32,
-        declared_size_bytes: u32,
-    ) -> Result<DeclareUploadResponse, MusicUploaderClientError> {
+        declared_size_bytes: u64,
+    ) -> Result<DeclaredUpload, MusicUploaderClientError> {
         self.client
             .declare_upload(
                 &self.config,
                 hash,
-                self.artist,
-                self.album,
-                &self.song.song_name,
+                &self.album.artist,
+                &self.album.album_name,
+                self.file_name,
                 part_size_bytes,
                 declared_size_bytes,
             )
@@ -154,19 +318,45 @@ impl<'a> UploadState<'a> {
     async fn upload_part(
         &self,
         key: &String,
-        index: u8,
+        index: u32,
         max_part_size: usize,
     ) -> Result<String, MusicUploaderClientError> {
-        let start = index as usize * max_part_size;
-        let end = usize::min(self.data.len(), (index as usize + 1) * max_part_size);
-        if end - start <= 0 {
+        let start = index as u64 * max_part_size as u64;
+        let file = self.file.clone();
+        let data = run_blocking(move || file.read_range(start, max_part_size)).await?;
+        if data.is_empty() {
             return Err(MusicUploaderClientError::AlbumUploadFailure(format!(
                 "Tried to upload a zero size part for index: {index}"
             )));
         }
-        let data = &self.data[start..end];
         self.client
-            .upload_part(&self.config, key, index, data.to_vec())
+            .upload_part(&self.config, key, index, data, &self.progress)
             .await
     }
 }
+
+/// runs file reads and hashing off the async runtime. songs and parts are all polled
+/// from the same task, so reading one song in place would hold up every other one.
+async fn run_blocking<T: Send + 'static>(
+    read: impl FnOnce() -> Result<T, MusicUploaderClientError> + Send + 'static,
+) -> Result<T, MusicUploaderClientError> {
+    tauri::async_runtime::spawn_blocking(read)
+        .await
+        .map_err(|e| MusicUploaderClientError::AlbumUploadFailure(e.to_string()))?
+}
+
+// the server understood the request but did not like what was declared
+fn is_rejected_declaration(error: &MusicUploaderClientError) -> bool {
+    match error {
+        MusicUploaderClientError::UnhappyResponse(status, _) => {
+            (400..500).contains(status) && *status != 401 && *status != 403
+        }
+        _ => false,
+    }
+}
+
+fn too_old_for_large_files(error: &MusicUploaderClientError) -> MusicUploaderClientError {
+    MusicUploaderClientError::AlbumUploadFailure(format!(
+        "The server is too old for files over 4 GB, it rejected the upload: {error}"
+    ))
+}
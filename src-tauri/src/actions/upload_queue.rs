@@ -0,0 +1,89 @@
+use crate::actions::upload_album::upload_album_inner;
+use crate::gui_logger::GuiLogger;
+use crate::upload_queue::{QueuedAlbum, UploadQueueSnapshot};
+use crate::{result_to_string, GuiState, RunState};
+use tauri::{AppHandle, Manager, State};
+
+#[tauri::command]
+pub fn enqueue_album(state: State<'_, GuiState>, album: QueuedAlbum) -> Result<String, String> {
+    let run_state = get_run_state(&state)?;
+    let album_id = album.album_id.clone();
+    if !run_state.upload_queue.lock().unwrap().enqueue(album) {
+        return Err(format!("Album {album_id} is already queued"));
+    }
+    run_state.upload_queue_notify.notify_one();
+    emit_queue_changed(&state, run_state);
+    Ok(format!("Queued album {album_id}"))
+}
+
+#[tauri::command]
+pub fn list_upload_queue(state: State<'_, GuiState>) -> Result<UploadQueueSnapshot, String> {
+    let run_state = get_run_state(&state)?;
+    let snapshot = run_state.upload_queue.lock().unwrap().snapshot();
+    Ok(snapshot)
+}
+
+#[tauri::command]
+pub fn reorder_upload_queue(
+    state: State<'_, GuiState>,
+    album_id: &str,
+    index: usize,
+) -> Result<String, String> {
+    let run_state = get_run_state(&state)?;
+    if !run_state
+        .upload_queue
+        .lock()
+        .unwrap()
+        .move_to(album_id, index)
+    {
+        return Err(format!("Album {album_id} is not waiting in the queue"));
+    }
+    emit_queue_changed(&state, run_state);
+    Ok(format!("Moved album {album_id} to position {index}"))
+}
+
+#[tauri::command]
+pub fn remove_from_upload_queue(
+    state: State<'_, GuiState>,
+    album_id: &str,
+) -> Result<String, String> {
+    let run_state = get_run_state(&state)?;
+    if !run_state.upload_queue.lock().unwrap().remove(album_id) {
+        return Err(format!("Album {album_id} is not waiting in the queue"));
+    }
+    emit_queue_changed(&state, run_state);
+    Ok(format!("Removed album {album_id} from the queue"))
+}
+
+/// uploads queued albums one after another for as long as the app runs.
+pub async fn drain_upload_queue(app: AppHandle) {
+    let state = app.state::<GuiState>();
+    let Some(run_state) = state.run_state.as_ref() else {
+        return;
+    };
+    let logger = GuiLogger::new(app.clone());
+    loop {
+        let next = run_state.upload_queue.lock().unwrap().start_next();
+        let Some(album) = next else {
+            run_state.upload_queue_notify.notified().await;
+            continue;
+        };
+        logger.queue_changed(&run_state.upload_queue.lock().unwrap().snapshot());
+        let result = upload_album_inner(app.clone(), state.clone(), album).await;
+        logger.log(result_to_string(result).unwrap_or_else(|e| e));
+        run_state.upload_queue.lock().unwrap().finish_current();
+        logger.queue_changed(&run_state.upload_queue.lock().unwrap().snapshot());
+    }
+}
+
+fn emit_queue_changed(state: &State<'_, GuiState>, run_state: &RunState) {
+    let snapshot = run_state.upload_queue.lock().unwrap().snapshot();
+    GuiLogger::new(state.app_handle.clone()).queue_changed(&snapshot);
+}
+
+fn get_run_state<'a>(state: &'a State<'_, GuiState>) -> Result<&'a RunState, String> {
+    state
+        .run_state
+        .as_ref()
+        .ok_or("Client did not succesfully boot".to_string())
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+
+use crate::audio_format::check_audio_file;
+use crate::metadata::{read_metadata, SongMetadata};
+use crate::song_file::{SongFile, SongHashes};
+use crate::upload_queue::QueuedAlbum;
+use crate::{GuiState, Song};
+use serde::Serialize;
+use tauri::State;
+
+// track numbers and totals past this are typos or junk, not reasons to list thousands of missing tracks
+const MAX_TRACK_NUMBER: u32 = 999;
+
+/// Something about an album that is probably a mistake. None of these stop an
+/// upload, the user gets to decide.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlbumWarning {
+    MismatchedAlbumTags {
+        values: Vec<TagValueGroup>,
+    },
+    MismatchedArtistTags {
+        values: Vec<TagValueGroup>,
+    },
+    DuplicateTrackNumber {
+        disc: Option<u32>,
+        track: u32,
+        paths: Vec<String>,
+    },
+    MissingTracks {
+        disc: Option<u32>,
+        missing: Vec<u32>,
+    },
+    MixedFormats {
+        values: Vec<TagValueGroup>,
+    },
+    InvalidAudioFile {
+        path: String,
+        error: String,
+    },
+    UnreadableTags {
+        path: String,
+        error: String,
+    },
+    // the same song under different names, the first path is the one worth keeping
+    DuplicateSongs {
+        paths: Vec<String>,
+    },
+    // the same song is already waiting in, or uploading with, another album
+    QueuedInAnotherAlbum {
+        path: String,
+        album_id: String,
+        album_name: String,
+        other_path: String,
+    },
+}
+
+/// one of the values seen for a tag and the songs that have it.
+#[derive(Serialize)]
+pub struct TagValueGroup {
+    value: String,
+    paths: Vec<String>,
+}
+
+#[tauri::command]
+pub async fn validate_album(
+    state: State<'_, GuiState>,
+    album_id: String,
+    songs: Vec<Song>,
+) -> Result<Vec<AlbumWarning>, String> {
+    let song_hashes = state
+        .run_state
+        .as_ref()
+        .map(|run_state| run_state.song_hashes.clone())
+        .unwrap_or_default();
+    // every album in the queue except this one, in case it is already in there
+    let queued_albums: Vec<QueuedAlbum> = state
+        .run_state
+        .as_ref()
+        .map(|run_state| run_state.upload_queue.lock().unwrap().snapshot())
+        .map(|snapshot| snapshot.uploading.into_iter().chain(snapshot.queued))
+        .into_iter()
+        .flatten()
+        .filter(|album| album.album_id != album_id)
+        .collect();
+    // reading and hashing every song would hold up the async runtime
+    tauri::async_runtime::spawn_blocking(move || check_album(&song_hashes, &songs, &queued_albums))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn check_album(
+    song_hashes: &SongHashes,
+    songs: &[Song],
+    queued_albums: &[QueuedAlbum],
+) -> Vec<AlbumWarning> {
+    let mut warnings = Vec::new();
+    let mut tagged_songs = Vec::new();
+    let mut formats = Vec::new();
+    let mut hashes = Vec::new();
+    for song in songs.iter() {
+        let key = song.key();
+        let file = match SongFile::open(song) {
+            Ok(file) => file,
+            Err(e) => {
+                warnings.push(AlbumWarning::InvalidAudioFile {
+                    path: key,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        // a song that cannot be read was already warned about when it was opened
+        if let Ok(hash) = song_hashes.hash(&file) {
+            hashes.push((key.clone(), hash));
+        }
+        match check_audio_file(&file) {
+            Ok(format) => formats.push((key.clone(), format.name().to_string())),
+            Err(e) => warnings.push(AlbumWarning::InvalidAudioFile {
+                path: key.clone(),
+                error: e.to_string(),
+            }),
+        }
+        match read_metadata(&file) {
+            Ok(metadata) => tagged_songs.push((key, metadata)),
+            Err(e) => warnings.push(AlbumWarning::UnreadableTags {
+                path: key,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    let albums = group_by(&tagged_songs, |metadata| metadata.album.clone());
+    if albums.len() > 1 {
+        warnings.push(AlbumWarning::MismatchedAlbumTags { values: albums });
+    }
+    // track artists are allowed to differ on a compilation as long as the album artist agrees
+    let has_album_artist = tagged_songs
+        .iter()
+        .any(|(_, metadata)| metadata.album_artist.is_some());
+    let artists = group_by(&tagged_songs, |metadata| match has_album_artist {
+        true => metadata.album_artist.clone(),
+        false => metadata.artist.clone(),
+    });
+    if artists.len() > 1 {
+        warnings.push(AlbumWarning::MismatchedArtistTags { values: artists });
+    }
+    warnings.extend(track_number_warnings(&tagged_songs));
+
+    let formats = group_paths(formats.into_iter());
+    if formats.len() > 1 {
+        warnings.push(AlbumWarning::MixedFormats { values: formats });
+    }
+
+    let duplicates = group_paths(hashes.iter().cloned())
+        .into_iter()
+        .filter(|group| group.paths.len() > 1)
+        .map(|group| AlbumWarning::DuplicateSongs { paths: group.paths });
+    warnings.extend(duplicates);
+    for album in queued_albums {
+        for song in album.songs.iter() {
+            let Some(hash) = SongFile::open(song)
+                .and_then(|file| song_hashes.hash(&file))
+                .ok()
+            else {
+                continue;
+            };
+            let same_songs = hashes.iter().filter(|(_, h)| *h == hash);
+            for (path, _) in same_songs {
+                warnings.push(AlbumWarning::QueuedInAnotherAlbum {
+                    path: path.clone(),
+                    album_id: album.album_id.clone(),
+                    album_name: album.album_name.clone(),
+                    other_path: song.key(),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+fn track_number_warnings(tagged_songs: &[(String, SongMetadata)]) -> Vec<AlbumWarning> {
+    let mut discs: BTreeMap<Option<u32>, BTreeMap<u32, Vec<String>>> = BTreeMap::new();
+    let mut track_totals: BTreeMap<Option<u32>, u32> = BTreeMap::new();
+    for (path, metadata) in tagged_songs {
+        let Some(track) = metadata.track_number else {
+            continue;
+        };
+        discs
+            .entry(metadata.disc_number)
+            .or_default()
+            .entry(track)
+            .or_default()
+            .push(path.clone());
+        if let Some(total) = metadata
+            .track_total
+            .filter(|total| *total <= MAX_TRACK_NUMBER)
+        {
+            let known_total = track_totals.entry(metadata.disc_number).or_insert(total);
+            *known_total = u32::max(*known_total, total);
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (disc, tracks) in discs {
+        for (track, paths) in tracks.iter() {
+            if paths.len() > 1 {
+                warnings.push(AlbumWarning::DuplicateTrackNumber {
+                    disc,
+                    track: *track,
+                    paths: paths.clone(),
+                });
+            }
+        }
+        // without a total we can only notice gaps before the highest track we have
+        let highest_track = tracks
+            .keys()
+            .filter(|track| **track <= MAX_TRACK_NUMBER)
+            .max()
+            .copied()
+            .unwrap_or(0);
+        let last_track = track_totals
+            .get(&disc)
+            .map(|total| u32::max(*total, highest_track))
+            .unwrap_or(highest_track);
+        let missing: Vec<u32> = (1..=last_track)
+            .filter(|track| !tracks.contains_key(track))
+            .collect();
+        if !missing.is_empty() {
+            warnings.push(AlbumWarning::MissingTracks { disc, missing });
+        }
+    }
+    warnings
+}
+
+// songs without the tag are left out, a missing tag is not a conflicting one
+fn group_by(
+    tagged_songs: &[(String, SongMetadata)],
+    value_of: impl Fn(&SongMetadata) -> Option<String>,
+) -> Vec<TagValueGroup> {
+    group_paths(
+        tagged_songs
+            .iter()
+            .filter_map(|(path, metadata)| value_of(metadata).map(|value| (path.clone(), value))),
+    )
+}
+
+fn group_paths(paths_and_values: impl Iterator<Item = (String, String)>) -> Vec<TagValueGroup> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (path, value) in paths_and_values {
+        groups.entry(value).or_default().push(path);
+    }
+    groups
+        .into_iter()
+        .map(|(value, paths)| TagValueGroup { value, paths })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(track_number: u32, track_total: Option<u32>) -> (String, SongMetadata) {
+        let metadata = SongMetadata {
+            track_number: Some(track_number),
+            track_total,
+            ..Default::default()
+        };
+        (format!("{track_number}.flac"), metadata)
+    }
+
+    fn missing_tracks(warnings: &[AlbumWarning]) -> Vec<u32> {
+        warnings
+            .iter()
+            .flat_map(|warning| match warning {
+                AlbumWarning::MissingTracks { missing, .. } => missing.clone(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gaps_up_to_the_total_are_missing() {
+        let warnings = track_number_warnings(&[song(1, Some(4)), song(3, None)]);
+        assert_eq!(missing_tracks(&warnings), vec![2, 4]);
+    }
+
+    #[test]
+    fn huge_track_numbers_do_not_count_as_gaps() {
+        let warnings =
+            track_number_warnings(&[song(1, Some(u32::MAX)), song(2, None), song(u32::MAX, None)]);
+        assert!(missing_tracks(&warnings).is_empty());
+    }
+}
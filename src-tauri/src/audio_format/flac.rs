@@ -0,0 +1,137 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{invalid, skip_id3v2, truncated, CheckError};
+
+pub const MAGIC: &[u8] = b"fLaC";
+const STREAMINFO_BLOCK: u8 = 0;
+const STREAMINFO_LEN: u64 = 34;
+const INVALID_BLOCK: u8 = 127;
+
+/// walks the metadata blocks, which all have to fit in the file, and makes
+/// sure audio frames follow them.
+pub fn check<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+    let mut position = skip_id3v2(reader, file_len)?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("the flac stream marker is missing".to_string()));
+    }
+    position += 4;
+
+    let mut is_first_block = true;
+    loop {
+        let mut header = [0u8; 4];
+        if position + 4 > file_len {
+            return Err(truncated("the flac metadata", position + 4, file_len));
+        }
+        reader.read_exact(&mut header)?;
+        let is_last_block = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let block_len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        if is_first_block && (block_type != STREAMINFO_BLOCK || block_len != STREAMINFO_LEN) {
+            return Err(invalid(
+                "the flac file does not start with stream info".to_string(),
+            ));
+        }
+        if block_type == INVALID_BLOCK {
+            return Err(invalid(
+                "the flac file has an invalid metadata block".to_string(),
+            ));
+        }
+        let block_end = position + 4 + block_len;
+        if block_end > file_len {
+            return Err(truncated("a flac metadata block", block_end, file_len));
+        }
+        position = block_end;
+        reader.seek(SeekFrom::Start(position))?;
+        is_first_block = false;
+        if is_last_block {
+            break;
+        }
+    }
+
+    let mut frame_start = [0u8; 2];
+    if position + 2 > file_len {
+        return Err(invalid("the flac file has no audio".to_string()));
+    }
+    reader.read_exact(&mut frame_start)?;
+    // every frame starts with 14 set bits followed by a zero
+    if frame_start[0] != 0xFF || frame_start[1] & 0xFE != 0xF8 {
+        return Err(invalid(
+            "no flac audio frame follows the metadata".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn block(block_type: u8, is_last: bool, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![block_type | if is_last { 0x80 } else { 0 }];
+        data.extend_from_slice(&(content.len() as u32).to_be_bytes()[1..]);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn file(blocks: &[Vec<u8>]) -> Vec<u8> {
+        [MAGIC.to_vec(), blocks.concat(), vec![0xFF, 0xF8, 0, 0]].concat()
+    }
+
+    fn check_bytes(data: &[u8]) -> Result<(), CheckError> {
+        check(&mut Cursor::new(data), data.len() as u64)
+    }
+
+    #[test]
+    fn whole_files_pass() {
+        let data = file(&[
+            block(STREAMINFO_BLOCK, false, &[0; 34]),
+            block(4, true, &[0; 10]),
+        ]);
+        assert!(check_bytes(&data).is_ok());
+    }
+
+    #[test]
+    fn an_id3_tag_in_front_is_skipped() {
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x02\x00\x00".to_vec();
+        data.extend(file(&[block(STREAMINFO_BLOCK, true, &[0; 34])]));
+        assert!(check_bytes(&data).is_ok());
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let data = file(&[
+            block(STREAMINFO_BLOCK, false, &[0; 34]),
+            block(4, true, &[0; 10]),
+        ]);
+        for len in [2, 6, 20, 45, data.len() - 4] {
+            assert!(check_bytes(&data[..len]).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn stream_info_has_to_come_first() {
+        let data = file(&[block(4, true, &[0; 34])]);
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+
+    #[test]
+    fn audio_frames_have_to_follow() {
+        let mut data = file(&[block(STREAMINFO_BLOCK, true, &[0; 34])]);
+        let len = data.len();
+        data[len - 4..].copy_from_slice(b"text");
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+
+    #[test]
+    fn invalid_blocks_are_invalid() {
+        let data = file(&[
+            block(STREAMINFO_BLOCK, false, &[0; 34]),
+            block(INVALID_BLOCK, true, &[]),
+        ]);
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+}
@@ -0,0 +1,357 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{invalid, truncated, CheckError};
+
+// some writers stream wav files and never go back to fill the sizes in
+const UNKNOWN_SIZE: u32 = u32::MAX;
+
+/// wav (RIFF) and aiff (FORM) are both made of the same kind of chunks, they
+/// only differ in byte order and in which chunks they need.
+struct ChunkLayout {
+    container: &'static str,
+    big_endian: bool,
+    // chunk id and what to call it when it is missing
+    required_chunks: &'static [(&'static [u8; 4], &'static str)],
+    // the chunk holding the audio, it can run to the end of the file with an unknown size
+    audio_chunk: &'static [u8; 4],
+    // containers for files over 4 GB, which keep their real sizes in a ds64 chunk
+    large_containers: &'static [&'static [u8; 4]],
+}
+
+const WAV: ChunkLayout = ChunkLayout {
+    container: "RIFF",
+    big_endian: false,
+    required_chunks: &[(b"fmt ", "fmt"), (b"data", "data")],
+    audio_chunk: b"data",
+    large_containers: &[b"RF64", b"BW64"],
+};
+
+const AIFF: ChunkLayout = ChunkLayout {
+    container: "FORM",
+    big_endian: true,
+    required_chunks: &[(b"COMM", "COMM"), (b"SSND", "sound data")],
+    audio_chunk: b"SSND",
+    large_containers: &[],
+};
+
+pub fn check_wav<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+    WAV.check(reader, file_len)
+}
+
+pub fn check_aiff<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+    AIFF.check(reader, file_len)
+}
+
+impl ChunkLayout {
+    fn check<R: Read + Seek>(&self, reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        let large_sizes = match self
+            .large_containers
+            .iter()
+            .any(|id| header[0..4] == id[..])
+        {
+            true => Some(LargeSizes::read(reader, file_len)?),
+            false => None,
+        };
+        // a plain RIFF file can't hold its real sizes past 4 GB, some writers let them wrap around
+        let sizes_wrap = large_sizes.is_none() && file_len > 8 + u32::MAX as u64;
+        let container_size = match &large_sizes {
+            Some(sizes) => Some(sizes.container),
+            None => Some(self.read_size(&header[4..8]))
+                .filter(|size| *size != UNKNOWN_SIZE && !sizes_wrap)
+                .map(u64::from),
+        };
+        let container_end = container_size.map(|size| size.saturating_add(8));
+        // allow for a missing pad byte at the very end, plenty of writers forget it
+        if let Some(container_end) = container_end.filter(|end| *end > file_len + 1) {
+            let what = format!("the {} header", self.container);
+            return Err(truncated(&what, container_end, file_len));
+        }
+
+        let mut found_chunks = Vec::new();
+        let mut position = 12;
+        let chunks_end = container_end.map_or(file_len, |end| u64::min(end, file_len));
+        while position + 8 <= chunks_end {
+            reader.seek(SeekFrom::Start(position))?;
+            let mut chunk_header = [0u8; 8];
+            reader.read_exact(&mut chunk_header)?;
+            let id = [
+                chunk_header[0],
+                chunk_header[1],
+                chunk_header[2],
+                chunk_header[3],
+            ];
+            let size = self.read_size(&chunk_header[4..8]);
+            found_chunks.push(id);
+            let size = match &large_sizes {
+                Some(sizes) if size == UNKNOWN_SIZE => sizes.chunk(&id),
+                None if size == UNKNOWN_SIZE && &id == self.audio_chunk => None,
+                _ => Some(size as u64),
+            };
+            let size = match size {
+                // the audio runs to the end of the file, nothing after it can be found
+                _ if &id == self.audio_chunk && sizes_wrap => break,
+                None if &id == self.audio_chunk => break,
+                None => {
+                    let what = String::from_utf8_lossy(&id).trim().to_string();
+                    return Err(invalid(format!("the ds64 chunk has no size for {what}")));
+                }
+                Some(size) => size,
+            };
+            let what = || format!("the {} chunk", String::from_utf8_lossy(&id).trim());
+            let Some(chunk_end) = (position + 8).checked_add(size) else {
+                return Err(invalid(format!("{} has an impossible size", what())));
+            };
+            if chunk_end > file_len {
+                return Err(truncated(&what(), chunk_end, file_len));
+            }
+            // chunks are padded to an even length
+            position = chunk_end + (size & 1);
+        }
+
+        for (id, name) in self.required_chunks {
+            if !found_chunks.contains(id) {
+                return Err(invalid(format!("the file has no {name} chunk")));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_size(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// the real sizes of an RF64 file, from the ds64 chunk that has to come first.
+struct LargeSizes {
+    container: u64,
+    data: u64,
+    // other chunks over 4 GB, which are rare
+    table: Vec<([u8; 4], u64)>,
+}
+
+impl LargeSizes {
+    fn read<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<Self, CheckError> {
+        // chunk header, then the container, data and sample counts and the table length
+        const FIXED_LEN: u64 = 8 + 8 + 8 + 4;
+        const TABLE_ENTRY_LEN: u64 = 4 + 8;
+        if 12 + 8 > file_len {
+            return Err(truncated("the ds64 chunk", 12 + 8, file_len));
+        }
+        let mut chunk_header = [0u8; 8];
+        reader.read_exact(&mut chunk_header)?;
+        let size = u32::from_le_bytes(chunk_header[4..8].try_into().unwrap()) as u64;
+        if &chunk_header[0..4] != b"ds64" || size < FIXED_LEN {
+            return Err(invalid("the RF64 file has no ds64 chunk".to_string()));
+        }
+        let chunk_end = 12 + 8 + size;
+        if chunk_end > file_len {
+            return Err(truncated("the ds64 chunk", chunk_end, file_len));
+        }
+        let mut fixed = [0u8; FIXED_LEN as usize];
+        reader.read_exact(&mut fixed)?;
+        let read_u64 = |at: usize| u64::from_le_bytes(fixed[at..at + 8].try_into().unwrap());
+        let table_len = u32::from_le_bytes(fixed[24..28].try_into().unwrap()) as u64;
+        let table_len = u64::min(table_len, (size - FIXED_LEN) / TABLE_ENTRY_LEN);
+        let mut table = Vec::new();
+        for _ in 0..table_len {
+            let mut entry = [0u8; TABLE_ENTRY_LEN as usize];
+            reader.read_exact(&mut entry)?;
+            let id = [entry[0], entry[1], entry[2], entry[3]];
+            table.push((id, u64::from_le_bytes(entry[4..12].try_into().unwrap())));
+        }
+        Ok(Self {
+            container: read_u64(0),
+            data: read_u64(8),
+            table,
+        })
+    }
+
+    fn chunk(&self, id: &[u8; 4]) -> Option<u64> {
+        match id {
+            b"data" => Some(self.data),
+            _ => self
+                .table
+                .iter()
+                .find(|(table_id, _)| table_id == id)
+                .map(|(_, size)| *size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chunk(id: &[u8; 4], size: u32, content: &[u8], big_endian: bool) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend_from_slice(&match big_endian {
+            true => size.to_be_bytes(),
+            false => size.to_le_bytes(),
+        });
+        data.extend_from_slice(content);
+        if content.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    fn wav_chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        chunk(id, content.len() as u32, content, false)
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        wav_chunk(b"RIFF", &[b"WAVE".to_vec(), chunks.concat()].concat())
+    }
+
+    fn ds64(container: u64, data: u64, table: &[(&[u8; 4], u64)]) -> Vec<u8> {
+        let mut content = [
+            container.to_le_bytes(),
+            data.to_le_bytes(),
+            0u64.to_le_bytes(),
+        ]
+        .concat();
+        content.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        for (id, size) in table {
+            content.extend_from_slice(*id);
+            content.extend_from_slice(&size.to_le_bytes());
+        }
+        wav_chunk(b"ds64", &content)
+    }
+
+    fn rf64(chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(
+            b"RF64",
+            u32::MAX,
+            &[b"WAVE".to_vec(), chunks.concat()].concat(),
+            false,
+        )
+    }
+
+    fn aiff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let content = [b"AIFF".to_vec(), chunks.concat()].concat();
+        chunk(b"FORM", content.len() as u32, &content, true)
+    }
+
+    fn check_wav_bytes(data: &[u8], file_len: u64) -> Result<(), CheckError> {
+        check_wav(&mut Cursor::new(data), file_len)
+    }
+
+    fn is_invalid(result: Result<(), CheckError>) -> bool {
+        matches!(result, Err(CheckError::Invalid(_)))
+    }
+
+    #[test]
+    fn whole_wav_files_pass() {
+        let data = wav(&[wav_chunk(b"fmt ", &[0; 16]), wav_chunk(b"data", &[0; 7])]);
+        assert!(check_wav_bytes(&data, data.len() as u64).is_ok());
+        // the pad byte after the odd sized data is missing
+        assert!(check_wav_bytes(&data, data.len() as u64 - 1).is_ok());
+    }
+
+    #[test]
+    fn truncated_wav_files_fail() {
+        let data = wav(&[wav_chunk(b"fmt ", &[0; 16]), wav_chunk(b"data", &[0; 32])]);
+        for len in [0, 10, 20, 40, data.len() - 2] {
+            let result = check_wav_bytes(&data[..len], len as u64);
+            assert!(result.is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn wav_files_need_fmt_and_data() {
+        let data = wav(&[wav_chunk(b"data", &[0; 8])]);
+        assert!(is_invalid(check_wav_bytes(&data, data.len() as u64)));
+        let data = wav(&[wav_chunk(b"fmt ", &[0; 16])]);
+        assert!(is_invalid(check_wav_bytes(&data, data.len() as u64)));
+    }
+
+    #[test]
+    fn streamed_wav_files_with_unknown_sizes_pass() {
+        let mut data = wav(&[
+            wav_chunk(b"fmt ", &[0; 16]),
+            chunk(b"data", u32::MAX, &[0; 8], false),
+        ]);
+        data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(check_wav_bytes(&data, data.len() as u64).is_ok());
+    }
+
+    #[test]
+    fn wrapped_sizes_in_wav_files_over_4gb_stop_at_the_data() {
+        let file_len = 4 * 1024 * 1024 * 1024u64 + 60;
+        // the data size wrapped around to 16, the audio after that is not a chunk
+        let mut data = wav(&[
+            wav_chunk(b"fmt ", &[0; 16]),
+            wav_chunk(b"data", &[0xAB; 64]),
+        ]);
+        data[40..44].copy_from_slice(&((file_len - 44) as u32).to_le_bytes());
+        data[4..8].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+        assert!(check_wav_bytes(&data, file_len).is_ok());
+    }
+
+    #[test]
+    fn rf64_files_take_their_sizes_from_ds64() {
+        let file_len = 5 * 1024 * 1024 * 1024u64;
+        // RF64 header, ds64, fmt and the data chunk's header come first
+        let data_size = file_len - 12 - 36 - 24 - 8;
+        let data = rf64(&[
+            ds64(file_len - 8, data_size, &[]),
+            wav_chunk(b"fmt ", &[0; 16]),
+            chunk(b"data", u32::MAX, &[0; 8], false),
+        ]);
+        assert!(check_wav_bytes(&data, file_len).is_ok());
+        let result = check_wav_bytes(&data, file_len - 1);
+        assert!(matches!(result, Err(CheckError::Invalid(reason)) if reason.contains("truncated")));
+    }
+
+    #[test]
+    fn rf64_chunk_sizes_can_come_from_the_table() {
+        let data = rf64(&[
+            ds64(0, 8, &[(b"junk", 4)]),
+            chunk(b"junk", u32::MAX, &[0; 4], false),
+            wav_chunk(b"fmt ", &[0; 16]),
+            chunk(b"data", u32::MAX, &[0; 8], false),
+        ]);
+        let mut data = data;
+        let container_size = data.len() as u64 - 8;
+        data[20..28].copy_from_slice(&container_size.to_le_bytes());
+        assert!(check_wav_bytes(&data, data.len() as u64).is_ok());
+    }
+
+    #[test]
+    fn rf64_files_need_ds64_first() {
+        let data = rf64(&[wav_chunk(b"fmt ", &[0; 16]), wav_chunk(b"data", &[0; 8])]);
+        assert!(is_invalid(check_wav_bytes(&data, data.len() as u64)));
+        let data = rf64(&[]);
+        assert!(check_wav_bytes(&data, data.len() as u64).is_err());
+    }
+
+    #[test]
+    fn huge_rf64_sizes_are_invalid() {
+        let data = rf64(&[
+            ds64(u64::MAX, u64::MAX, &[]),
+            wav_chunk(b"fmt ", &[0; 16]),
+            chunk(b"data", u32::MAX, &[0; 8], false),
+        ]);
+        assert!(is_invalid(check_wav_bytes(&data, u64::MAX - 1)));
+    }
+
+    #[test]
+    fn aiff_files_are_big_endian() {
+        let be_chunk = |id, content: &[u8]| chunk(id, content.len() as u32, content, true);
+        let data = aiff(&[be_chunk(b"COMM", &[0; 18]), be_chunk(b"SSND", &[0; 16])]);
+        let check = |data: &[u8], len| check_aiff(&mut Cursor::new(data), len);
+        assert!(check(&data, data.len() as u64).is_ok());
+        assert!(check(&data[..data.len() - 4], data.len() as u64 - 4).is_err());
+        let data = aiff(&[be_chunk(b"COMM", &[0; 18])]);
+        assert!(is_invalid(check(&data, data.len() as u64)));
+    }
+}
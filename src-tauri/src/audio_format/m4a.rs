@@ -0,0 +1,129 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{invalid, truncated, CheckError};
+
+/// walks the top level atoms, every one of them has to fit in the file.
+pub fn check<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+    let mut has_moov = false;
+    let mut has_mdat = false;
+    let mut position = 0;
+    while position + 8 <= file_len {
+        reader.seek(SeekFrom::Start(position))?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let kind_name = String::from_utf8_lossy(&kind).to_string();
+        let impossible_size = || invalid(format!("the {kind_name} atom has an impossible size"));
+        let end = match size {
+            0 => file_len,
+            1 => {
+                let mut large_size = [0u8; 8];
+                reader.read_exact(&mut large_size)?;
+                position
+                    .checked_add(u64::from_be_bytes(large_size))
+                    .ok_or_else(impossible_size)?
+            }
+            _ => position + size,
+        };
+        if end < position + 8 {
+            return Err(impossible_size());
+        }
+        if end > file_len {
+            return Err(truncated(&format!("the {kind_name} atom"), end, file_len));
+        }
+        match &kind {
+            b"moov" => has_moov = true,
+            b"mdat" => has_mdat = true,
+            _ => {}
+        }
+        position = end;
+    }
+    match (has_moov, has_mdat) {
+        (false, _) => Err(invalid("the m4a file has no moov atom".to_string())),
+        (_, false) => Err(invalid("the m4a file has no audio data".to_string())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn atom(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = (content.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn large_atom(kind: &[u8; 4], size: u64) -> Vec<u8> {
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(&size.to_be_bytes());
+        data
+    }
+
+    fn check_bytes(data: &[u8]) -> Result<(), CheckError> {
+        check(&mut Cursor::new(data), data.len() as u64)
+    }
+
+    fn file() -> Vec<u8> {
+        [
+            atom(b"ftyp", b"M4A "),
+            atom(b"moov", &atom(b"mvhd", &[0; 16])),
+            atom(b"mdat", &[0; 32]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn whole_files_pass() {
+        assert!(check_bytes(&file()).is_ok());
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let data = file();
+        for len in [10, 30, data.len() - 1] {
+            assert!(check_bytes(&data[..len]).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn files_need_moov_and_mdat() {
+        let data = [atom(b"ftyp", b"M4A "), atom(b"mdat", &[0; 32])].concat();
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+        let data = [atom(b"ftyp", b"M4A "), atom(b"moov", &[])].concat();
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+
+    #[test]
+    fn mdat_can_run_to_the_end_of_the_file() {
+        let mut data = [atom(b"ftyp", b"M4A "), atom(b"moov", &[])].concat();
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&[0; 32]);
+        assert!(check_bytes(&data).is_ok());
+    }
+
+    #[test]
+    fn large_sizes_are_followed() {
+        let mut data = [atom(b"ftyp", b"M4A "), atom(b"moov", &[])].concat();
+        data.extend(large_atom(b"mdat", 16 + 32));
+        data.extend_from_slice(&[0; 32]);
+        assert!(check_bytes(&data).is_ok());
+    }
+
+    #[test]
+    fn impossible_sizes_are_invalid() {
+        let mut data = atom(b"ftyp", b"M4A ");
+        data.extend(large_atom(b"mdat", u64::MAX));
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+        let mut data = file();
+        data[0..4].copy_from_slice(&4u32.to_be_bytes());
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+}
@@ -0,0 +1,275 @@
+mod flac;
+mod iff;
+mod m4a;
+mod mp3;
+mod ogg;
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use serde::Serialize;
+
+use crate::{song_file::SongFile, uploader_client::MusicUploaderClientError};
+
+// enough to see the first packet of an ogg stream
+const SNIFF_BYTES: u64 = 512;
+
+/// An audio format we can tell apart by looking at the file's content.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Mp3,
+    Wav,
+    M4a,
+    Flac,
+    Vorbis,
+    Opus,
+    Aiff,
+}
+
+impl AudioFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Wav => "wav",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Vorbis => "ogg vorbis",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Aiff => "aiff",
+        }
+    }
+
+    /// Guesses the format from the first bytes of the file, leaving the reader at
+    /// the start. This is only a guess, `check_audio_file` is what actually
+    /// checks the file.
+    pub fn sniff<R: Read + Seek>(reader: &mut R) -> io::Result<Option<AudioFormat>> {
+        let start = read_start(reader, 0)?;
+        let format = if start.starts_with(b"ID3") {
+            // flac files sometimes carry an id3 tag in front too
+            let after_tag = match id3v2_tag_len(&start) {
+                Some(tag_len) => read_start(reader, tag_len)?,
+                None => Vec::new(),
+            };
+            match after_tag.starts_with(flac::MAGIC) {
+                true => Some(AudioFormat::Flac),
+                false => Some(AudioFormat::Mp3),
+            }
+        } else if start.starts_with(flac::MAGIC) {
+            Some(AudioFormat::Flac)
+        } else if start.starts_with(ogg::MAGIC) {
+            ogg::sniff_codec(&start)
+        } else if mp3::MpegFrameHeader::parse(&start).is_some() {
+            Some(AudioFormat::Mp3)
+        } else if start.len() >= 12
+            && matches!(&start[0..4], b"RIFF" | b"RF64" | b"BW64")
+            && &start[8..12] == b"WAVE"
+        {
+            Some(AudioFormat::Wav)
+        } else if start.len() >= 12
+            && &start[0..4] == b"FORM"
+            && matches!(&start[8..12], b"AIFF" | b"AIFC")
+        {
+            Some(AudioFormat::Aiff)
+        } else if start.len() >= 8 && &start[4..8] == b"ftyp" {
+            Some(AudioFormat::M4a)
+        } else {
+            None
+        };
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(format)
+    }
+}
+
+/// Makes sure the file really is audio in a format we support and that it is
+/// all there, whatever its extension says.
+pub fn check_audio_file(file: &SongFile) -> Result<AudioFormat, MusicUploaderClientError> {
+    let read_error = |e| file.read_error(e);
+    let invalid = |reason: String| {
+        MusicUploaderClientError::InvalidAudioFile(file.name().to_string(), reason)
+    };
+    let file_len = file.size_bytes();
+    let mut reader = file.reader().map_err(read_error)?;
+    let Some(format) = AudioFormat::sniff(&mut reader).map_err(read_error)? else {
+        return Err(invalid(
+            "it is not in any of the audio formats we support".to_string(),
+        ));
+    };
+    let check = match format {
+        AudioFormat::Mp3 => mp3::check(&mut reader, file_len),
+        AudioFormat::Wav => iff::check_wav(&mut reader, file_len),
+        AudioFormat::M4a => m4a::check(&mut reader, file_len),
+        AudioFormat::Flac => flac::check(&mut reader, file_len),
+        AudioFormat::Vorbis | AudioFormat::Opus => ogg::check(&mut reader, file_len),
+        AudioFormat::Aiff => iff::check_aiff(&mut reader, file_len),
+    };
+    match check {
+        Ok(()) => Ok(format),
+        Err(CheckError::Io(e)) => Err(read_error(e)),
+        Err(CheckError::Invalid(reason)) => Err(invalid(reason)),
+    }
+}
+
+/// length of the id3v2 tag (header, footer and all) that `header` starts, if it is one.
+pub fn id3v2_tag_len(header: &[u8]) -> Option<u64> {
+    if header.len() < 10 || !header.starts_with(b"ID3") {
+        return None;
+    }
+    let tag_size = header[6..10]
+        .iter()
+        .fold(0u64, |size, byte| (size << 7) | (*byte & 0x7F) as u64);
+    let has_footer = header[5] & 0x10 != 0;
+    Some(10 + tag_size + if has_footer { 10 } else { 0 })
+}
+
+enum CheckError {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl From<io::Error> for CheckError {
+    fn from(e: io::Error) -> Self {
+        CheckError::Io(e)
+    }
+}
+
+fn invalid(reason: String) -> CheckError {
+    CheckError::Invalid(reason)
+}
+
+fn truncated(what: &str, declared_end: u64, file_len: u64) -> CheckError {
+    invalid(format!(
+        "{what} needs {declared_end} bytes but the file is only {file_len} bytes, it is probably truncated"
+    ))
+}
+
+fn read_start<R: Read + Seek>(reader: &mut R, position: u64) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(position))?;
+    let mut start = Vec::new();
+    reader.take(SNIFF_BYTES).read_to_end(&mut start)?;
+    Ok(start)
+}
+
+/// skips an id3v2 tag at the start of the file, returns where the audio starts.
+fn skip_id3v2<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<u64, CheckError> {
+    let header = read_start(reader, 0)?;
+    let audio_start = match id3v2_tag_len(&header) {
+        Some(tag_len) => tag_len,
+        None if header.starts_with(b"ID3") => return Err(truncated("the id3 tag", 10, file_len)),
+        None => 0,
+    };
+    if audio_start > file_len {
+        return Err(truncated("the id3 tag", audio_start, file_len));
+    }
+    reader.seek(SeekFrom::Start(audio_start))?;
+    Ok(audio_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+
+    fn sniff_bytes(data: &[u8]) -> Option<AudioFormat> {
+        let mut reader = Cursor::new(data);
+        let format = AudioFormat::sniff(&mut reader).unwrap();
+        assert_eq!(reader.position(), 0);
+        format
+    }
+
+    fn with_container(id: &[u8; 4], form: &[u8; 4]) -> Vec<u8> {
+        [&id[..], &[0; 4], &form[..], &[0; 16]].concat()
+    }
+
+    #[test]
+    fn formats_are_told_apart_by_their_content() {
+        assert_eq!(
+            sniff_bytes(&with_container(b"RIFF", b"WAVE")),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(
+            sniff_bytes(&with_container(b"RF64", b"WAVE")),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(
+            sniff_bytes(&with_container(b"BW64", b"WAVE")),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(sniff_bytes(&with_container(b"RIFF", b"AVI ")), None);
+        assert_eq!(
+            sniff_bytes(&with_container(b"FORM", b"AIFC")),
+            Some(AudioFormat::Aiff)
+        );
+        assert_eq!(
+            sniff_bytes(b"\0\0\0\x14ftypM4A \0\0\0\0"),
+            Some(AudioFormat::M4a)
+        );
+        assert_eq!(sniff_bytes(b"fLaC\0\0\0\x22"), Some(AudioFormat::Flac));
+        assert_eq!(
+            sniff_bytes(&[0xFF, 0xFB, 0x90, 0x00]),
+            Some(AudioFormat::Mp3)
+        );
+    }
+
+    #[test]
+    fn id3_tags_are_looked_past() {
+        let tag = b"ID3\x04\x00\x00\x00\x00\x00\x02\x00\x00".to_vec();
+        assert_eq!(
+            sniff_bytes(&[tag.clone(), b"fLaC".to_vec()].concat()),
+            Some(AudioFormat::Flac)
+        );
+        assert_eq!(
+            sniff_bytes(&[tag, vec![0xFF, 0xFB, 0x90, 0x00]].concat()),
+            Some(AudioFormat::Mp3)
+        );
+    }
+
+    #[test]
+    fn ogg_codecs_are_told_apart() {
+        let page = |packet: &[u8]| {
+            let mut data = b"OggS".to_vec();
+            data.resize(ogg::PAGE_HEADER_LEN - 1, 0);
+            data.push(1);
+            data.push(packet.len() as u8);
+            data.extend_from_slice(packet);
+            data
+        };
+        assert_eq!(sniff_bytes(&page(b"\x01vorbis")), Some(AudioFormat::Vorbis));
+        assert_eq!(sniff_bytes(&page(b"OpusHead")), Some(AudioFormat::Opus));
+        assert_eq!(sniff_bytes(&page(b"Speex   ")), None);
+    }
+
+    #[test]
+    fn other_files_are_not_audio() {
+        assert_eq!(sniff_bytes(b""), None);
+        assert_eq!(sniff_bytes(b"RIFF"), None);
+        assert_eq!(sniff_bytes(b"track listing\n1. intro\n"), None);
+    }
+
+    #[test]
+    fn id3_tag_lengths_include_the_footer() {
+        assert_eq!(
+            id3v2_tag_len(b"ID3\x04\x00\x00\x00\x00\x01\x01"),
+            Some(10 + 129)
+        );
+        assert_eq!(
+            id3v2_tag_len(b"ID3\x04\x00\x10\x00\x00\x00\x05"),
+            Some(10 + 5 + 10)
+        );
+        assert_eq!(id3v2_tag_len(b"ID3\x04"), None);
+    }
+
+    #[test]
+    fn renamed_text_files_are_refused() {
+        let path =
+            std::env::temp_dir().join(format!("music_uploader_renamed_{}.mp3", std::process::id()));
+        fs::write(&path, "these are the lyrics, not the song\n".repeat(20)).unwrap();
+        let file = SongFile::open_path(&path.to_string_lossy().to_string()).unwrap();
+        let result = check_audio_file(&file);
+        let _ = fs::remove_file(&path);
+        assert!(matches!(
+            result,
+            Err(MusicUploaderClientError::InvalidAudioFile(_, reason)) if reason.contains("not in any")
+        ));
+    }
+}
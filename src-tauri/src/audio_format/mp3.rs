@@ -0,0 +1,216 @@
+use std::io::{Read, Seek};
+
+use super::{invalid, skip_id3v2, truncated, CheckError};
+
+// how far past the id3 tag we look for the first mpeg frame, encoders like to pad
+const MAX_SYNC_SEARCH_BYTES: usize = 64 * 1024;
+// the largest possible mpeg audio frame is a little under 3k
+const MAX_FRAME_BYTES: usize = 4 * 1024;
+
+pub fn check<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+    let audio_start = skip_id3v2(reader, file_len)?;
+    let mut window = Vec::new();
+    reader
+        .take((MAX_SYNC_SEARCH_BYTES + MAX_FRAME_BYTES) as u64)
+        .read_to_end(&mut window)?;
+    let window_reaches_end = audio_start + window.len() as u64 == file_len;
+    let first_frame = (0..usize::min(window.len(), MAX_SYNC_SEARCH_BYTES)).find_map(|offset| {
+        let frame = MpegFrameHeader::parse(&window[offset..])?;
+        // a single valid looking header is easy to hit by chance, so the next frame has to line up too
+        let Some(frame_len) = frame.frame_len else {
+            return Some((offset, frame));
+        };
+        let next_offset = offset + frame_len;
+        let next_lines_up = match window.get(next_offset..) {
+            Some([]) => window_reaches_end,
+            Some(next) if next.len() < 4 => false,
+            Some(next) => MpegFrameHeader::parse(next).is_some(),
+            None => false,
+        };
+        next_lines_up.then_some((offset, frame))
+    });
+    let Some((offset, frame)) = first_frame else {
+        return Err(invalid("no mpeg audio frames were found".to_string()));
+    };
+
+    // vbr files start with a xing/info frame that says how long the audio is
+    let frame_start = audio_start + offset as u64;
+    let frame_bytes =
+        &window[offset..usize::min(window.len(), offset + frame.frame_len.unwrap_or(0))];
+    if let Some(declared_bytes) = xing_byte_count(frame_bytes) {
+        let declared_end = frame_start + declared_bytes;
+        if declared_end > file_len {
+            return Err(truncated("the audio", declared_end, file_len));
+        }
+    }
+    Ok(())
+}
+
+fn xing_byte_count(frame: &[u8]) -> Option<u64> {
+    const FRAMES_FLAG: u32 = 0x1;
+    const BYTES_FLAG: u32 = 0x2;
+    // the tag sits after the side info, whose length depends on version and channels
+    let tag_start = [4 + 32, 4 + 17, 4 + 9]
+        .into_iter()
+        .find(|start| matches!(frame.get(*start..start + 4), Some(b"Xing") | Some(b"Info")))?;
+    let read_u32 = |at: usize| {
+        frame
+            .get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let flags = read_u32(tag_start + 4)?;
+    if flags & BYTES_FLAG == 0 {
+        return None;
+    }
+    let bytes_at = match flags & FRAMES_FLAG {
+        0 => tag_start + 8,
+        _ => tag_start + 12,
+    };
+    read_u32(bytes_at)
+        .map(|bytes| bytes as u64)
+        .filter(|bytes| *bytes > 0)
+}
+
+pub struct MpegFrameHeader {
+    // None for free format bitrate, where the length cannot be worked out from the header
+    frame_len: Option<usize>,
+}
+
+impl MpegFrameHeader {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        const BITRATES_V1_L1: [u32; 15] = [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ];
+        const BITRATES_V1_L2: [u32; 15] = [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ];
+        const BITRATES_V1_L3: [u32; 15] = [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ];
+        const BITRATES_V2_L1: [u32; 15] = [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ];
+        const BITRATES_V2_L2_L3: [u32; 15] =
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+        let header = bytes.get(0..4)?;
+        if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        // 0 is mpeg 2.5, 2 is mpeg 2, 3 is mpeg 1
+        let version = (header[1] >> 3) & 0b11;
+        // 1 is layer 3, 2 is layer 2, 3 is layer 1
+        let layer = (header[1] >> 1) & 0b11;
+        let bitrate_index = (header[2] >> 4) as usize;
+        let sample_rate_index = ((header[2] >> 2) & 0b11) as usize;
+        let padding = ((header[2] >> 1) & 1) as u32;
+        if version == 1 || layer == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+        let sample_rate = match version {
+            3 => [44100, 48000, 32000][sample_rate_index],
+            2 => [22050, 24000, 16000][sample_rate_index],
+            _ => [11025, 12000, 8000][sample_rate_index],
+        };
+        let is_v1 = version == 3;
+        let bitrate_table = match (is_v1, layer) {
+            (true, 3) => &BITRATES_V1_L1,
+            (true, 2) => &BITRATES_V1_L2,
+            (true, _) => &BITRATES_V1_L3,
+            (false, 3) => &BITRATES_V2_L1,
+            (false, _) => &BITRATES_V2_L2_L3,
+        };
+        let bitrate = bitrate_table[bitrate_index] * 1000;
+        if bitrate == 0 {
+            return Some(Self { frame_len: None });
+        }
+        let frame_len = match layer {
+            3 => (12 * bitrate / sample_rate + padding) * 4,
+            1 if !is_v1 => 72 * bitrate / sample_rate + padding,
+            _ => 144 * bitrate / sample_rate + padding,
+        };
+        Some(Self {
+            frame_len: Some(frame_len as usize),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // mpeg 1 layer 3, 128 kbps, 44.1 kHz, stereo
+    const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const FRAME_LEN: usize = 417;
+
+    fn frame() -> Vec<u8> {
+        let mut data = FRAME_HEADER.to_vec();
+        data.resize(FRAME_LEN, 0);
+        data
+    }
+
+    fn xing_frame(declared_bytes: u32) -> Vec<u8> {
+        let mut data = frame();
+        let tag_start = 4 + 32;
+        data[tag_start..tag_start + 4].copy_from_slice(b"Xing");
+        data[tag_start + 4..tag_start + 8].copy_from_slice(&3u32.to_be_bytes());
+        data[tag_start + 12..tag_start + 16].copy_from_slice(&declared_bytes.to_be_bytes());
+        data
+    }
+
+    fn check_bytes(data: &[u8]) -> Result<(), CheckError> {
+        check(&mut Cursor::new(data), data.len() as u64)
+    }
+
+    #[test]
+    fn frame_lengths_follow_the_header() {
+        let header = MpegFrameHeader::parse(&FRAME_HEADER).unwrap();
+        assert_eq!(header.frame_len, Some(FRAME_LEN));
+        let padded = MpegFrameHeader::parse(&[0xFF, 0xFB, 0x92, 0x00]).unwrap();
+        assert_eq!(padded.frame_len, Some(FRAME_LEN + 1));
+        assert!(MpegFrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0x00]).is_none());
+        assert!(MpegFrameHeader::parse(&[0xFF, 0xFB]).is_none());
+    }
+
+    #[test]
+    fn whole_files_pass() {
+        assert!(check_bytes(&[frame(), frame(), frame()].concat()).is_ok());
+        assert!(check_bytes(&frame()).is_ok());
+    }
+
+    #[test]
+    fn an_id3_tag_in_front_is_skipped() {
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x04".to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend([frame(), frame()].concat());
+        assert!(check_bytes(&data).is_ok());
+        assert!(check_bytes(&data[..12]).is_err());
+    }
+
+    #[test]
+    fn text_is_not_mpeg_audio() {
+        let data = "just some notes, not a song\n".repeat(40);
+        assert!(matches!(
+            check_bytes(data.as_bytes()),
+            Err(CheckError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn a_lone_sync_word_is_not_enough() {
+        let mut data = frame();
+        data.extend_from_slice(&[0x55; 1000]);
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+
+    #[test]
+    fn vbr_files_shorter_than_declared_are_truncated() {
+        let data = [xing_frame(4 * FRAME_LEN as u32), frame(), frame()].concat();
+        let result = check_bytes(&data);
+        assert!(matches!(result, Err(CheckError::Invalid(reason)) if reason.contains("truncated")));
+        let data = [xing_frame(3 * FRAME_LEN as u32), frame(), frame()].concat();
+        assert!(check_bytes(&data).is_ok());
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{invalid, truncated, AudioFormat, CheckError};
+
+pub const MAGIC: &[u8] = b"OggS";
+pub const PAGE_HEADER_LEN: usize = 27;
+const END_OF_STREAM_FLAG: u8 = 0x04;
+
+/// tells vorbis and opus apart by the first packet of the first page.
+pub fn sniff_codec(start: &[u8]) -> Option<AudioFormat> {
+    let segment_count = *start.get(PAGE_HEADER_LEN - 1)? as usize;
+    let first_packet = start.get(PAGE_HEADER_LEN + segment_count..)?;
+    if first_packet.starts_with(b"\x01vorbis") {
+        Some(AudioFormat::Vorbis)
+    } else if first_packet.starts_with(b"OpusHead") {
+        Some(AudioFormat::Opus)
+    } else {
+        None
+    }
+}
+
+/// Ogg files are a run of pages with nothing in between. Every page has to fit
+/// in the file, and the last one has to close the stream, which is what a cut off
+/// file gets wrong.
+pub fn check<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(), CheckError> {
+    let mut position = 0;
+    let mut last_header_type = 0;
+    while position < file_len {
+        if position + PAGE_HEADER_LEN as u64 > file_len {
+            return Err(truncated(
+                "an ogg page",
+                position + PAGE_HEADER_LEN as u64,
+                file_len,
+            ));
+        }
+        reader.seek(SeekFrom::Start(position))?;
+        let mut header = [0u8; PAGE_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC || header[4] != 0 {
+            return Err(invalid(format!("expected an ogg page at byte {position}")));
+        }
+        let segment_count = header[PAGE_HEADER_LEN - 1] as usize;
+        let mut segment_table = vec![0u8; segment_count];
+        reader.read_exact(&mut segment_table)?;
+        let body_len: u64 = segment_table.iter().map(|len| *len as u64).sum();
+        let page_end = position + (PAGE_HEADER_LEN + segment_count) as u64 + body_len;
+        if page_end > file_len {
+            return Err(truncated("an ogg page", page_end, file_len));
+        }
+        last_header_type = header[5];
+        position = page_end;
+    }
+    if last_header_type & END_OF_STREAM_FLAG == 0 {
+        return Err(invalid(
+            "the ogg stream never ends, it is probably truncated".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn page(header_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.push(0);
+        data.push(header_type);
+        // granule position, serial number, page sequence and crc
+        data.extend_from_slice(&[0; 20]);
+        data.push(1);
+        data.push(body.len() as u8);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn file() -> Vec<u8> {
+        [
+            page(0x02, b"\x01vorbis-header"),
+            page(0, &[0; 40]),
+            page(END_OF_STREAM_FLAG, &[0; 20]),
+        ]
+        .concat()
+    }
+
+    fn check_bytes(data: &[u8]) -> Result<(), CheckError> {
+        check(&mut Cursor::new(data), data.len() as u64)
+    }
+
+    #[test]
+    fn whole_files_pass() {
+        assert!(check_bytes(&file()).is_ok());
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let data = file();
+        for len in [10, 30, 60, data.len() - 1] {
+            assert!(check_bytes(&data[..len]).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn files_cut_between_pages_never_end() {
+        let data = file();
+        let first_two_pages = data.len() - (PAGE_HEADER_LEN + 1 + 20);
+        let result = check_bytes(&data[..first_two_pages]);
+        assert!(matches!(result, Err(CheckError::Invalid(_))));
+    }
+
+    #[test]
+    fn anything_between_pages_is_invalid() {
+        let mut data = page(0x02, b"\x01vorbis-header");
+        data.extend_from_slice(b"junk");
+        data.extend(page(END_OF_STREAM_FLAG, &[]));
+        assert!(matches!(check_bytes(&data), Err(CheckError::Invalid(_))));
+    }
+
+    #[test]
+    fn the_codec_is_told_apart_by_the_first_packet() {
+        let sniff = |body: &[u8]| sniff_codec(&page(0x02, body));
+        assert_eq!(sniff(b"\x01vorbis"), Some(AudioFormat::Vorbis));
+        assert_eq!(sniff(b"OpusHead"), Some(AudioFormat::Opus));
+        assert_eq!(sniff(b"\x80theora"), None);
+        assert_eq!(sniff_codec(MAGIC), None);
+    }
+}
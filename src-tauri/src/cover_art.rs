@@ -0,0 +1,419 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    metadata::read_embedded_picture, song_file::SongFile,
+    uploader_client::MusicUploaderClientError, zip_entry::ZipEntry, Song,
+};
+
+// plex is happy with far smaller, this only keeps out things that are not really covers
+pub const MAX_COVER_ART_BYTES: u64 = 10 * 1024 * 1024;
+const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+// what rippers and stores tend to call the cover, these are offered before other images
+const COVER_ART_NAMES: [&str; 6] = ["cover", "folder", "front", "album", "albumart", "artwork"];
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_START: &[u8] = &[0xFF, 0xD8, 0xFF];
+
+/// Where the cover art for an album comes from, picked by the user from what
+/// `find_cover_art` offers.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CoverArtSource {
+    // an image next to the songs, or in the same zip as them
+    File { path: String, entry: Option<String> },
+    // the picture in a song's tags
+    Embedded { song: Song },
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+/// A cover that checked out, ready to be uploaded.
+pub struct CoverArt {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// A possible cover for the user to choose from.
+#[derive(Serialize)]
+pub struct CoverArtCandidate {
+    source: CoverArtSource,
+    // what to show the user, the file name or the song it is embedded in
+    name: String,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    size_bytes: u64,
+}
+
+impl CoverArtSource {
+    fn name(&self) -> String {
+        match self {
+            CoverArtSource::File { path, entry } => Song::key_for(path, entry.as_ref()),
+            CoverArtSource::Embedded { song } => song.key(),
+        }
+    }
+}
+
+/// Reads the image and makes sure it is a jpeg or png of a sensible size.
+pub fn load_cover_art(source: &CoverArtSource) -> Result<CoverArt, MusicUploaderClientError> {
+    let invalid = |reason: String| MusicUploaderClientError::InvalidCoverArt(source.name(), reason);
+    let data = match source {
+        CoverArtSource::File { path, entry } => {
+            let file = match entry {
+                Some(entry) => SongFile::open_entry(path, entry)?,
+                None => SongFile::open_path(path)?,
+            };
+            if file.size_bytes() > MAX_COVER_ART_BYTES {
+                return Err(invalid(too_big(file.size_bytes())));
+            }
+            file.read_all()?
+        }
+        CoverArtSource::Embedded { song } => {
+            let file = SongFile::open(song)?;
+            let picture = read_embedded_picture(&file)
+                .map_err(|e| invalid(e.to_string()))?
+                .ok_or(invalid("the song has no picture in its tags".to_string()))?;
+            picture.data
+        }
+    };
+    if data.len() as u64 > MAX_COVER_ART_BYTES {
+        return Err(invalid(too_big(data.len() as u64)));
+    }
+    let (format, width, height) = inspect_image(&data).map_err(invalid)?;
+    Ok(CoverArt {
+        format,
+        width,
+        height,
+        data,
+    })
+}
+
+fn too_big(size_bytes: u64) -> String {
+    format!("it is {size_bytes} bytes, cover art can be at most {MAX_COVER_ART_BYTES} bytes")
+}
+
+/// Looks for cover art next to the songs, in the zips they came from and in
+/// their tags. Images that turn out to be the same picture are only offered
+/// once, and likely covers come first.
+pub fn find_cover_art(songs: &[Song]) -> Vec<CoverArtCandidate> {
+    let mut image_files = BTreeSet::new();
+    for song in songs {
+        image_files.extend(images_next_to(song));
+    }
+    let mut image_files: Vec<_> = image_files.into_iter().collect();
+    image_files.sort_by_key(|(path, entry)| {
+        let name = entry.as_ref().unwrap_or(path);
+        !is_cover_art_name(Path::new(name))
+    });
+    let sources = image_files
+        .into_iter()
+        .map(|(path, entry)| CoverArtSource::File { path, entry })
+        .chain(
+            songs
+                .iter()
+                .map(|song| CoverArtSource::Embedded { song: song.clone() }),
+        );
+
+    let mut candidates = Vec::new();
+    let mut seen = HashSet::new();
+    for source in sources {
+        // songs without art and images that are not covers are simply not offered
+        let Ok(cover_art) = load_cover_art(&source) else {
+            continue;
+        };
+        if !seen.insert(Sha256::digest(&cover_art.data)) {
+            continue;
+        }
+        let name = match &source {
+            CoverArtSource::File { path, entry } => {
+                file_name(Path::new(entry.as_ref().unwrap_or(path)))
+            }
+            CoverArtSource::Embedded { song } => format!("embedded in {}", song.song_name),
+        };
+        candidates.push(CoverArtCandidate {
+            name,
+            format: cover_art.format,
+            width: cover_art.width,
+            height: cover_art.height,
+            size_bytes: cover_art.data.len() as u64,
+            source,
+        });
+    }
+    candidates
+}
+
+/// images in the song's folder, or in its zip when it came out of one.
+fn images_next_to(song: &Song) -> Vec<(String, Option<String>)> {
+    if song.entry.is_some() {
+        let entries = ZipEntry::list(&song.path).unwrap_or_default();
+        return entries
+            .into_iter()
+            .filter(|entry| !entry.starts_with("__MACOSX/") && is_image(Path::new(entry)))
+            .map(|entry| (song.path.clone(), Some(entry)))
+            .collect();
+    }
+    let Some(folder) = Path::new(&song.path).parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_image(path))
+        .map(|path| (path.to_string_lossy().to_string(), None))
+        .collect()
+}
+
+fn is_image(path: &Path) -> bool {
+    let Some(extension) = path.extension() else {
+        return false;
+    };
+    let extension = extension.to_string_lossy().to_lowercase();
+    IMAGE_EXTENSIONS.contains(&extension.as_str())
+}
+
+// windows media player names them things like AlbumArt_{guid}_Large.jpg
+fn is_cover_art_name(path: &Path) -> bool {
+    let Some(stem) = path.file_stem() else {
+        return false;
+    };
+    let stem = stem.to_string_lossy().to_lowercase();
+    COVER_ART_NAMES
+        .iter()
+        .any(|name| stem == *name || stem.starts_with(&format!("{name}_")))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// works out the format and dimensions from the image's header, whatever the
+/// file extension or tag claimed it was.
+fn inspect_image(data: &[u8]) -> Result<(ImageFormat, u32, u32), String> {
+    let (format, dimensions) = if data.starts_with(PNG_SIGNATURE) {
+        (ImageFormat::Png, png_dimensions(data))
+    } else if data.starts_with(JPEG_START) {
+        (ImageFormat::Jpeg, jpeg_dimensions(data))
+    } else {
+        return Err("it is not a jpeg or png image".to_string());
+    };
+    match dimensions {
+        Some((width, height)) if width > 0 && height > 0 => Ok((format, width, height)),
+        _ => Err(format!(
+            "it looks like a {} but its header is broken",
+            format.extension()
+        )),
+    }
+}
+
+// the first chunk of a png is always IHDR, which starts with the width and height
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let ihdr = data.get(PNG_SIGNATURE.len()..PNG_SIGNATURE.len() + 16)?;
+    if &ihdr[4..8] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes([ihdr[8], ihdr[9], ihdr[10], ihdr[11]]);
+    let height = u32::from_be_bytes([ihdr[12], ihdr[13], ihdr[14], ihdr[15]]);
+    Some((width, height))
+}
+
+/// walks the jpeg segments up to the start of frame, which holds the dimensions.
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut position = 2;
+    loop {
+        if *data.get(position)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        // markers can be padded with any number of 0xFF bytes
+        if marker == 0xFF {
+            position += 1;
+            continue;
+        }
+        // restart markers and the like have no length
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            position += 2;
+            continue;
+        }
+        let length = u16::from_be_bytes([*data.get(position + 2)?, *data.get(position + 3)?]);
+        // every start of frame marker except the ones that are something else entirely
+        let is_start_of_frame =
+            (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_start_of_frame {
+            let frame = data.get(position + 4..position + 9)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Some((width, height));
+        }
+        position += 2 + length as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0]);
+        data
+    }
+
+    fn segment(marker: u8, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, marker];
+        data.extend_from_slice(&(content.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn start_of_frame(marker: u8, width: u16, height: u16) -> Vec<u8> {
+        let mut content = vec![8];
+        content.extend_from_slice(&height.to_be_bytes());
+        content.extend_from_slice(&width.to_be_bytes());
+        content.extend_from_slice(&[3, 1, 0x22, 0]);
+        segment(marker, &content)
+    }
+
+    fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        [vec![0xFF, 0xD8], segments.concat()].concat()
+    }
+
+    fn temp_image(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "music_uploader_cover_{}_{name}",
+            std::process::id()
+        ));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn png_dimensions_come_from_ihdr() {
+        assert_eq!(
+            inspect_image(&png(600, 400)),
+            Ok((ImageFormat::Png, 600, 400))
+        );
+    }
+
+    #[test]
+    fn broken_png_headers_are_refused() {
+        let data = png(600, 400);
+        assert!(inspect_image(&data[..20]).is_err());
+        let mut data = png(600, 400);
+        data[12..16].copy_from_slice(b"IDAT");
+        assert!(inspect_image(&data).is_err());
+        assert!(inspect_image(&png(0, 400)).is_err());
+    }
+
+    #[test]
+    fn jpeg_dimensions_come_from_the_start_of_frame() {
+        let data = jpeg(&[
+            segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"),
+            // huffman tables share the start of frame marker range
+            segment(0xC4, &[0; 20]),
+            start_of_frame(0xC2, 1200, 1000),
+        ]);
+        assert_eq!(inspect_image(&data), Ok((ImageFormat::Jpeg, 1200, 1000)));
+    }
+
+    #[test]
+    fn jpeg_fill_bytes_are_skipped() {
+        let mut data = jpeg(&[segment(0xE1, b"Exif\0\0")]);
+        data.extend_from_slice(&[0xFF, 0xFF]);
+        data.extend(start_of_frame(0xC0, 500, 500));
+        assert_eq!(inspect_image(&data), Ok((ImageFormat::Jpeg, 500, 500)));
+    }
+
+    #[test]
+    fn broken_jpegs_are_refused() {
+        let data = jpeg(&[segment(0xE0, b"JFIF\0"), start_of_frame(0xC0, 500, 500)]);
+        assert!(inspect_image(&data[..data.len() - 6]).is_err());
+        let mut data = jpeg(&[segment(0xE0, b"JFIF\0")]);
+        data.extend_from_slice(&[0x12, 0x34]);
+        assert!(inspect_image(&data).is_err());
+        assert!(inspect_image(&jpeg(&[start_of_frame(0xC0, 0, 500)])).is_err());
+    }
+
+    #[test]
+    fn other_images_are_refused() {
+        assert!(inspect_image(b"GIF89a\x01\0\x01\0").is_err());
+        assert!(inspect_image(b"").is_err());
+    }
+
+    #[test]
+    fn images_over_the_size_limit_are_refused() {
+        let mut data = png(600, 400);
+        data.resize(MAX_COVER_ART_BYTES as usize + 1, 0);
+        let path = temp_image("too_big.png", &data);
+        let source = CoverArtSource::File {
+            path: path.to_string_lossy().to_string(),
+            entry: None,
+        };
+        let result = load_cover_art(&source);
+        let _ = fs::remove_file(&path);
+        assert!(matches!(
+            result,
+            Err(MusicUploaderClientError::InvalidCoverArt(_, reason)) if reason.contains("at most")
+        ));
+    }
+
+    #[test]
+    fn images_are_loaded_whatever_their_extension_says() {
+        let path = temp_image("cover.jpg", &png(300, 300));
+        let source = CoverArtSource::File {
+            path: path.to_string_lossy().to_string(),
+            entry: None,
+        };
+        let result = load_cover_art(&source);
+        let _ = fs::remove_file(&path);
+        let cover_art = result.unwrap();
+        assert_eq!(cover_art.format, ImageFormat::Png);
+        assert_eq!((cover_art.width, cover_art.height), (300, 300));
+    }
+
+    #[test]
+    fn cover_art_names_are_recognised() {
+        assert!(is_cover_art_name(Path::new("Cover.JPG")));
+        assert!(is_cover_art_name(Path::new("AlbumArt_{1234}_Large.jpg")));
+        assert!(!is_cover_art_name(Path::new("back.jpg")));
+        assert!(!is_cover_art_name(Path::new("covers of the band.png")));
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    metadata::read_metadata,
+    song_file::{FileStamp, SongFile},
+    Song,
+};
+
+// most filesystems stop at 255 bytes, this leaves the server room for its own suffixes
+const MAX_NAME_BYTES: usize = 200;
+// not allowed in names on windows, and slashes would make folders anywhere
+const FORBIDDEN_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const REPLACEMENT_CHAR: char = '_';
+// windows refuses these as names no matter the extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The names an album ends up with on the server.
+#[derive(Serialize)]
+pub struct UploadNames {
+    pub album: String,
+    pub artist: String,
+    pub songs: Vec<SongUploadName>,
+}
+
+#[derive(Serialize)]
+pub struct SongUploadName {
+    // Song::key of the song, so the gui can match it to its file
+    pub key: String,
+    pub name: String,
+}
+
+/// Names worked out from the songs' tags, kept until a song changes. The
+/// preview is asked for on every keystroke in the name fields.
+#[derive(Default)]
+pub struct TagNames {
+    // song key -> stamp when the tags were read and the name without its extension
+    names: Mutex<HashMap<String, (FileStamp, Option<String>)>>,
+}
+
+impl TagNames {
+    fn get(&self, song: &Song) -> Option<String> {
+        let file = SongFile::open(song).ok()?;
+        let key = song.key();
+        if let Some((stamp, name)) = self.names.lock().unwrap().get(&key) {
+            if *stamp == file.stamp() {
+                return name.clone();
+            }
+        }
+        let name = name_from_tags(&file);
+        self.names
+            .lock()
+            .unwrap()
+            .insert(key, (file.stamp(), name.clone()));
+        name
+    }
+}
+
+/// Works out every name that is sent for an album. Used for both the upload
+/// and its preview so that the preview never lies. Songs are named from their
+/// tags when `tag_names` is given.
+pub fn upload_names(
+    album: &str,
+    artist: &str,
+    songs: &[Song],
+    tag_names: Option<&TagNames>,
+) -> UploadNames {
+    let mut taken = HashSet::new();
+    let songs = songs
+        .iter()
+        .map(|song| {
+            let name = song_file_name(song, tag_names);
+            SongUploadName {
+                key: song.key(),
+                name: deduplicate(name, &mut taken),
+            }
+        })
+        .collect();
+    UploadNames {
+        album: normalize_name(album),
+        artist: normalize_name(artist),
+        songs,
+    }
+}
+
+/// Makes a name safe to use as a folder or file name on the server: NFC so
+/// that the same name typed on different systems stays the same, no characters
+/// windows refuses, no trailing dots or spaces and not too long.
+pub fn normalize_name(name: &str) -> String {
+    clean(name, MAX_NAME_BYTES)
+}
+
+/// Like `normalize_name` but keeps the extension intact when the name has to be shortened.
+pub fn normalize_file_name(name: &str) -> String {
+    let (stem, Some(extension)) = split_extension(name) else {
+        return normalize_name(name);
+    };
+    let extension = clean(extension, MAX_NAME_BYTES);
+    let stem = clean(stem, MAX_NAME_BYTES.saturating_sub(extension.len() + 1));
+    format!("{stem}.{extension}")
+}
+
+// not Path::extension, a slash in a song name is part of the name and not a folder
+fn split_extension(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    }
+}
+
+fn song_file_name(song: &Song, tag_names: Option<&TagNames>) -> String {
+    let extension = split_extension(&song.song_name).1;
+    let name = match (tag_names, extension) {
+        (Some(tag_names), Some(extension)) => tag_names
+            .get(song)
+            .map(|name| format!("{name}.{extension}"))
+            .unwrap_or(song.song_name.clone()),
+        _ => song.song_name.clone(),
+    };
+    normalize_file_name(&name)
+}
+
+/// "NN - Title", with the disc in front for albums with more than one.
+/// None when the song has no title to name it by.
+fn name_from_tags(file: &SongFile) -> Option<String> {
+    let metadata = read_metadata(file).ok()?;
+    let title = metadata.title.filter(|title| !title.trim().is_empty())?;
+    let track = match (
+        metadata.track_number,
+        metadata.disc_number,
+        metadata.disc_total,
+    ) {
+        (Some(track), Some(disc), Some(discs)) if discs > 1 => format!("{disc}-{track:02} - "),
+        (Some(track), _, _) => format!("{track:02} - "),
+        (None, _, _) => String::new(),
+    };
+    Some(format!("{track}{}", title.trim()))
+}
+
+fn clean(name: &str, max_bytes: usize) -> String {
+    let mut cleaned: String = name
+        .nfc()
+        .map(|c| match FORBIDDEN_CHARS.contains(&c) || c.is_control() {
+            true => REPLACEMENT_CHAR,
+            false => c,
+        })
+        .collect();
+    let stem = cleaned.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.contains(&stem.trim().to_uppercase().as_str()) {
+        cleaned.insert(stem.len(), REPLACEMENT_CHAR);
+    }
+    // trimming again after truncating since that can leave a trailing space behind
+    let cleaned = trim(&cleaned);
+    let cleaned = trim(truncate(cleaned, max_bytes));
+    match cleaned.is_empty() {
+        true => REPLACEMENT_CHAR.to_string(),
+        false => cleaned.to_string(),
+    }
+}
+
+// windows drops trailing dots and spaces, so "Vol." and "Vol" would be the same folder
+fn trim(name: &str) -> &str {
+    name.trim_start()
+        .trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+}
+
+fn truncate(name: &str, max_bytes: usize) -> &str {
+    if name.len() <= max_bytes {
+        return name;
+    }
+    let mut end = max_bytes;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+/// two songs can end up with the same name once cleaned, the later ones get a number.
+fn deduplicate(name: String, taken: &mut HashSet<String>) -> String {
+    // the server's filesystem may well ignore case
+    if taken.insert(name.to_lowercase()) {
+        return name;
+    }
+    let (stem, extension) = split_extension(&name);
+    let extension = extension
+        .map(|extension| format!(".{extension}"))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| {
+            let suffix = format!(" ({n}){extension}");
+            let stem = trim(truncate(stem, MAX_NAME_BYTES.saturating_sub(suffix.len())));
+            format!("{stem}{suffix}")
+        })
+        .find(|candidate| taken.insert(candidate.to_lowercase()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(song_name: &str) -> Song {
+        Song {
+            song_name: song_name.to_string(),
+            path: format!("/music/{song_name}"),
+            entry: None,
+        }
+    }
+
+    fn song_names(names: &[&str]) -> Vec<String> {
+        let songs: Vec<Song> = names.iter().map(|name| song(name)).collect();
+        upload_names("Album", "Artist", &songs, None)
+            .songs
+            .into_iter()
+            .map(|song| song.name)
+            .collect()
+    }
+
+    #[test]
+    fn names_are_nfc() {
+        assert_eq!(normalize_name("Beyonce\u{301}"), "Beyonc\u{e9}");
+        assert_eq!(normalize_name("Beyonc\u{e9}"), "Beyonc\u{e9}");
+    }
+
+    #[test]
+    fn forbidden_and_control_characters_are_replaced() {
+        assert_eq!(normalize_name("AC/DC: Live?"), "AC_DC_ Live_");
+        assert_eq!(normalize_name(r#"<"a\b|c*">"#), "__a_b_c___");
+        assert_eq!(normalize_name("line\nbreak\ttab"), "line_break_tab");
+    }
+
+    #[test]
+    fn reserved_names_get_a_suffix() {
+        assert_eq!(normalize_name("CON"), "CON_");
+        assert_eq!(normalize_name("lpt1"), "lpt1_");
+        assert_eq!(normalize_file_name("nul.flac"), "nul_.flac");
+        assert_eq!(normalize_name("Console"), "Console");
+        assert_eq!(normalize_name("COM10"), "COM10");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_trimmed() {
+        assert_eq!(normalize_name("  Vol. 2... "), "Vol. 2");
+        assert_eq!(normalize_name("..."), "_");
+        assert_eq!(normalize_name(""), "_");
+        assert_eq!(normalize_file_name("Song .flac"), "Song.flac");
+    }
+
+    #[test]
+    fn long_names_are_cut_on_char_boundaries() {
+        let name = normalize_name(&"東".repeat(100));
+        assert!(name.len() <= MAX_NAME_BYTES);
+        assert_eq!(name, "東".repeat(66));
+
+        let name = normalize_file_name(&format!("{}.flac", "é".repeat(150)));
+        assert!(name.len() <= MAX_NAME_BYTES);
+        assert_eq!(name, format!("{}.flac", "é".repeat(97)));
+    }
+
+    #[test]
+    fn duplicates_get_numbers_ignoring_case() {
+        assert_eq!(
+            song_names(&["Song.flac", "song.flac", "SONG.flac", "Other.flac"]),
+            ["Song.flac", "song (2).flac", "SONG (3).flac", "Other.flac"]
+        );
+        // cleaning can be what makes two names the same
+        assert_eq!(
+            song_names(&["A/B.mp3", "A_B.mp3", "A:B"]),
+            ["A_B.mp3", "A_B (2).mp3", "A_B"]
+        );
+    }
+
+    #[test]
+    fn numbered_duplicates_stay_within_the_limit() {
+        let long_name = format!("{}.flac", "a".repeat(250));
+        let names = song_names(&[&long_name, &long_name]);
+        assert!(names.iter().all(|name| name.len() <= MAX_NAME_BYTES));
+        assert!(names[1].ends_with(" (2).flac"));
+        assert_ne!(names[0], names[1]);
+    }
+
+    fn wav_titled(title: &str) -> Vec<u8> {
+        let chunk = |id: &[u8], content: &[u8]| {
+            [id, &(content.len() as u32).to_le_bytes()[..], content].concat()
+        };
+        let info = chunk(
+            b"LIST",
+            &[&b"INFO"[..], &chunk(b"INAM", title.as_bytes())].concat(),
+        );
+        let content = [&b"WAVE"[..], &info, &chunk(b"data", &[0; 4])].concat();
+        chunk(b"RIFF", &content)
+    }
+
+    #[test]
+    fn tag_names_are_read_again_once_the_song_changes() {
+        use std::{
+            fs::{self, File},
+            time::{Duration, SystemTime},
+        };
+        let path = std::env::temp_dir().join(format!(
+            "music_uploader_tag_names_{}.wav",
+            std::process::id()
+        ));
+        let song = Song {
+            song_name: "track.wav".to_string(),
+            path: path.to_string_lossy().to_string(),
+            entry: None,
+        };
+        let tag_names = TagNames::default();
+        let name = |title: &str, modified: SystemTime| {
+            fs::write(&path, wav_titled(title)).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_modified(modified))
+                .unwrap();
+            upload_names(
+                "Album",
+                "Artist",
+                std::slice::from_ref(&song),
+                Some(&tag_names),
+            )
+            .songs[0]
+                .name
+                .clone()
+        };
+        let before = SystemTime::now() - Duration::from_secs(60);
+        assert_eq!(name("First", before), "First.wav");
+        // same size and time, so the cached name is used without reading the tags
+        assert_eq!(name("Other", before), "First.wav");
+        assert_eq!(name("Other", SystemTime::now()), "Other.wav");
+        let _ = fs::remove_file(&path);
+    }
+}
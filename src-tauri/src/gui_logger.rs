@@ -1,6 +1,9 @@
 use serde::Serialize;
 use tauri::{AppHandle, Emitter};
 
+use crate::upload_queue::UploadQueueSnapshot;
+
+#[derive(Clone)]
 pub struct GuiLogger {
     app: AppHandle,
 }
@@ -80,6 +83,69 @@ impl GuiLogger {
             Err(e) => Ok(self.log(e.to_string())),
         };
     }
+
+    pub fn album_cancelled(&self, album_id: &String) {
+        let _ = self
+            .app
+            .emit("music_uploader://album_cancelled", album_id.to_string())
+            .map_err(|e| {
+                println!("GuiLogger.album_cancelled failed for: {}", e);
+            });
+    }
+
+    pub fn file_cancelled(&self, album_id: &String, file: &String) {
+        let file_cancelled_payload = FileCancelled {
+            album_id: album_id.to_string(),
+            file: file.to_string(),
+        };
+        let _ = match serde_json::to_string(&file_cancelled_payload) {
+            Ok(json) => self
+                .app
+                .emit("music_uploader://file_cancelled", json)
+                .map_err(|e| {
+                    println!("GuiLogger.file_cancelled failed for: {}", e);
+                }),
+            Err(e) => Ok(self.log(e.to_string())),
+        };
+    }
+
+    pub fn queue_changed(&self, snapshot: &UploadQueueSnapshot) {
+        let _ = match serde_json::to_string(snapshot) {
+            Ok(json) => self
+                .app
+                .emit("music_uploader://queue_changed", json)
+                .map_err(|e| {
+                    println!("GuiLogger.queue_changed failed for: {}", e);
+                }),
+            Err(e) => Ok(self.log(e.to_string())),
+        };
+    }
+
+    pub fn file_progress(
+        &self,
+        album_id: &String,
+        file: &String,
+        bytes_sent: u64,
+        total_bytes: u64,
+        bytes_per_second: f64,
+    ) {
+        let file_progress = FileProgress {
+            album_id: album_id.to_string(),
+            file: file.to_string(),
+            bytes_sent,
+            total_bytes,
+            bytes_per_second,
+        };
+        let _ = match serde_json::to_string(&file_progress) {
+            Ok(json) => self
+                .app
+                .emit("music_uploader://file_progress", json)
+                .map_err(|e| {
+                    println!("GuiLogger.file_progress failed for: {}", e);
+                }),
+            Err(e) => Ok(self.log(e.to_string())),
+        };
+    }
 }
 
 #[derive(Serialize)]
@@ -102,3 +168,18 @@ struct FileIsUploading {
     album_id: String,
     file: String,
 }
+
+#[derive(Serialize)]
+struct FileCancelled {
+    album_id: String,
+    file: String,
+}
+
+#[derive(Serialize)]
+struct FileProgress {
+    album_id: String,
+    file: String,
+    bytes_sent: u64,
+    total_bytes: u64,
+    bytes_per_second: f64,
+}
@@ -1,22 +1,76 @@
 mod actions;
+pub(crate) mod audio_format;
+pub(crate) mod cover_art;
+pub(crate) mod file_names;
 pub(crate) mod gui_logger;
+pub(crate) mod metadata;
+pub(crate) mod secret_store;
 pub(crate) mod settings;
+pub(crate) mod song_file;
+pub(crate) mod upload_journal;
+pub(crate) mod upload_progress;
+pub(crate) mod upload_queue;
 pub(crate) mod uploader_client;
+pub(crate) mod zip_entry;
 
-use crate::actions::upload_album::upload_album;
+use crate::actions::check_songs::check_songs_on_server;
+use crate::actions::cover_art::find_cover_art;
+use crate::actions::file_names::preview_upload_names;
+use crate::actions::profiles::{
+    create_profile, delete_profile, list_profiles, rename_profile, switch_profile,
+};
+use crate::actions::scan_directory::scan_directory;
+use crate::actions::song_metadata::read_song_metadata;
+use crate::actions::upload_album::{
+    cancel_upload, list_resumable_uploads, resume_upload, upload_album,
+};
+use crate::actions::upload_queue::{
+    drain_upload_queue, enqueue_album, list_upload_queue, remove_from_upload_queue,
+    reorder_upload_queue,
+};
+use crate::actions::validate_album::validate_album;
 
+use file_names::TagNames;
 use gui_logger::GuiLogger;
 use music_uploader_server::model::AlbumSearchResponse;
+use secret_store::{open_secret_store, SecretStore};
 use serde::{Deserialize, Serialize};
 use settings::{load_settings, Settings, UserEditableSettings};
-use std::{env, sync::RwLock};
+use song_file::SongHashes;
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex, RwLock},
+};
 use tauri::{AppHandle, Manager, State};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use upload_journal::UploadJournal;
+use upload_queue::UploadQueue;
 use uploader_client::{MusicUploaderClient, MusicUploaderClientConfig, MusicUploaderClientError};
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct Song {
     song_name: String,
     path: String,
+    // set when `path` is a zip archive and the song is one of the files in it
+    #[serde(default)]
+    entry: Option<String>,
+}
+
+impl Song {
+    /// identifies the song in events and the journal, several songs can share a
+    /// path when they come from the same zip.
+    fn key(&self) -> String {
+        Song::key_for(&self.path, self.entry.as_ref())
+    }
+
+    fn key_for(path: &String, entry: Option<&String>) -> String {
+        match entry {
+            Some(entry) => format!("{path}!/{entry}"),
+            None => path.to_string(),
+        }
+    }
 }
 
 #[tauri::command]
@@ -43,10 +97,7 @@ async fn run_settings_checks(state: State<'_, GuiState>) -> Result<String, Strin
     let logger = GuiLogger::new(state.app_handle.clone());
     if let Some(ref run_state) = state.run_state {
         let config = run_state.get_config();
-        let (user, server_url) = {
-            let settings = &run_state.settings.read().unwrap();
-            (settings.user.clone(), settings.server_url.clone())
-        };
+        let (user, server_url) = (config.user.clone(), config.server_url.clone());
         match run_state.client.check_conn(&config).await {
             Ok(_) => {
                 logger.log("Connection is good".to_string());
@@ -70,12 +121,15 @@ async fn run_settings_checks(state: State<'_, GuiState>) -> Result<String, Strin
 #[tauri::command]
 fn reload_settings(state: State<'_, GuiState>) -> Result<String, String> {
     let app_handle = &state.app_handle;
-    let result = load_settings(app_handle)?;
+    let result = load_settings(app_handle, state.secret_store.as_ref())?;
     let logger = GuiLogger::new(app_handle.clone());
     match state.run_state.as_ref() {
         Some(run_state) => {
             let mut settings = run_state.settings.write().unwrap();
             *settings = result.settings;
+            run_state
+                .client
+                .set_upload_rate_limit(settings.get_upload_rate_limit_bytes_per_second());
             logger.log(result.startup_message);
             Ok("Success".to_string())
         }
@@ -125,25 +179,19 @@ fn get_settings(state: State<'_, GuiState>) -> GetSettingsResult {
 #[tauri::command]
 fn save_settings(
     state: State<'_, GuiState>,
-    user: String,
-    password: String,
-    url: String,
-    max_part_size_mb: u32,
+    settings: UserEditableSettings,
 ) -> Result<String, String> {
     match state.run_state.as_ref() {
         Some(run_state) => {
-            let incoming_settings = UserEditableSettings {
-                user,
-                password,
-                server_url: url,
-                max_part_size_mb,
-            };
             let to_save = {
-                let mut settings = run_state.settings.write().unwrap();
-                settings.update(incoming_settings);
-                settings.clone()
+                let mut current_settings = run_state.settings.write().unwrap();
+                current_settings.update(settings);
+                current_settings.clone()
             };
-            to_save.save_settings(&state.app_handle)
+            run_state
+                .client
+                .set_upload_rate_limit(to_save.get_upload_rate_limit_bytes_per_second());
+            to_save.save_settings(&state.app_handle, state.secret_store.as_ref())
         }
         None => Err("no run state".to_string()),
     }
@@ -186,17 +234,68 @@ struct GuiState {
     run_state: Option<RunState>,
     startup_message: String,
     app_handle: AppHandle,
+    // where passwords and login tokens are kept instead of the settings file
+    secret_store: Arc<dyn SecretStore>,
 }
 
 struct RunState {
     client: MusicUploaderClient,
     settings: RwLock<Settings>,
+    // album id -> token that cancels that album's upload
+    active_uploads: Mutex<HashMap<String, CancellationToken>>,
+    journal: Mutex<UploadJournal>,
+    upload_queue: Mutex<UploadQueue>,
+    // woken whenever an album is added to the upload queue
+    upload_queue_notify: Notify,
+    // shared with the blocking tasks that do the hashing
+    song_hashes: Arc<SongHashes>,
+    // shared with the blocking tasks that preview upload names
+    tag_names: Arc<TagNames>,
 }
 
 impl RunState {
     pub fn get_config(&self) -> MusicUploaderClientConfig {
         self.settings.read().unwrap().get_config()
     }
+
+    /// the config an album uploads with, the active profile's unless the album picked one.
+    pub fn get_profile_config(
+        &self,
+        profile_id: Option<&String>,
+    ) -> Result<MusicUploaderClientConfig, MusicUploaderClientError> {
+        self.settings
+            .read()
+            .unwrap()
+            .get_profile_config(profile_id)
+            .ok_or(MusicUploaderClientError::BadConfig(format!(
+                "The profile {} no longer exists",
+                profile_id.map(String::as_str).unwrap_or_default()
+            )))
+    }
+
+    pub fn start_upload(&self, album_id: &String) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.active_uploads
+            .lock()
+            .unwrap()
+            .insert(album_id.to_string(), token.clone());
+        token
+    }
+
+    pub fn finish_upload(&self, album_id: &String) {
+        self.active_uploads.lock().unwrap().remove(album_id);
+    }
+
+    /// returns false if the album is not currently uploading.
+    pub fn cancel_upload(&self, album_id: &str) -> bool {
+        match self.active_uploads.lock().unwrap().get(album_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 const SUCCESS_MESSAGE: &str = "Boot Success :)";
@@ -206,7 +305,12 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
             let logger = GuiLogger::new(app.handle().clone());
-            let potential_settings = load_settings(app.handle());
+            let secret_store = open_secret_store(app.handle())?;
+            let potential_settings = load_settings(app.handle(), secret_store.as_ref());
+            let journal = UploadJournal::load(app.handle()).unwrap_or_else(|e| {
+                println!("{e}, uploads will not be resumable");
+                UploadJournal::in_memory()
+            });
             let state = GuiState {
                 startup_message: match &potential_settings {
                     Ok(load_settings_result) => format!(
@@ -215,20 +319,52 @@ pub fn run() {
                     ),
                     Err(fail_message) => fail_message.clone(),
                 },
-                run_state: potential_settings
-                    .ok()
-                    .map(|load_settings_result| RunState {
-                        client: MusicUploaderClient::new(logger),
+                run_state: potential_settings.ok().map(|load_settings_result| {
+                    let client = MusicUploaderClient::new(logger, secret_store.clone());
+                    client.set_upload_rate_limit(
+                        load_settings_result
+                            .settings
+                            .get_upload_rate_limit_bytes_per_second(),
+                    );
+                    RunState {
+                        client,
                         settings: RwLock::new(load_settings_result.settings),
-                    }),
+                        active_uploads: Mutex::new(HashMap::new()),
+                        journal: Mutex::new(journal),
+                        upload_queue: Mutex::new(UploadQueue::default()),
+                        upload_queue_notify: Notify::new(),
+                        song_hashes: Arc::new(SongHashes::default()),
+                        tag_names: Arc::new(TagNames::default()),
+                    }
+                }),
                 app_handle: app.handle().clone(),
+                secret_store,
             };
             app.manage(state);
+            tauri::async_runtime::spawn(drain_upload_queue(app.handle().clone()));
             Ok(())
         })
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             upload_album,
+            cancel_upload,
+            list_resumable_uploads,
+            resume_upload,
+            enqueue_album,
+            list_upload_queue,
+            reorder_upload_queue,
+            remove_from_upload_queue,
+            read_song_metadata,
+            validate_album,
+            scan_directory,
+            find_cover_art,
+            preview_upload_names,
+            check_songs_on_server,
+            list_profiles,
+            create_profile,
+            rename_profile,
+            delete_profile,
+            switch_profile,
             generate_guid,
             get_valid_extensions,
             get_startup_message,
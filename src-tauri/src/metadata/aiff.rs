@@ -0,0 +1,126 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{
+    decode_utf8_or_latin1, first_value, id3v2, read_bytes, stream_len, MetadataError, SongMetadata,
+    TagField, MAX_TEXT_VALUE_BYTES,
+};
+
+const FORM_HEADER_LEN: u64 = 12;
+const CHUNK_HEADER_LEN: u64 = 8;
+
+/// Reads the id3 chunk of an aiff file, falling back to the NAME and AUTH text
+/// chunks for files that only have those.
+pub fn read<R: Read + Seek>(
+    reader: &mut R,
+    with_picture: bool,
+) -> Result<SongMetadata, MetadataError> {
+    let file_end = stream_len(reader)?;
+    let mut header = [0u8; FORM_HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"FORM" || !matches!(&header[8..12], b"AIFF" | b"AIFC") {
+        return Err(MetadataError::Malformed("not an AIFF file".to_string()));
+    }
+    let form_size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as u64;
+    let form_end = u64::min(file_end, 8 + form_size);
+
+    let mut id3_metadata = SongMetadata::default();
+    let mut text_metadata = SongMetadata::default();
+    let mut position = FORM_HEADER_LEN;
+    while position + CHUNK_HEADER_LEN <= form_end {
+        reader.seek(SeekFrom::Start(position))?;
+        let mut chunk_header = [0u8; CHUNK_HEADER_LEN as usize];
+        reader.read_exact(&mut chunk_header)?;
+        let id = [
+            chunk_header[0],
+            chunk_header[1],
+            chunk_header[2],
+            chunk_header[3],
+        ];
+        let size = u32::from_be_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as u64;
+        let text_field = match &id {
+            b"NAME" => Some(TagField::Title),
+            b"AUTH" => Some(TagField::Artist),
+            _ => None,
+        };
+        if let Some(field) = text_field {
+            if size <= MAX_TEXT_VALUE_BYTES {
+                let text = decode_utf8_or_latin1(&read_bytes(reader, size)?);
+                if let Some(text) = first_value(&text) {
+                    text_metadata.set(field, text);
+                }
+            }
+        } else if matches!(&id, b"ID3 " | b"id3 ") {
+            id3_metadata.fill_missing(id3v2::read(reader, with_picture)?);
+        }
+        // chunks are padded to an even length
+        position += CHUNK_HEADER_LEN + size + (size & 1);
+    }
+    id3_metadata.fill_missing(text_metadata);
+    Ok(id3_metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        data.extend_from_slice(content);
+        if content.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    fn file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let content = [b"AIFF".to_vec(), chunks.concat()].concat();
+        chunk(b"FORM", &content)
+    }
+
+    fn read_file(data: Vec<u8>) -> Result<SongMetadata, MetadataError> {
+        read(&mut Cursor::new(data), false)
+    }
+
+    #[test]
+    fn text_chunks_are_read() {
+        let data = file(&[
+            chunk(b"COMM", &[0; 18]),
+            chunk(b"NAME", b"Song"),
+            chunk(b"AUTH", b"Artist"),
+            chunk(b"SSND", &[0; 8]),
+        ]);
+        let metadata = read_file(data).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = file(&[chunk(b"NAME", b"Song")]);
+        for len in [0, 6, 11, data.len() - 1] {
+            assert!(read_file(data[..len].to_vec()).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn other_files_are_malformed() {
+        let mut data = file(&[]);
+        data[8..12].copy_from_slice(b"WAVE");
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+
+    #[test]
+    fn huge_chunk_sizes_end_the_file() {
+        let mut data = file(&[chunk(b"COMM", &[0; 18]), chunk(b"NAME", b"Song")]);
+        data[16..20].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(read_file(data).unwrap().title.is_none());
+    }
+}
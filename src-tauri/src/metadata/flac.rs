@@ -0,0 +1,147 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{
+    id3v2, read_bytes, vorbis, EmbeddedPicture, MetadataError, SongMetadata,
+    FRONT_COVER_PICTURE_TYPE, MAX_PICTURE_BYTES,
+};
+use crate::audio_format::id3v2_tag_len;
+
+const VORBIS_COMMENT_BLOCK: u8 = 4;
+const PICTURE_BLOCK: u8 = 6;
+
+/// Reads the vorbis comment block. An id3 tag in front of the stream is not
+/// standard but it happens, it fills in whatever the comments leave out.
+pub fn read<R: Read + Seek>(
+    reader: &mut R,
+    with_picture: bool,
+) -> Result<SongMetadata, MetadataError> {
+    let id3_metadata = id3v2::read(reader, with_picture)?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 10];
+    reader.read_exact(&mut header)?;
+    let stream_start = id3v2_tag_len(&header).unwrap_or(0);
+    reader.seek(SeekFrom::Start(stream_start))?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        return Err(MetadataError::Malformed(
+            "flac stream marker is missing".to_string(),
+        ));
+    }
+
+    let mut metadata = SongMetadata::default();
+    let mut pictures = Vec::new();
+    loop {
+        let mut block_header = [0u8; 4];
+        reader.read_exact(&mut block_header)?;
+        let is_last_block = block_header[0] & 0x80 != 0;
+        let block_type = block_header[0] & 0x7F;
+        let block_len =
+            u32::from_be_bytes([0, block_header[1], block_header[2], block_header[3]]) as u64;
+        let block_end = reader.stream_position()? + block_len;
+        if block_type == VORBIS_COMMENT_BLOCK {
+            let max_len = vorbis::max_comment_bytes(with_picture);
+            let comments = read_bytes(reader, u64::min(block_len, max_len))?;
+            metadata = vorbis::parse_comments(&comments, with_picture);
+        } else if with_picture && block_type == PICTURE_BLOCK && block_len <= MAX_PICTURE_BYTES {
+            pictures.extend(parse_picture_block(&read_bytes(reader, block_len)?));
+        }
+        if is_last_block {
+            break;
+        }
+        reader.seek(SeekFrom::Start(block_end))?;
+    }
+    for picture in pictures {
+        metadata.set_picture(picture);
+    }
+    metadata.fill_missing(id3_metadata);
+    Ok(metadata)
+}
+
+/// picture blocks hold the picture type, mime type, description and dimensions
+/// before the image, each length a big endian u32. Vorbis comments carry the
+/// same block in base64.
+pub fn parse_picture_block(data: &[u8]) -> Option<EmbeddedPicture> {
+    let read_u32 = |position: usize| {
+        data.get(position..position + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let picture_type = read_u32(0)?;
+    let mime_len = read_u32(4)? as usize;
+    let description_len = read_u32(8 + mime_len)? as usize;
+    // width, height, colour depth and palette size sit between the description and the image
+    let data_len_position = 8 + mime_len + 4 + description_len + 16;
+    let data_len = read_u32(data_len_position)? as usize;
+    let image_start = data_len_position + 4;
+    Some(EmbeddedPicture {
+        is_front_cover: picture_type == FRONT_COVER_PICTURE_TYPE,
+        data: data.get(image_start..image_start + data_len)?.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::metadata::vorbis::tests::comment_block;
+
+    const STREAMINFO_BLOCK: u8 = 0;
+
+    fn block(block_type: u8, is_last: bool, content: &[u8]) -> Vec<u8> {
+        let len = (content.len() as u32).to_be_bytes();
+        let flag = if is_last { 0x80 } else { 0 };
+        let mut data = vec![flag | block_type, len[1], len[2], len[3]];
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn file(comments: &[&str]) -> Vec<u8> {
+        [
+            b"fLaC".to_vec(),
+            block(STREAMINFO_BLOCK, false, &[0; 34]),
+            block(VORBIS_COMMENT_BLOCK, true, &comment_block(comments)),
+        ]
+        .concat()
+    }
+
+    fn read_file(data: Vec<u8>) -> Result<SongMetadata, MetadataError> {
+        read(&mut Cursor::new(data), false)
+    }
+
+    #[test]
+    fn comments_are_read() {
+        let metadata = read_file(file(&["TITLE=Song", "DISCNUMBER=2/3"])).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.disc_number, Some(2));
+        assert_eq!(metadata.disc_total, Some(3));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = file(&["TITLE=Song"]);
+        for len in [0, 3, 6, 20, data.len() - 1] {
+            assert!(read_file(data[..len].to_vec()).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn a_missing_stream_marker_is_malformed() {
+        let mut data = file(&["TITLE=Song"]);
+        data[0..4].copy_from_slice(b"OggS");
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+
+    #[test]
+    fn running_out_of_blocks_before_the_last_one_is_an_error() {
+        let data = [b"fLaC".to_vec(), block(STREAMINFO_BLOCK, false, &[0; 34])].concat();
+        assert!(read_file(data).is_err());
+    }
+
+    #[test]
+    fn picture_blocks_with_huge_lengths_are_ignored() {
+        let mut picture = vec![0, 0, 0, 3];
+        picture.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse_picture_block(&picture).is_none());
+    }
+}
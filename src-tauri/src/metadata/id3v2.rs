@@ -0,0 +1,369 @@
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use super::{
+    decode_latin1, first_value, read_bytes, EmbeddedPicture, MetadataError, SongMetadata, TagField,
+    FRONT_COVER_PICTURE_TYPE, MAX_PICTURE_BYTES, MAX_TEXT_VALUE_BYTES,
+};
+
+const TAG_HEADER_LEN: u64 = 10;
+const TAG_FLAG_UNSYNCHRONISATION: u8 = 0x80;
+// in 2.2 this bit means the whole tag is compressed instead
+const TAG_FLAG_EXTENDED_HEADER: u8 = 0x40;
+
+const V3_FRAME_COMPRESSED: u16 = 0x0080;
+const V3_FRAME_ENCRYPTED: u16 = 0x0040;
+const V3_FRAME_GROUPED: u16 = 0x0020;
+const V4_FRAME_GROUPED: u16 = 0x0040;
+const V4_FRAME_COMPRESSED: u16 = 0x0008;
+const V4_FRAME_ENCRYPTED: u16 = 0x0004;
+const V4_FRAME_UNSYNCHRONISED: u16 = 0x0002;
+const V4_FRAME_DATA_LENGTH: u16 = 0x0001;
+
+/// Reads an id3v2 tag (versions 2.2 to 2.4) starting at the reader's current
+/// position. Gives back empty metadata if there is no tag there.
+pub fn read<R: Read + Seek>(
+    reader: &mut R,
+    with_picture: bool,
+) -> Result<SongMetadata, MetadataError> {
+    let mut header = [0u8; TAG_HEADER_LEN as usize];
+    let header_len = reader.read(&mut header)?;
+    if header_len < header.len() || &header[0..3] != b"ID3" {
+        return Ok(SongMetadata::default());
+    }
+    let version = header[3];
+    let flags = header[5];
+    let tag_size = syncsafe(&header[6..10]) as u64;
+    if !(2..=4).contains(&version) {
+        return Err(MetadataError::Malformed(format!(
+            "unknown id3 version 2.{version}"
+        )));
+    }
+    if version == 2 && flags & TAG_FLAG_EXTENDED_HEADER != 0 {
+        // compressed 2.2 tags were never properly specified, nobody writes them
+        return Ok(SongMetadata::default());
+    }
+    let tag = Tag {
+        version,
+        unsynchronised: flags & TAG_FLAG_UNSYNCHRONISATION != 0,
+        has_extended_header: version > 2 && flags & TAG_FLAG_EXTENDED_HEADER != 0,
+        with_picture,
+    };
+    // before 2.4 unsynchronisation applies to the whole tag, so undo it before looking at frames
+    if tag.unsynchronised && version < 4 {
+        let data = remove_unsynchronisation(&read_bytes(reader, tag_size)?);
+        let len = data.len() as u64;
+        return tag.read_frames(&mut Cursor::new(data), len);
+    }
+    tag.read_frames(reader, tag_size)
+}
+
+struct Tag {
+    version: u8,
+    unsynchronised: bool,
+    has_extended_header: bool,
+    with_picture: bool,
+}
+
+struct FrameHeader {
+    id: [u8; 4],
+    size: u64,
+    flags: u16,
+}
+
+impl Tag {
+    fn read_frames<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        tag_size: u64,
+    ) -> Result<SongMetadata, MetadataError> {
+        let end = reader.stream_position()? + tag_size;
+        if self.has_extended_header {
+            self.skip_extended_header(reader)?;
+        }
+        let mut metadata = SongMetadata::default();
+        while reader.stream_position()? + self.frame_header_len() <= end {
+            let Some(frame) = self.read_frame_header(reader)? else {
+                // reached the padding
+                break;
+            };
+            let frame_end = reader.stream_position()? + frame.size;
+            if frame_end > end {
+                break;
+            }
+            if let Some(field) = field_for(&frame.id) {
+                if frame.size <= MAX_TEXT_VALUE_BYTES {
+                    let data = read_bytes(reader, frame.size)?;
+                    let text = self.frame_data(&frame, data).and_then(|d| decode_text(&d));
+                    if let Some(text) = text {
+                        metadata.set(field, &text);
+                    }
+                }
+            } else if self.is_wanted_picture(&frame) {
+                let data = read_bytes(reader, frame.size)?;
+                let picture = self
+                    .frame_data(&frame, data)
+                    .and_then(|d| parse_picture(self.version, &d));
+                if let Some(picture) = picture {
+                    metadata.set_picture(picture);
+                }
+            }
+            reader.seek(SeekFrom::Start(frame_end))?;
+        }
+        Ok(metadata)
+    }
+
+    fn skip_extended_header<R: Read + Seek>(&self, reader: &mut R) -> Result<(), MetadataError> {
+        let mut size = [0u8; 4];
+        reader.read_exact(&mut size)?;
+        // 2.3 does not count the size bytes themselves, 2.4 does
+        let remaining = match self.version {
+            3 => u32::from_be_bytes(size) as i64,
+            _ => syncsafe(&size) as i64 - 4,
+        };
+        reader.seek(SeekFrom::Current(remaining.max(0)))?;
+        Ok(())
+    }
+
+    fn frame_header_len(&self) -> u64 {
+        match self.version {
+            2 => 6,
+            _ => 10,
+        }
+    }
+
+    fn read_frame_header<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<FrameHeader>, MetadataError> {
+        let mut header = [0u8; 10];
+        let header = &mut header[..self.frame_header_len() as usize];
+        reader.read_exact(header)?;
+        if header[0] == 0 {
+            return Ok(None);
+        }
+        let frame = match self.version {
+            2 => FrameHeader {
+                id: [header[0], header[1], header[2], 0],
+                size: u32::from_be_bytes([0, header[3], header[4], header[5]]) as u64,
+                flags: 0,
+            },
+            3 => FrameHeader {
+                id: [header[0], header[1], header[2], header[3]],
+                size: u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as u64,
+                flags: u16::from_be_bytes([header[8], header[9]]),
+            },
+            _ => FrameHeader {
+                id: [header[0], header[1], header[2], header[3]],
+                size: syncsafe(&header[4..8]) as u64,
+                flags: u16::from_be_bytes([header[8], header[9]]),
+            },
+        };
+        Ok(Some(frame))
+    }
+
+    fn is_wanted_picture(&self, frame: &FrameHeader) -> bool {
+        self.with_picture
+            && matches!(&frame.id, b"APIC" | b"PIC\0")
+            && frame.size <= MAX_PICTURE_BYTES
+    }
+
+    /// strips whatever the frame flags put in front of the frame's content.
+    fn frame_data(&self, frame: &FrameHeader, data: Vec<u8>) -> Option<Vec<u8>> {
+        let mut data = data;
+        match self.version {
+            3 => {
+                if frame.flags & (V3_FRAME_COMPRESSED | V3_FRAME_ENCRYPTED) != 0 {
+                    return None;
+                }
+                if frame.flags & V3_FRAME_GROUPED != 0 {
+                    data = data.get(1..)?.to_vec();
+                }
+            }
+            4 => {
+                if frame.flags & (V4_FRAME_COMPRESSED | V4_FRAME_ENCRYPTED) != 0 {
+                    return None;
+                }
+                if frame.flags & V4_FRAME_GROUPED != 0 {
+                    data = data.get(1..)?.to_vec();
+                }
+                if frame.flags & V4_FRAME_DATA_LENGTH != 0 {
+                    data = data.get(4..)?.to_vec();
+                }
+                if self.unsynchronised || frame.flags & V4_FRAME_UNSYNCHRONISED != 0 {
+                    data = remove_unsynchronisation(&data);
+                }
+            }
+            _ => {}
+        }
+        Some(data)
+    }
+}
+
+fn field_for(id: &[u8; 4]) -> Option<TagField> {
+    match id {
+        b"TIT2" | b"TT2\0" => Some(TagField::Title),
+        b"TPE1" | b"TP1\0" => Some(TagField::Artist),
+        b"TPE2" | b"TP2\0" => Some(TagField::AlbumArtist),
+        b"TALB" | b"TAL\0" => Some(TagField::Album),
+        b"TRCK" | b"TRK\0" => Some(TagField::Track),
+        b"TPOS" | b"TPA\0" => Some(TagField::Disc),
+        b"TDRC" | b"TYER" | b"TYE\0" => Some(TagField::Year),
+        _ => None,
+    }
+}
+
+/// picture frames hold a text encoding, the image type, the picture type and a
+/// description before the image itself. The image type is a mime type, or three
+/// letters in 2.2, and is ignored since the image is checked by its content anyway.
+fn parse_picture(version: u8, data: &[u8]) -> Option<EmbeddedPicture> {
+    let (encoding, rest) = data.split_first()?;
+    let rest = match version {
+        2 => rest.get(3..)?,
+        _ => {
+            let end = rest.iter().position(|b| *b == 0)?;
+            &rest[end + 1..]
+        }
+    };
+    let (picture_type, rest) = rest.split_first()?;
+    let data = skip_terminated_text(rest, *encoding)?;
+    Some(EmbeddedPicture {
+        is_front_cover: *picture_type as u32 == FRONT_COVER_PICTURE_TYPE,
+        data: data.to_vec(),
+    })
+}
+
+// utf-16 text ends with two zero bytes, everything else with one
+fn skip_terminated_text(data: &[u8], encoding: u8) -> Option<&[u8]> {
+    match encoding {
+        1 | 2 => {
+            let end = data.chunks_exact(2).position(|pair| pair == [0, 0])?;
+            data.get(end * 2 + 2..)
+        }
+        _ => {
+            let end = data.iter().position(|b| *b == 0)?;
+            data.get(end + 1..)
+        }
+    }
+}
+
+/// text frames start with a byte saying how the rest is encoded.
+fn decode_text(data: &[u8]) -> Option<String> {
+    let (encoding, text) = data.split_first()?;
+    let decoded = match encoding {
+        0 => decode_latin1(text),
+        1 => decode_utf16(text, text.starts_with(&[0xFE, 0xFF])),
+        2 => decode_utf16(text, true),
+        3 => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+    first_value(&decoded).map(str::to_string)
+}
+
+fn decode_utf16(data: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| match big_endian {
+            true => u16::from_be_bytes([pair[0], pair[1]]),
+            false => u16::from_le_bytes([pair[0], pair[1]]),
+        })
+        .collect();
+    String::from_utf16_lossy(&units).replace('\u{FEFF}', "")
+}
+
+// 7 bits per byte so the size can never look like a frame sync
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, byte| (size << 7) | (*byte & 0x7F) as u32)
+}
+
+// unsynchronisation puts a zero after every 0xFF, this takes them back out
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut previous_was_ff = false;
+    for byte in data {
+        if !(previous_was_ff && *byte == 0) {
+            result.push(*byte);
+        }
+        previous_was_ff = *byte == 0xFF;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syncsafe_bytes(size: u32) -> [u8; 4] {
+        [
+            (size >> 21) as u8 & 0x7F,
+            (size >> 14) as u8 & 0x7F,
+            (size >> 7) as u8 & 0x7F,
+            size as u8 & 0x7F,
+        ]
+    }
+
+    // a 2.3 tag, where frame sizes are plain big endian
+    fn tag(frames: &[(&[u8; 4], &str)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (id, text) in frames {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+            body.extend_from_slice(&[0, 0, 0]);
+            body.extend_from_slice(text.as_bytes());
+        }
+        // padding
+        body.extend_from_slice(&[0; 16]);
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend_from_slice(&syncsafe_bytes(body.len() as u32));
+        data.extend(body);
+        data
+    }
+
+    fn read_tag(data: Vec<u8>) -> Result<SongMetadata, MetadataError> {
+        read(&mut Cursor::new(data), false)
+    }
+
+    #[test]
+    fn text_frames_are_read() {
+        let metadata = read_tag(tag(&[
+            (b"TIT2", "Song"),
+            (b"TPE1", "Artist"),
+            (b"TRCK", "3/12"),
+        ]))
+        .unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.track_number, Some(3));
+        assert_eq!(metadata.track_total, Some(12));
+    }
+
+    #[test]
+    fn no_tag_is_no_metadata() {
+        let metadata = read_tag(b"\xFF\xFB\x90\x00 not a tag".to_vec()).unwrap();
+        assert!(metadata.title.is_none());
+    }
+
+    #[test]
+    fn truncated_tags_are_errors() {
+        let data = tag(&[(b"TIT2", "Song"), (b"TALB", "Album")]);
+        for len in [TAG_HEADER_LEN as usize + 4, TAG_HEADER_LEN as usize + 14] {
+            assert!(read_tag(data[..len].to_vec()).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn unknown_versions_are_malformed() {
+        let mut data = tag(&[(b"TIT2", "Song")]);
+        data[3] = 7;
+        assert!(matches!(read_tag(data), Err(MetadataError::Malformed(_))));
+    }
+
+    #[test]
+    fn frames_bigger_than_the_tag_are_skipped() {
+        let mut data = tag(&[(b"TIT2", "Song")]);
+        data[TAG_HEADER_LEN as usize + 4..TAG_HEADER_LEN as usize + 8]
+            .copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(read_tag(data).unwrap().title.is_none());
+    }
+}
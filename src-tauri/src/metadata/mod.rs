@@ -0,0 +1,258 @@
+mod aiff;
+mod flac;
+mod id3v2;
+mod mp4;
+mod ogg;
+mod riff;
+mod vorbis;
+
+use std::{
+    fmt,
+    io::{self, Read, Seek},
+    path::Path,
+};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{audio_format::AudioFormat, song_file::SongFile};
+
+// text values are short, anything bigger than this is not something we want to read
+const MAX_TEXT_VALUE_BYTES: u64 = 64 * 1024;
+// nobody embeds cover art this big on purpose
+const MAX_PICTURE_BYTES: u64 = 16 * 1024 * 1024;
+// the picture type id3 and flac both use for the front cover
+const FRONT_COVER_PICTURE_TYPE: u32 = 3;
+
+/// Tags read from a song file. Every field is optional since files are tagged
+/// (or not) in all kinds of ways.
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct SongMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub year: Option<u32>,
+    // only read when asked for, see `read_embedded_picture`
+    #[serde(skip)]
+    pub picture: Option<EmbeddedPicture>,
+}
+
+/// A picture stored in a song's tags.
+#[derive(Clone)]
+pub struct EmbeddedPicture {
+    pub is_front_cover: bool,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Debug for EmbeddedPicture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmbeddedPicture")
+            .field("is_front_cover", &self.is_front_cover)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    #[error("Failed to read tags: {0}")]
+    Io(#[from] io::Error),
+    #[error("Cannot read tags from .{0} files")]
+    UnsupportedFormat(String),
+    #[error("Tags are malformed: {0}")]
+    Malformed(String),
+}
+
+/// the tag fields we know about, so each format only has to map its own names onto these.
+#[derive(Clone, Copy)]
+enum TagField {
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    // "3" or "3/12"
+    Track,
+    Disc,
+    Year,
+}
+
+pub fn read_metadata(file: &SongFile) -> Result<SongMetadata, MetadataError> {
+    read_tags(file, false)
+}
+
+/// the cover art in the song's tags, or whichever picture is there if none is
+/// marked as the front cover.
+pub fn read_embedded_picture(file: &SongFile) -> Result<Option<EmbeddedPicture>, MetadataError> {
+    read_tags(file, true).map(|metadata| metadata.picture)
+}
+
+// pictures can be megabytes each, so they are only read when `with_picture` is set
+fn read_tags(file: &SongFile, with_picture: bool) -> Result<SongMetadata, MetadataError> {
+    let extension = Path::new(file.name())
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut reader = file.reader()?;
+    // go by the content, a renamed file should still have its tags read properly
+    match AudioFormat::sniff(&mut reader)? {
+        Some(AudioFormat::Mp3) => id3v2::read(&mut reader, with_picture),
+        Some(AudioFormat::M4a) => mp4::read(&mut reader, with_picture),
+        Some(AudioFormat::Wav) => riff::read(&mut reader, with_picture),
+        Some(AudioFormat::Flac) => flac::read(&mut reader, with_picture),
+        Some(AudioFormat::Vorbis | AudioFormat::Opus) => ogg::read(&mut reader, with_picture),
+        Some(AudioFormat::Aiff) => aiff::read(&mut reader, with_picture),
+        None => Err(MetadataError::UnsupportedFormat(extension)),
+    }
+}
+
+impl SongMetadata {
+    /// the first value found for a field wins, later ones are ignored.
+    fn set(&mut self, field: TagField, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        match field {
+            TagField::Title => set_if_missing(&mut self.title, value.to_string()),
+            TagField::Artist => set_if_missing(&mut self.artist, value.to_string()),
+            TagField::AlbumArtist => set_if_missing(&mut self.album_artist, value.to_string()),
+            TagField::Album => set_if_missing(&mut self.album, value.to_string()),
+            TagField::Track => {
+                let (number, total) = parse_position(value);
+                self.set_track(number, total);
+            }
+            TagField::Disc => {
+                let (number, total) = parse_position(value);
+                self.set_disc(number, total);
+            }
+            TagField::Year => {
+                if let Some(year) = parse_year(value) {
+                    set_if_missing(&mut self.year, year);
+                }
+            }
+        }
+    }
+
+    fn set_track(&mut self, number: Option<u32>, total: Option<u32>) {
+        if let Some(number) = number {
+            set_if_missing(&mut self.track_number, number);
+        }
+        if let Some(total) = total {
+            set_if_missing(&mut self.track_total, total);
+        }
+    }
+
+    fn set_disc(&mut self, number: Option<u32>, total: Option<u32>) {
+        if let Some(number) = number {
+            set_if_missing(&mut self.disc_number, number);
+        }
+        if let Some(total) = total {
+            set_if_missing(&mut self.disc_total, total);
+        }
+    }
+
+    /// a front cover replaces any other picture, otherwise the first picture wins.
+    fn set_picture(&mut self, picture: EmbeddedPicture) {
+        let keep_current = self
+            .picture
+            .as_ref()
+            .is_some_and(|current| current.is_front_cover || !picture.is_front_cover);
+        if !keep_current {
+            self.picture = Some(picture);
+        }
+    }
+
+    /// fills in whatever this is missing from `other`, for files carrying more than one tag.
+    fn fill_missing(&mut self, other: SongMetadata) {
+        let SongMetadata {
+            title,
+            artist,
+            album_artist,
+            album,
+            track_number,
+            track_total,
+            disc_number,
+            disc_total,
+            year,
+            picture,
+        } = other;
+        self.title = self.title.take().or(title);
+        self.artist = self.artist.take().or(artist);
+        self.album_artist = self.album_artist.take().or(album_artist);
+        self.album = self.album.take().or(album);
+        self.track_number = self.track_number.or(track_number);
+        self.track_total = self.track_total.or(track_total);
+        self.disc_number = self.disc_number.or(disc_number);
+        self.disc_total = self.disc_total.or(disc_total);
+        self.year = self.year.or(year);
+        if let Some(picture) = picture {
+            self.set_picture(picture);
+        }
+    }
+}
+
+fn set_if_missing<T>(target: &mut Option<T>, value: T) {
+    if target.is_none() {
+        *target = Some(value);
+    }
+}
+
+// zero means "not set" in every format we read
+fn parse_position(value: &str) -> (Option<u32>, Option<u32>) {
+    let mut split = value.splitn(2, '/');
+    let number = split.next().and_then(|n| n.trim().parse().ok());
+    let total = split.next().and_then(|t| t.trim().parse().ok());
+    (number.filter(|n| *n > 0), total.filter(|t| *t > 0))
+}
+
+// dates come as "2004", "2004-05-01" or "2004-05-01T00:00:00Z"
+fn parse_year(value: &str) -> Option<u32> {
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        4 => digits.parse().ok(),
+        _ => None,
+    }
+}
+
+/// reads exactly `len` bytes, without trusting `len` enough to allocate it up front.
+fn read_bytes<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>, MetadataError> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(MetadataError::Malformed(
+            "file ended in the middle of a tag".to_string(),
+        ));
+    }
+    Ok(data)
+}
+
+fn stream_len<R: Seek>(reader: &mut R) -> Result<u64, MetadataError> {
+    let position = reader.stream_position()?;
+    let len = reader.seek(io::SeekFrom::End(0))?;
+    reader.seek(io::SeekFrom::Start(position))?;
+    Ok(len)
+}
+
+fn decode_latin1(data: &[u8]) -> String {
+    data.iter().map(|b| *b as char).collect()
+}
+
+/// plenty of taggers write utf-8 where the format says latin-1, so try that first.
+fn decode_utf8_or_latin1(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => decode_latin1(data),
+    }
+}
+
+// some formats allow several null separated values, we only show the first
+fn first_value(text: &str) -> Option<&str> {
+    text.split('\0')
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+}
@@ -0,0 +1,279 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{
+    first_value, read_bytes, stream_len, EmbeddedPicture, MetadataError, SongMetadata, TagField,
+    MAX_PICTURE_BYTES, MAX_TEXT_VALUE_BYTES,
+};
+
+// the value in a "data" atom comes after 4 bytes of type and 4 bytes of locale
+const DATA_VALUE_OFFSET: usize = 8;
+
+struct Atom {
+    kind: [u8; 4],
+    data_start: u64,
+    end: u64,
+}
+
+/// Reads the itunes style tags in moov/udta/meta/ilst. The audio itself is never
+/// read, atoms we are not interested in are skipped over.
+pub fn read<R: Read + Seek>(
+    reader: &mut R,
+    with_picture: bool,
+) -> Result<SongMetadata, MetadataError> {
+    let file_end = stream_len(reader)?;
+    let Some(moov) = find_child(reader, 0, file_end, b"moov")? else {
+        return Err(MetadataError::Malformed("no moov atom".to_string()));
+    };
+    let Some(udta) = find_child(reader, moov.data_start, moov.end, b"udta")? else {
+        return Ok(SongMetadata::default());
+    };
+    let Some(meta) = find_child(reader, udta.data_start, udta.end, b"meta")? else {
+        return Ok(SongMetadata::default());
+    };
+    let meta_children_start = match is_full_atom(reader, &meta)? {
+        true => meta.data_start + 4,
+        false => meta.data_start,
+    };
+    let Some(ilst) = find_child(reader, meta_children_start, meta.end, b"ilst")? else {
+        return Ok(SongMetadata::default());
+    };
+
+    let mut metadata = SongMetadata::default();
+    let mut position = ilst.data_start;
+    while let Some(item) = read_atom_header(reader, position, ilst.end)? {
+        position = item.end;
+        if with_picture && &item.kind == b"covr" {
+            if let Some(picture) = read_picture(reader, &item)? {
+                metadata.set_picture(picture);
+            }
+            continue;
+        }
+        let Some(value) = read_item_value(reader, &item)? else {
+            continue;
+        };
+        match &item.kind {
+            b"trkn" => {
+                let (number, total) = parse_pair(&value);
+                metadata.set_track(number, total);
+            }
+            b"disk" => {
+                let (number, total) = parse_pair(&value);
+                metadata.set_disc(number, total);
+            }
+            kind => {
+                if let Some(field) = field_for(kind) {
+                    let text = String::from_utf8_lossy(&value);
+                    if let Some(text) = first_value(&text) {
+                        metadata.set(field, text);
+                    }
+                }
+            }
+        }
+    }
+    Ok(metadata)
+}
+
+fn field_for(kind: &[u8; 4]) -> Option<TagField> {
+    match kind {
+        b"\xA9nam" => Some(TagField::Title),
+        b"\xA9ART" => Some(TagField::Artist),
+        b"aART" => Some(TagField::AlbumArtist),
+        b"\xA9alb" => Some(TagField::Album),
+        b"\xA9day" => Some(TagField::Year),
+        _ => None,
+    }
+}
+
+/// the raw value of an ilst item, if it is one we care about.
+fn read_item_value<R: Read + Seek>(
+    reader: &mut R,
+    item: &Atom,
+) -> Result<Option<Vec<u8>>, MetadataError> {
+    let wanted = matches!(&item.kind, b"trkn" | b"disk") || field_for(&item.kind).is_some();
+    if !wanted {
+        return Ok(None);
+    }
+    read_data(reader, item, MAX_TEXT_VALUE_BYTES)
+}
+
+// itunes does not mark which picture is the front cover, it is taken to be the first
+fn read_picture<R: Read + Seek>(
+    reader: &mut R,
+    item: &Atom,
+) -> Result<Option<EmbeddedPicture>, MetadataError> {
+    let data = read_data(reader, item, MAX_PICTURE_BYTES)?;
+    Ok(data.map(|data| EmbeddedPicture {
+        is_front_cover: false,
+        data,
+    }))
+}
+
+/// the value of the first data atom in an item, unless it is over `max_len`.
+fn read_data<R: Read + Seek>(
+    reader: &mut R,
+    item: &Atom,
+    max_len: u64,
+) -> Result<Option<Vec<u8>>, MetadataError> {
+    let Some(data) = find_child(reader, item.data_start, item.end, b"data")? else {
+        return Ok(None);
+    };
+    let len = data.end - data.data_start;
+    if len > max_len {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(data.data_start))?;
+    let value = read_bytes(reader, len)?;
+    Ok(value.get(DATA_VALUE_OFFSET..).map(|v| v.to_vec()))
+}
+
+// track and disc are stored as 2 bytes padding, 2 bytes number, 2 bytes total
+fn parse_pair(value: &[u8]) -> (Option<u32>, Option<u32>) {
+    let read_u16 = |offset: usize| {
+        value
+            .get(offset..offset + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+            .filter(|n| *n > 0)
+    };
+    (read_u16(2), read_u16(4))
+}
+
+fn find_child<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    kind: &[u8; 4],
+) -> Result<Option<Atom>, MetadataError> {
+    let mut position = start;
+    while let Some(atom) = read_atom_header(reader, position, end)? {
+        if &atom.kind == kind {
+            return Ok(Some(atom));
+        }
+        position = atom.end;
+    }
+    Ok(None)
+}
+
+fn read_atom_header<R: Read + Seek>(
+    reader: &mut R,
+    position: u64,
+    parent_end: u64,
+) -> Result<Option<Atom>, MetadataError> {
+    if position + 8 > parent_end {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(position))?;
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let kind = [header[4], header[5], header[6], header[7]];
+    let does_not_fit = || {
+        MetadataError::Malformed(format!(
+            "atom {} does not fit in its parent",
+            String::from_utf8_lossy(&kind)
+        ))
+    };
+    let (data_start, end) = match size {
+        // the atom runs to the end of its parent
+        0 => (position + 8, parent_end),
+        // the real size follows as 64 bits, and can be anything
+        1 => {
+            let mut large_size = [0u8; 8];
+            reader.read_exact(&mut large_size)?;
+            let end = position
+                .checked_add(u64::from_be_bytes(large_size))
+                .ok_or_else(does_not_fit)?;
+            (position + 16, end)
+        }
+        _ => (position + 8, position + size),
+    };
+    if end < data_start || end > parent_end {
+        return Err(does_not_fit());
+    }
+    Ok(Some(Atom {
+        kind,
+        data_start,
+        end,
+    }))
+}
+
+// itunes writes meta as a full atom (version and flags before the children),
+// quicktime does not. In the quicktime layout the first child, hdlr, starts right away.
+fn is_full_atom<R: Read + Seek>(reader: &mut R, meta: &Atom) -> Result<bool, MetadataError> {
+    if meta.data_start + 8 > meta.end {
+        return Ok(true);
+    }
+    reader.seek(SeekFrom::Start(meta.data_start))?;
+    let mut start = [0u8; 8];
+    reader.read_exact(&mut start)?;
+    Ok(&start[4..8] != b"hdlr")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn atom(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = (content.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn item(kind: &[u8; 4], value: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; DATA_VALUE_OFFSET];
+        data.extend_from_slice(value);
+        atom(kind, &atom(b"data", &data))
+    }
+
+    fn file(items: &[Vec<u8>]) -> Vec<u8> {
+        let ilst = atom(b"ilst", &items.concat());
+        // itunes style, version and flags before the children
+        let meta = atom(b"meta", &[vec![0; 4], ilst].concat());
+        let moov = atom(b"moov", &atom(b"udta", &meta));
+        [atom(b"ftyp", b"M4A "), moov, atom(b"mdat", &[0; 32])].concat()
+    }
+
+    fn read_file(data: Vec<u8>) -> Result<SongMetadata, MetadataError> {
+        read(&mut Cursor::new(data), false)
+    }
+
+    #[test]
+    fn items_are_read() {
+        let metadata = read_file(file(&[
+            item(b"\xA9nam", b"Song"),
+            item(b"trkn", &[0, 0, 0, 3, 0, 12, 0, 0]),
+        ]))
+        .unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.track_number, Some(3));
+        assert_eq!(metadata.track_total, Some(12));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = file(&[item(b"\xA9nam", b"Song")]);
+        // cut off inside the first header, inside ftyp and at both ends of moov
+        for len in [4, 10, 30, data.len() - 41] {
+            assert!(read_file(data[..len].to_vec()).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn atoms_smaller_than_their_header_are_malformed() {
+        let mut data = file(&[item(b"\xA9nam", b"Song")]);
+        // the size of moov, right after the 12 byte ftyp
+        data[12..16].copy_from_slice(&4u32.to_be_bytes());
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+
+    #[test]
+    fn huge_large_sizes_are_malformed() {
+        let mut data = atom(b"free", &[]);
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"moov");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{read_bytes, stream_len, vorbis, MetadataError, SongMetadata};
+
+const PAGE_HEADER_LEN: u64 = 27;
+// a packet continues on the next segment for as long as segments are full
+const FULL_SEGMENT_LEN: u8 = 255;
+
+/// Ogg vorbis and opus both keep their comments in the second packet of the
+/// stream, behind a codec specific marker.
+pub fn read<R: Read + Seek>(
+    reader: &mut R,
+    with_picture: bool,
+) -> Result<SongMetadata, MetadataError> {
+    let max_packet_len = vorbis::max_comment_bytes(with_picture);
+    let packets = read_first_packets(reader, 2, max_packet_len)?;
+    let Some(comment_packet) = packets.get(1) else {
+        return Err(MetadataError::Malformed(
+            "ogg stream ends before its comments".to_string(),
+        ));
+    };
+    let comments = if let Some(comments) = comment_packet.strip_prefix(b"\x03vorbis") {
+        comments
+    } else if let Some(comments) = comment_packet.strip_prefix(b"OpusTags") {
+        comments
+    } else {
+        return Err(MetadataError::Malformed(
+            "second ogg packet is not a comment header".to_string(),
+        ));
+    };
+    Ok(vorbis::parse_comments(comments, with_picture))
+}
+
+/// reassembles the first `count` packets of the first logical stream in the file,
+/// keeping no more than `max_packet_len` bytes of each.
+fn read_first_packets<R: Read + Seek>(
+    reader: &mut R,
+    count: usize,
+    max_packet_len: u64,
+) -> Result<Vec<Vec<u8>>, MetadataError> {
+    let file_len = stream_len(reader)?;
+    let mut packets = Vec::new();
+    let mut current_packet = Vec::new();
+    let mut stream_serial = None;
+    let mut position = 0;
+    while packets.len() < count && position + PAGE_HEADER_LEN <= file_len {
+        reader.seek(SeekFrom::Start(position))?;
+        let header = read_bytes(reader, PAGE_HEADER_LEN)?;
+        if &header[0..4] != b"OggS" {
+            return Err(MetadataError::Malformed(format!(
+                "expected an ogg page at byte {position}"
+            )));
+        }
+        let serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        let segment_table = read_bytes(reader, header[26] as u64)?;
+        let body_len: u64 = segment_table.iter().map(|len| *len as u64).sum();
+        position += PAGE_HEADER_LEN + segment_table.len() as u64 + body_len;
+        // pages of other streams can be interleaved with ours
+        if *stream_serial.get_or_insert(serial) != serial {
+            continue;
+        }
+        let body = read_bytes(reader, body_len)?;
+        let mut segment_start = 0;
+        for segment_len in segment_table {
+            let segment = &body[segment_start..segment_start + segment_len as usize];
+            segment_start += segment_len as usize;
+            // anything past this is cover art, the comments we want come before it
+            if (current_packet.len() as u64) < max_packet_len {
+                current_packet.extend_from_slice(segment);
+            }
+            if segment_len < FULL_SEGMENT_LEN {
+                packets.push(std::mem::take(&mut current_packet));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::metadata::vorbis::tests::comment_block;
+
+    // a page holding whole packets, laced into segments the way ogg does it
+    fn page(serial: u32, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut segment_table = Vec::new();
+        for packet in packets {
+            segment_table.extend(vec![FULL_SEGMENT_LEN; packet.len() / 255]);
+            segment_table.push((packet.len() % 255) as u8);
+        }
+        let mut data = b"OggS".to_vec();
+        data.extend_from_slice(&[0; 10]);
+        data.extend_from_slice(&serial.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.push(segment_table.len() as u8);
+        data.extend(segment_table);
+        data.extend(packets.concat());
+        data
+    }
+
+    fn comment_packet(comments: &[&str]) -> Vec<u8> {
+        [b"\x03vorbis".to_vec(), comment_block(comments)].concat()
+    }
+
+    fn read_file(data: Vec<u8>) -> Result<SongMetadata, MetadataError> {
+        read(&mut Cursor::new(data), false)
+    }
+
+    #[test]
+    fn comments_are_read_from_the_second_packet() {
+        let title = format!("TITLE={}", "long ".repeat(100));
+        let data = [
+            page(1, &[b"\x01vorbis".to_vec()]),
+            // another stream in between is skipped
+            page(2, &[comment_packet(&["TITLE=Other"])]),
+            page(1, &[comment_packet(&[&title, "ARTIST=Artist"])]),
+        ]
+        .concat();
+        let metadata = read_file(data).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some(title[6..].trim()));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = [
+            page(1, &[b"\x01vorbis".to_vec()]),
+            page(1, &[comment_packet(&["TITLE=Song"])]),
+        ]
+        .concat();
+        for len in [0, 20, 30, 40, data.len() - 1] {
+            assert!(read_file(data[..len].to_vec()).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn pages_without_a_capture_pattern_are_malformed() {
+        let mut data = page(1, &[b"\x01vorbis".to_vec()]);
+        data.extend(b"not an ogg page, just some bytes".to_vec());
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+
+    #[test]
+    fn a_second_packet_that_is_not_comments_is_malformed() {
+        let data = page(1, &[b"\x01vorbis".to_vec(), b"\x05vorbis".to_vec()]);
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+}
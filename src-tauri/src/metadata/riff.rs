@@ -0,0 +1,177 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{
+    decode_utf8_or_latin1, first_value, id3v2, read_bytes, stream_len, MetadataError, SongMetadata,
+    TagField, MAX_TEXT_VALUE_BYTES,
+};
+
+const RIFF_HEADER_LEN: u64 = 12;
+const CHUNK_HEADER_LEN: u64 = 8;
+
+/// Reads the INFO list of a wav file, plus the id3 chunk some taggers add. When
+/// both are there the id3 tag wins, it has more fields and is usually newer.
+pub fn read<R: Read + Seek>(
+    reader: &mut R,
+    with_picture: bool,
+) -> Result<SongMetadata, MetadataError> {
+    let file_end = stream_len(reader)?;
+    let mut header = [0u8; RIFF_HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(MetadataError::Malformed("not a RIFF WAVE file".to_string()));
+    }
+    let riff_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+    let riff_end = u64::min(file_end, 8 + riff_size);
+
+    let mut id3_metadata = SongMetadata::default();
+    let mut info_metadata = SongMetadata::default();
+    let mut position = RIFF_HEADER_LEN;
+    while let Some((id, size)) = read_chunk_header(reader, position, riff_end)? {
+        let data_start = position + CHUNK_HEADER_LEN;
+        match &id {
+            b"LIST" => read_info_list(
+                reader,
+                data_start,
+                u64::min(riff_end, data_start + size),
+                &mut info_metadata,
+            )?,
+            b"id3 " | b"ID3 " => id3_metadata.fill_missing(id3v2::read(reader, with_picture)?),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        position = data_start + size + (size & 1);
+    }
+    id3_metadata.fill_missing(info_metadata);
+    Ok(id3_metadata)
+}
+
+/// reads a chunk header at `position` and leaves the reader at the start of its data.
+fn read_chunk_header<R: Read + Seek>(
+    reader: &mut R,
+    position: u64,
+    end: u64,
+) -> Result<Option<([u8; 4], u64)>, MetadataError> {
+    if position + CHUNK_HEADER_LEN > end {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(position))?;
+    let mut header = [0u8; CHUNK_HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    let id = [header[0], header[1], header[2], header[3]];
+    let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+    Ok(Some((id, size)))
+}
+
+fn read_info_list<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    metadata: &mut SongMetadata,
+) -> Result<(), MetadataError> {
+    let mut list_type = [0u8; 4];
+    reader.read_exact(&mut list_type)?;
+    if &list_type != b"INFO" {
+        return Ok(());
+    }
+    let mut position = start + 4;
+    while let Some((id, size)) = read_chunk_header(reader, position, end)? {
+        if let Some(field) = field_for(&id) {
+            if size <= MAX_TEXT_VALUE_BYTES && position + CHUNK_HEADER_LEN + size <= end {
+                let text = decode_utf8_or_latin1(&read_bytes(reader, size)?);
+                if let Some(text) = first_value(&text) {
+                    metadata.set(field, text);
+                }
+            }
+        }
+        position += CHUNK_HEADER_LEN + size + (size & 1);
+    }
+    Ok(())
+}
+
+fn field_for(id: &[u8; 4]) -> Option<TagField> {
+    match id {
+        b"INAM" => Some(TagField::Title),
+        b"IART" => Some(TagField::Artist),
+        b"IPRD" => Some(TagField::Album),
+        b"ITRK" | b"IPRT" => Some(TagField::Track),
+        b"ICRD" => Some(TagField::Year),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        data.extend_from_slice(content);
+        if content.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    fn file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let content = [b"WAVE".to_vec(), chunks.concat()].concat();
+        chunk(b"RIFF", &content)
+    }
+
+    fn info_list(values: &[(&[u8; 4], &str)]) -> Vec<u8> {
+        let mut content = b"INFO".to_vec();
+        for (id, text) in values {
+            content.extend(chunk(id, text.as_bytes()));
+        }
+        chunk(b"LIST", &content)
+    }
+
+    fn read_file(data: Vec<u8>) -> Result<SongMetadata, MetadataError> {
+        read(&mut Cursor::new(data), false)
+    }
+
+    #[test]
+    fn info_values_are_read() {
+        let data = file(&[
+            chunk(b"fmt ", &[0; 16]),
+            info_list(&[(b"INAM", "Song"), (b"IART", "Artist"), (b"ITRK", "3")]),
+            chunk(b"data", &[0; 8]),
+        ]);
+        let metadata = read_file(data).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.track_number, Some(3));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = file(&[info_list(&[(b"INAM", "Song")])]);
+        for len in [0, 6, 11] {
+            assert!(read_file(data[..len].to_vec()).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn values_past_the_end_are_left_out() {
+        let data = file(&[info_list(&[(b"INAM", "Song"), (b"IART", "Artist")])]);
+        let metadata = read_file(data[..data.len() - 3].to_vec()).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert!(metadata.artist.is_none());
+    }
+
+    #[test]
+    fn other_files_are_malformed() {
+        let mut data = file(&[]);
+        data[8..12].copy_from_slice(b"AVI ");
+        assert!(matches!(read_file(data), Err(MetadataError::Malformed(_))));
+    }
+
+    #[test]
+    fn huge_chunk_sizes_end_the_file() {
+        let mut data = file(&[chunk(b"junk", &[0; 4]), info_list(&[(b"INAM", "Song")])]);
+        data[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_file(data).unwrap().title.is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::{flac, parse_position, SongMetadata, TagField, MAX_PICTURE_BYTES};
+
+// comment blocks can carry cover art, past this we stop collecting and read what we have
+const MAX_COMMENT_BYTES: u64 = 1024 * 1024;
+
+/// how much of a comment block is worth reading. Base64 makes a picture a third
+/// bigger, this leaves room for that and then some.
+pub fn max_comment_bytes(with_picture: bool) -> u64 {
+    match with_picture {
+        true => MAX_COMMENT_BYTES + 2 * MAX_PICTURE_BYTES,
+        false => MAX_COMMENT_BYTES,
+    }
+}
+
+/// Parses a vorbis comment block, the tag format of flac, ogg vorbis and opus.
+/// A block that was cut short gives back whatever comments fit.
+pub fn parse_comments(data: &[u8], with_picture: bool) -> SongMetadata {
+    let mut metadata = SongMetadata::default();
+    let mut reader = LengthPrefixed { data, position: 0 };
+    // the first string is the name of the encoder
+    if reader.next().is_none() {
+        return metadata;
+    }
+    let Some(comment_count) = reader.read_u32() else {
+        return metadata;
+    };
+    for _ in 0..comment_count {
+        let Some(comment) = reader.next() else {
+            break;
+        };
+        let comment = String::from_utf8_lossy(comment);
+        let Some((key, value)) = comment.split_once('=') else {
+            continue;
+        };
+        // keys are case insensitive and there are a few spellings around for the same thing
+        match key.to_uppercase().as_str() {
+            "TITLE" => metadata.set(TagField::Title, value),
+            "ARTIST" => metadata.set(TagField::Artist, value),
+            "ALBUMARTIST" | "ALBUM ARTIST" | "ALBUM_ARTIST" => {
+                metadata.set(TagField::AlbumArtist, value)
+            }
+            "ALBUM" => metadata.set(TagField::Album, value),
+            "TRACKNUMBER" => metadata.set(TagField::Track, value),
+            "TRACKTOTAL" | "TOTALTRACKS" => metadata.set_track(None, parse_position(value).0),
+            "DISCNUMBER" => metadata.set(TagField::Disc, value),
+            "DISCTOTAL" | "TOTALDISCS" => metadata.set_disc(None, parse_position(value).0),
+            "DATE" | "YEAR" => metadata.set(TagField::Year, value),
+            "METADATA_BLOCK_PICTURE" if with_picture => {
+                let picture = STANDARD
+                    .decode(value.trim())
+                    .ok()
+                    .and_then(|block| flac::parse_picture_block(&block));
+                if let Some(picture) = picture {
+                    metadata.set_picture(picture);
+                }
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+// every string in the block is a little endian u32 length followed by that many bytes
+struct LengthPrefixed<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> LengthPrefixed<'a> {
+    fn read_u32(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.position..self.position + 4)?;
+        self.position += 4;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        let value = self
+            .data
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// a comment block as flac and ogg store it, for their tests too.
+    pub fn comment_block(comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_string(&mut data, "encoder");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            push_string(&mut data, comment);
+        }
+        data
+    }
+
+    fn push_string(data: &mut Vec<u8>, text: &str) {
+        data.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        data.extend_from_slice(text.as_bytes());
+    }
+
+    #[test]
+    fn comments_are_read_whatever_the_key_case() {
+        let metadata = parse_comments(
+            &comment_block(&[
+                "title=Song",
+                "ALBUMARTIST=Artist",
+                "TrackNumber=3",
+                "TRACKTOTAL=12",
+            ]),
+            false,
+        );
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.album_artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.track_number, Some(3));
+        assert_eq!(metadata.track_total, Some(12));
+    }
+
+    #[test]
+    fn truncated_blocks_keep_the_comments_that_fit() {
+        let data = comment_block(&["TITLE=Song", "ALBUM=Album"]);
+        for len in 0..data.len() {
+            parse_comments(&data[..len], false);
+        }
+        let metadata = parse_comments(&data[..data.len() - 1], false);
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert!(metadata.album.is_none());
+    }
+
+    #[test]
+    fn huge_lengths_and_counts_do_not_overflow() {
+        let mut data = comment_block(&[]);
+        // claims u32::MAX comments, the first of them u32::MAX bytes long
+        let count_position = data.len() - 4;
+        data[count_position..].copy_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_comments(&data, false).title.is_none());
+    }
+}
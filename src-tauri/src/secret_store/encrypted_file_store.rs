@@ -0,0 +1,252 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::{SecretStore, SecretStoreError};
+
+const SECRETS_FILE_NAME: &str = "secrets.json";
+const KEY_FILE_NAME: &str = "secrets.key";
+const NONCE_LEN: usize = 12;
+
+/// Secrets encrypted with aes-gcm in a file, with the key in a second file
+/// only the user can read. Anyone who can read both files as the user can
+/// read the secrets, so this keeps them out of backups of the settings and
+/// away from casual eyes rather than safe from the user's own account.
+pub struct EncryptedFileStore {
+    dir: PathBuf,
+    // read, change, write has to happen one at a time
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn secrets_path(&self) -> PathBuf {
+        self.dir.join(SECRETS_FILE_NAME)
+    }
+
+    fn key_path(&self) -> PathBuf {
+        self.dir.join(KEY_FILE_NAME)
+    }
+
+    /// name -> base64 of the nonce followed by the encrypted secret.
+    fn read_secrets(&self) -> Result<BTreeMap<String, String>, SecretStoreError> {
+        let path = self.secrets_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| corrupt(&path, e.to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(file_error(&path, e)),
+        }
+    }
+
+    fn write_secrets(&self, secrets: &BTreeMap<String, String>) -> Result<(), SecretStoreError> {
+        let json =
+            serde_json::to_string_pretty(secrets).expect("a map of strings always serializes");
+        write_private(&self.secrets_path(), json.as_bytes())
+    }
+
+    /// the key is made the first time something is stored.
+    fn cipher(&self, create: bool) -> Result<Option<Aes256Gcm>, SecretStoreError> {
+        let path = self.key_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let key = STANDARD
+                    .decode(text.trim())
+                    .ok()
+                    .filter(|key| key.len() == 32)
+                    .ok_or(corrupt(
+                        &path,
+                        "the key is not 32 bytes of base64".to_string(),
+                    ))?;
+                Ok(Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound && create => {
+                // without the key, whatever was stored before cannot be read any more
+                if self.secrets_path().exists() {
+                    return Err(corrupt(&path, "the key is missing".to_string()));
+                }
+                let key = Aes256Gcm::generate_key(OsRng);
+                write_private(&path, STANDARD.encode(key).as_bytes())?;
+                Ok(Some(Aes256Gcm::new(&key)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(file_error(&path, e)),
+        }
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, name: &str) -> Result<Option<String>, SecretStoreError> {
+        let _guard = self.lock.lock().unwrap();
+        let secrets = self.read_secrets()?;
+        let Some(encrypted) = secrets.get(name) else {
+            return Ok(None);
+        };
+        let path = self.secrets_path();
+        let cipher = self
+            .cipher(false)?
+            .ok_or(corrupt(&self.key_path(), "the key is missing".to_string()))?;
+        let data = STANDARD
+            .decode(encrypted)
+            .ok()
+            .filter(|data| data.len() > NONCE_LEN)
+            .ok_or(corrupt(&path, format!("{name} is not valid base64")))?;
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        // the name is authenticated too, so secrets cannot be swapped around in the file
+        let payload = Payload {
+            msg: ciphertext,
+            aad: name.as_bytes(),
+        };
+        let secret = cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| corrupt(&path, format!("{name} does not decrypt with the key")))?;
+        String::from_utf8(secret)
+            .map(Some)
+            .map_err(|_| corrupt(&path, format!("{name} is not text")))
+    }
+
+    fn set(&self, name: &str, secret: &str) -> Result<(), SecretStoreError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut secrets = self.read_secrets()?;
+        let cipher = self.cipher(true)?.expect("created when missing");
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: secret.as_bytes(),
+            aad: name.as_bytes(),
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .expect("encrypting into memory does not fail");
+        let data = [nonce.as_slice(), &ciphertext].concat();
+        secrets.insert(name.to_string(), STANDARD.encode(data));
+        self.write_secrets(&secrets)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), SecretStoreError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut secrets = self.read_secrets()?;
+        if secrets.remove(name).is_some() {
+            self.write_secrets(&secrets)?;
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("an encrypted file in {}", self.dir.to_string_lossy())
+    }
+}
+
+/// writes then renames so a crash cannot leave half a file, readable only by the user.
+fn write_private(path: &Path, data: &[u8]) -> Result<(), SecretStoreError> {
+    let temp_path = path.with_extension("tmp");
+    let write = || -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut f = options.open(&temp_path)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+        fs::rename(&temp_path, path)
+    };
+    write().map_err(|e| file_error(path, e))
+}
+
+fn file_error(path: &Path, e: io::Error) -> SecretStoreError {
+    SecretStoreError::File(path.to_string_lossy().to_string(), e)
+}
+
+fn corrupt(path: &Path, reason: String) -> SecretStoreError {
+    SecretStoreError::Corrupt(path.to_string_lossy().to_string(), reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(test_name: &str) -> EncryptedFileStore {
+        let dir = std::env::temp_dir().join(format!(
+            "music_uploader_secrets_{test_name}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        EncryptedFileStore::new(dir)
+    }
+
+    #[test]
+    fn secrets_round_trip() {
+        let store = temp_store("round_trip");
+        assert_eq!(store.get("password").unwrap(), None);
+        store.set("password", "hunter2 ✨").unwrap();
+        store.set("other", "something else").unwrap();
+        assert_eq!(
+            store.get("password").unwrap().as_deref(),
+            Some("hunter2 ✨")
+        );
+        // a new store over the same files, like after restarting the app
+        let reopened = EncryptedFileStore::new(store.dir.clone());
+        assert_eq!(
+            reopened.get("other").unwrap().as_deref(),
+            Some("something else")
+        );
+        reopened.set("password", "changed").unwrap();
+        assert_eq!(
+            reopened.get("password").unwrap().as_deref(),
+            Some("changed")
+        );
+        reopened.delete("password").unwrap();
+        reopened.delete("never stored").unwrap();
+        assert_eq!(reopened.get("password").unwrap(), None);
+        assert_eq!(
+            reopened.get("other").unwrap().as_deref(),
+            Some("something else")
+        );
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn secrets_are_not_written_in_the_clear() {
+        let store = temp_store("not_clear");
+        store.set("password", "plaintext-password").unwrap();
+        let file = fs::read_to_string(store.secrets_path()).unwrap();
+        assert!(!file.contains("plaintext-password"));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn swapped_secrets_do_not_decrypt() {
+        let store = temp_store("swapped");
+        store.set("a", "first").unwrap();
+        store.set("b", "second").unwrap();
+        let mut secrets = store.read_secrets().unwrap();
+        let a = secrets.remove("a").unwrap();
+        secrets.insert("b".to_string(), a);
+        store.write_secrets(&secrets).unwrap();
+        assert!(matches!(
+            store.get("b"),
+            Err(SecretStoreError::Corrupt(_, _))
+        ));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
@@ -0,0 +1,44 @@
+use keyring::Entry;
+
+use super::{SecretStore, SecretStoreError};
+
+const SERVICE: &str = "music_uploader_gui";
+
+/// Keychain on macos, credential manager on windows and the secret service on linux.
+pub struct KeyringStore;
+
+impl KeyringStore {
+    /// fails when the platform has no keyring that can be used.
+    pub fn open() -> Result<Self, SecretStoreError> {
+        // reading something that was never stored tells us whether the keyring works at all
+        match Entry::new(SERVICE, "probe")?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(Self),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn get(&self, name: &str) -> Result<Option<String>, SecretStoreError> {
+        match Entry::new(SERVICE, name)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&self, name: &str, secret: &str) -> Result<(), SecretStoreError> {
+        Ok(Entry::new(SERVICE, name)?.set_password(secret)?)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), SecretStoreError> {
+        match Entry::new(SERVICE, name)?.delete_credential() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        "the os keyring".to_string()
+    }
+}
@@ -0,0 +1,52 @@
+mod encrypted_file_store;
+mod keyring_store;
+
+use std::{env, sync::Arc};
+
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+use thiserror::Error;
+
+pub use encrypted_file_store::EncryptedFileStore;
+pub use keyring_store::KeyringStore;
+
+// set to "file" to keep secrets in the encrypted file even when there is a keyring
+const SECRET_STORE_ENV: &str = "MUSIC_UPLOADER_SECRET_STORE";
+const SECRETS_DIR_NAME: &str = "secrets";
+
+/// Somewhere to keep credentials that is not the settings file.
+pub trait SecretStore: Send + Sync {
+    /// None when nothing has been stored under `name`.
+    fn get(&self, name: &str) -> Result<Option<String>, SecretStoreError>;
+    fn set(&self, name: &str, secret: &str) -> Result<(), SecretStoreError>;
+    /// forgetting something that is not there is not an error.
+    fn delete(&self, name: &str) -> Result<(), SecretStoreError>;
+    /// where the secrets end up, for telling the user.
+    fn describe(&self) -> String;
+}
+
+#[derive(Error, Debug)]
+pub enum SecretStoreError {
+    #[error("the os keyring failed: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("failed to use the secrets file {0}: {1}")]
+    File(String, std::io::Error),
+    #[error("the secrets file {0} is damaged: {1}")]
+    Corrupt(String, String),
+}
+
+/// The os keyring when there is one, headless linux often has none, otherwise
+/// an encrypted file in the app's data directory.
+pub fn open_secret_store(app: &AppHandle) -> Result<Arc<dyn SecretStore>, String> {
+    let force_file = env::var(SECRET_STORE_ENV).is_ok_and(|value| value == "file");
+    if !force_file {
+        match KeyringStore::open() {
+            Ok(store) => return Ok(Arc::new(store)),
+            Err(e) => println!("{e}, keeping secrets in an encrypted file instead"),
+        }
+    }
+    let dir = app
+        .path()
+        .resolve(SECRETS_DIR_NAME, BaseDirectory::AppData)
+        .map_err(|e| e.to_string())?;
+    Ok(Arc::new(EncryptedFileStore::new(dir)))
+}
@@ -7,49 +7,310 @@ use std::{
 use serde::{Deserialize, Serialize};
 use tauri::{path::BaseDirectory, AppHandle, Manager};
 
-use crate::uploader_client::MusicUploaderClientConfig;
+use crate::{
+    secret_store::{SecretStore, SecretStoreError},
+    uploader_client::MusicUploaderClientConfig,
+};
 
 const SETTINGS_FILE_NAME: &str = "Settings.toml";
+// where the password was kept before there were profiles
+const LEGACY_PASSWORD_SECRET: &str = "password";
+const DEFAULT_PROFILE_NAME: &str = "default";
 
 const DEFAULT_PART_SIZE_MB: u32 = 5;
+const DEFAULT_PART_CONCURRENCY: u32 = 3;
+const DEFAULT_SONG_CONCURRENCY: u32 = 2;
 const MEGABYTE_BYTES: u32 = 1_000_000;
+const KILOBYTE_BYTES: u64 = 1_000;
 
+/// A server and the account used on it.
 #[derive(Serialize, Deserialize, Clone)]
-pub struct Settings {
+pub struct Profile {
+    // stays the same through renames, the password is stored under it
+    pub id: String,
+    pub name: String,
     pub user: String,
+    pub server_url: String,
+    // lives in the secret store
+    #[serde(skip)]
     password: String,
+    // set when the user cleared the password, saving then removes it from the secret store
+    #[serde(skip)]
+    forget_password: bool,
+    // plain http is only used for this machine unless this is set
+    #[serde(default)]
+    pub allow_insecure_http: bool,
+    // paths of certificate files, for servers with a self signed certificate
+    #[serde(default)]
+    pub ca_certificate: Option<String>,
+    #[serde(default)]
+    pub pinned_certificate: Option<String>,
+}
+
+impl Profile {
+    fn new(name: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            user: String::new(),
+            server_url: String::new(),
+            password: String::new(),
+            forget_password: false,
+            allow_insecure_http: false,
+            ca_certificate: None,
+            pinned_certificate: None,
+        }
+    }
+
+    fn password_secret(&self) -> String {
+        format!("password:{}", self.id)
+    }
+
+    fn token_secret(&self) -> String {
+        format!("token:{}", self.id)
+    }
+
+    /// the password and login token, for when the profile is gone.
+    pub fn forget_secrets(&self, secrets: &dyn SecretStore) -> Result<(), SecretStoreError> {
+        secrets.delete(&self.password_secret())?;
+        secrets.delete(&self.token_secret())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Settings {
+    // settings from before profiles have the account here, it is moved into a profile on load
+    #[serde(default, skip_serializing)]
+    user: Option<String>,
+    #[serde(default, skip_serializing)]
+    password: Option<String>,
+    #[serde(default, skip_serializing)]
+    server_url: Option<String>,
+    // id of the profile used unless an album picks another
+    #[serde(default)]
+    active_profile: String,
     pub valid_extensions: Vec<String>,
-    pub server_url: String,
     pub max_part_size_mb: Option<u32>,
+    pub part_concurrency: Option<u32>,
+    pub song_concurrency: Option<u32>,
+    // unset or 0 means unlimited
+    pub upload_rate_limit_kbps: Option<u32>,
+    // name songs "NN - Title.ext" from their tags instead of keeping their file names
+    pub name_songs_from_tags: Option<bool>,
+    // last so toml writes the tables after the plain values
+    #[serde(default)]
+    profiles: Vec<Profile>,
+}
+
+/// what the gui needs to show and pick profiles, passwords are left out.
+#[derive(Serialize)]
+pub struct ProfileList {
+    pub active_profile: String,
+    pub profiles: Vec<ProfileSummary>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileSummary {
+    pub id: String,
+    pub name: String,
+    pub user: String,
+    pub server_url: String,
 }
 
 impl Settings {
+    /// the config for the active profile.
     pub fn get_config(&self) -> MusicUploaderClientConfig {
+        self.config_for(self.active())
+    }
+
+    /// the config for `profile_id`, or the active profile when there is none.
+    /// None when there is no profile with that id any more.
+    pub fn get_profile_config(
+        &self,
+        profile_id: Option<&String>,
+    ) -> Option<MusicUploaderClientConfig> {
+        match profile_id {
+            Some(profile_id) => self
+                .profile(profile_id)
+                .map(|profile| self.config_for(profile)),
+            None => Some(self.get_config()),
+        }
+    }
+
+    fn config_for(&self, profile: &Profile) -> MusicUploaderClientConfig {
         MusicUploaderClientConfig {
-            user: self.user.clone(),
-            password: self.password.clone(),
-            server_url: self.server_url.clone(),
-            max_upload_part_size: self.max_part_size_mb.unwrap_or(DEFAULT_PART_SIZE_MB) * MEGABYTE_BYTES,
+            user: profile.user.clone(),
+            password: profile.password.clone(),
+            server_url: profile.server_url.clone(),
+            max_upload_part_size: self.max_part_size_mb.unwrap_or(DEFAULT_PART_SIZE_MB)
+                * MEGABYTE_BYTES,
+            part_concurrency: self.get_part_concurrency().max(1) as usize,
+            song_concurrency: self.get_song_concurrency().max(1) as usize,
+            allow_insecure_http: profile.allow_insecure_http,
+            ca_certificate: profile.ca_certificate.clone(),
+            pinned_certificate: profile.pinned_certificate.clone(),
+            token_secret: profile.token_secret(),
+        }
+    }
+
+    /// the profile in use, there always is one once the settings are loaded.
+    pub fn active(&self) -> &Profile {
+        self.profile(&self.active_profile)
+            .unwrap_or_else(|| &self.profiles[0])
+    }
+
+    fn active_mut(&mut self) -> &mut Profile {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.id == self.active_profile)
+            .unwrap_or(0);
+        &mut self.profiles[index]
+    }
+
+    fn profile(&self, profile_id: &str) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.id == profile_id)
+    }
+
+    pub fn list_profiles(&self) -> ProfileList {
+        ProfileList {
+            active_profile: self.active().id.clone(),
+            profiles: self
+                .profiles
+                .iter()
+                .map(|profile| ProfileSummary {
+                    id: profile.id.clone(),
+                    name: profile.name.clone(),
+                    user: profile.user.clone(),
+                    server_url: profile.server_url.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// adds an empty profile, returns its id.
+    pub fn create_profile(&mut self, name: &str) -> Result<String, String> {
+        let name = self.check_profile_name(name, None)?;
+        let profile = Profile::new(name);
+        let profile_id = profile.id.clone();
+        self.profiles.push(profile);
+        Ok(profile_id)
+    }
+
+    pub fn rename_profile(&mut self, profile_id: &str, name: &str) -> Result<(), String> {
+        let name = self.check_profile_name(name, Some(profile_id))?;
+        let profile = self
+            .profiles
+            .iter_mut()
+            .find(|profile| profile.id == profile_id)
+            .ok_or(no_profile(profile_id))?;
+        profile.name = name;
+        Ok(())
+    }
+
+    /// returns the removed profile so its password can be forgotten too.
+    pub fn delete_profile(&mut self, profile_id: &str) -> Result<Profile, String> {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.id == profile_id)
+            .ok_or(no_profile(profile_id))?;
+        if self.profiles.len() == 1 {
+            return Err("The last profile cannot be deleted".to_string());
+        }
+        let profile = self.profiles.remove(index);
+        if self.active_profile == profile_id {
+            self.active_profile = self.profiles[0].id.clone();
         }
+        Ok(profile)
+    }
+
+    pub fn switch_profile(&mut self, profile_id: &str) -> Result<(), String> {
+        let profile = self.profile(profile_id).ok_or(no_profile(profile_id))?;
+        self.active_profile = profile.id.clone();
+        Ok(())
+    }
+
+    fn check_profile_name(&self, name: &str, profile_id: Option<&str>) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("A profile needs a name".to_string());
+        }
+        let taken = self
+            .profiles
+            .iter()
+            .any(|profile| profile.name == name && Some(profile.id.as_str()) != profile_id);
+        if taken {
+            return Err(format!("There already is a profile called {name}"));
+        }
+        Ok(name.to_string())
     }
 
     pub fn get_user_editable_settings(&self) -> UserEditableSettings {
+        let profile = self.active();
         UserEditableSettings {
-            user: self.user.clone(),
-            password: self.password.clone(),
-            server_url: self.server_url.clone(),
-            max_part_size_mb: self.max_part_size_mb.unwrap_or(DEFAULT_PART_SIZE_MB)
+            user: profile.user.clone(),
+            password: profile.password.clone(),
+            server_url: profile.server_url.clone(),
+            allow_insecure_http: profile.allow_insecure_http,
+            ca_certificate: profile.ca_certificate.clone().unwrap_or_default(),
+            pinned_certificate: profile.pinned_certificate.clone().unwrap_or_default(),
+            max_part_size_mb: self.max_part_size_mb.unwrap_or(DEFAULT_PART_SIZE_MB),
+            part_concurrency: self.get_part_concurrency(),
+            song_concurrency: self.get_song_concurrency(),
+            upload_rate_limit_kbps: self.upload_rate_limit_kbps.unwrap_or(0),
+            name_songs_from_tags: self.name_songs_from_tags(),
         }
     }
 
+    pub fn get_upload_rate_limit_bytes_per_second(&self) -> Option<u64> {
+        self.upload_rate_limit_kbps
+            .filter(|kbps| *kbps > 0)
+            .map(|kbps| kbps as u64 * KILOBYTE_BYTES)
+    }
+
+    pub fn name_songs_from_tags(&self) -> bool {
+        self.name_songs_from_tags.unwrap_or(false)
+    }
+
+    fn get_part_concurrency(&self) -> u32 {
+        self.part_concurrency.unwrap_or(DEFAULT_PART_CONCURRENCY)
+    }
+
+    fn get_song_concurrency(&self) -> u32 {
+        self.song_concurrency.unwrap_or(DEFAULT_SONG_CONCURRENCY)
+    }
+
+    /// the account fields go to the active profile, the rest applies to all of them.
     pub fn update(&mut self, user_editable_settings: UserEditableSettings) {
-        self.user = user_editable_settings.user;
-        self.password = user_editable_settings.password;
-        self.server_url = user_editable_settings.server_url;
+        let profile = self.active_mut();
+        profile.user = user_editable_settings.user;
+        // empty only means forget it when there was a password to forget, it is also
+        // empty when reading it failed at startup
+        let password = user_editable_settings.password;
+        profile.forget_password =
+            password.is_empty() && (profile.forget_password || !profile.password.is_empty());
+        profile.password = password;
+        profile.server_url = user_editable_settings.server_url;
+        profile.allow_insecure_http = user_editable_settings.allow_insecure_http;
+        profile.ca_certificate = non_empty(user_editable_settings.ca_certificate);
+        profile.pinned_certificate = non_empty(user_editable_settings.pinned_certificate);
         self.max_part_size_mb = Some(user_editable_settings.max_part_size_mb);
+        self.part_concurrency = Some(user_editable_settings.part_concurrency);
+        self.song_concurrency = Some(user_editable_settings.song_concurrency);
+        self.upload_rate_limit_kbps = Some(user_editable_settings.upload_rate_limit_kbps);
+        self.name_songs_from_tags = Some(user_editable_settings.name_songs_from_tags);
     }
 
-    pub fn save_settings(&self, app: &AppHandle) -> Result<String, String> {
+    pub fn save_settings(
+        &self,
+        app: &AppHandle,
+        secrets: &dyn SecretStore,
+    ) -> Result<String, String> {
+        // stored first, so a failure cannot leave a password nowhere
+        self.store_passwords(secrets)?;
         let settings_path = get_settings_path(app)?;
         let stringified_settings = toml::to_string(self).map_err(|e| e.to_string())?;
         let mut f = File::create(&settings_path).map_err(|e| e.to_string())?;
@@ -61,6 +322,29 @@ impl Settings {
             path_string(&settings_path)
         ))
     }
+
+    /// An empty password is left alone unless the user cleared it, it may only be
+    /// empty because reading it failed at startup.
+    fn store_passwords(&self, secrets: &dyn SecretStore) -> Result<(), String> {
+        for profile in &self.profiles {
+            let (result, action) = match (profile.password.is_empty(), profile.forget_password) {
+                (false, _) => (
+                    secrets.set(&profile.password_secret(), &profile.password),
+                    "store",
+                ),
+                (true, true) => (secrets.delete(&profile.password_secret()), "forget"),
+                (true, false) => continue,
+            };
+            result.map_err(|e| {
+                format!(
+                    "Failed to {action} the password of {} in {}: {e}",
+                    profile.name,
+                    secrets.describe()
+                )
+            })?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -68,7 +352,15 @@ pub struct UserEditableSettings {
     pub user: String,
     pub password: String,
     pub server_url: String,
+    pub allow_insecure_http: bool,
+    // empty when not used
+    pub ca_certificate: String,
+    pub pinned_certificate: String,
     pub max_part_size_mb: u32,
+    pub part_concurrency: u32,
+    pub song_concurrency: u32,
+    pub upload_rate_limit_kbps: u32,
+    pub name_songs_from_tags: bool,
 }
 
 pub struct LoadSettingsResult {
@@ -79,7 +371,10 @@ pub struct LoadSettingsResult {
 // i dislike the current mechanism i have for passing messages to the user
 // however, the gui listener for rust log events has not been added by the
 // point that the tuari app is being configured.
-pub fn load_settings(app: &AppHandle) -> Result<LoadSettingsResult, String> {
+pub fn load_settings(
+    app: &AppHandle,
+    secrets: &dyn SecretStore,
+) -> Result<LoadSettingsResult, String> {
     let settings_path = get_settings_path(app)?;
     let mut success_message = format!("looking for settings at ({})", path_string(&settings_path));
     // likely first time running, create the settings directory and copy the default settings over.
@@ -115,17 +410,85 @@ pub fn load_settings(app: &AppHandle) -> Result<LoadSettingsResult, String> {
             path_string(&settings_path)
         )
     })?;
-    toml::from_str::<Settings>(&file_text)
-        .map(|x| LoadSettingsResult {
-            settings: x,
-            startup_message: success_message,
-        })
-        .map_err(|_| {
+    let mut settings = toml::from_str::<Settings>(&file_text).map_err(|_| {
+        format!(
+            "Failed to parse contents of {}, probably typo",
+            path_string(&settings_path)
+        )
+    })?;
+    // a locked keyring should not keep the app from starting, uploads will just fail auth
+    for profile in settings.profiles.iter_mut() {
+        match secrets.get(&profile.password_secret()) {
+            Ok(password) => profile.password = password.unwrap_or_default(),
+            Err(e) => {
+                success_message = format!(
+                    "{success_message}\nFailed to read the password of {} from {}: {e}",
+                    profile.name,
+                    secrets.describe()
+                )
+            }
+        }
+    }
+    if settings.profiles.is_empty() {
+        success_message = format!(
+            "{success_message}\n{}",
+            move_into_profile(app, secrets, &mut settings)
+        );
+    }
+    if settings.profile(&settings.active_profile).is_none() {
+        settings.active_profile = settings.profiles[0].id.clone();
+    }
+    Ok(LoadSettingsResult {
+        settings,
+        startup_message: success_message,
+    })
+}
+
+/// Settings from before profiles have their account at the top level, and
+/// older still the password too. They become the first profile, and saving
+/// moves the password into the secret store and out of the file.
+fn move_into_profile(
+    app: &AppHandle,
+    secrets: &dyn SecretStore,
+    settings: &mut Settings,
+) -> String {
+    let mut profile = Profile::new(DEFAULT_PROFILE_NAME.to_string());
+    profile.user = settings.user.take().unwrap_or_default();
+    profile.server_url = settings.server_url.take().unwrap_or_default();
+    profile.password = match settings.password.take() {
+        Some(password) => password,
+        None => secrets
+            .get(LEGACY_PASSWORD_SECRET)
+            .ok()
+            .flatten()
+            .unwrap_or_default(),
+    };
+    settings.active_profile = profile.id.clone();
+    let name = profile.name.clone();
+    settings.profiles.push(profile);
+    match settings.save_settings(app, secrets) {
+        Ok(_) => {
+            // already copied to where the profile keeps it
+            let _ = secrets.delete(LEGACY_PASSWORD_SECRET);
             format!(
-                "Failed to parse contents of {}, probably typo",
-                path_string(&settings_path)
+                "Moved your account into the profile {name}, its password is kept in {}",
+                secrets.describe()
             )
-        })
+        }
+        Err(e) => format!("{e}, the settings file is left as it was for now"),
+    }
+}
+
+fn non_empty(path: String) -> Option<String> {
+    let path = path.trim();
+    match path.is_empty() {
+        true => None,
+        false => Some(path.to_string()),
+    }
+}
+
+fn no_profile(profile_id: &str) -> String {
+    format!("There is no profile with id {profile_id}")
 }
 
 fn get_settings_path(app: &AppHandle) -> Result<PathBuf, String> {
@@ -137,3 +500,83 @@ fn get_settings_path(app: &AppHandle) -> Result<PathBuf, String> {
 fn path_string(path: &Path) -> String {
     path.to_str().unwrap_or("<no path>").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::secret_store::EncryptedFileStore;
+
+    use super::*;
+
+    fn temp_store(test_name: &str) -> (EncryptedFileStore, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "music_uploader_settings_{test_name}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        (EncryptedFileStore::new(dir.clone()), dir)
+    }
+
+    fn settings() -> Settings {
+        let mut settings = toml::from_str::<Settings>("valid_extensions = [\"flac\"]").unwrap();
+        let profile = Profile::new("Home".to_string());
+        settings.active_profile = profile.id.clone();
+        settings.profiles.push(profile);
+        settings
+    }
+
+    fn with_password(settings: &mut Settings, password: &str) {
+        let mut editable = settings.get_user_editable_settings();
+        editable.password = password.to_string();
+        settings.update(editable);
+    }
+
+    fn stored_password(settings: &Settings, secrets: &dyn SecretStore) -> Option<String> {
+        secrets.get(&settings.active().password_secret()).unwrap()
+    }
+
+    #[test]
+    fn clearing_the_password_forgets_it() {
+        let (secrets, dir) = temp_store("clear");
+        let mut settings = settings();
+        with_password(&mut settings, "hunter2");
+        settings.store_passwords(&secrets).unwrap();
+        assert_eq!(
+            stored_password(&settings, &secrets).as_deref(),
+            Some("hunter2")
+        );
+
+        with_password(&mut settings, "");
+        settings.store_passwords(&secrets).unwrap();
+        assert_eq!(stored_password(&settings, &secrets), None);
+        // saving again without typing one keeps it forgotten
+        with_password(&mut settings, "");
+        settings.store_passwords(&secrets).unwrap();
+        assert_eq!(stored_password(&settings, &secrets), None);
+
+        with_password(&mut settings, "new");
+        settings.store_passwords(&secrets).unwrap();
+        assert_eq!(stored_password(&settings, &secrets).as_deref(), Some("new"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn passwords_that_failed_to_load_are_kept() {
+        let (secrets, dir) = temp_store("failed_load");
+        let settings_with_password = {
+            let mut settings = settings();
+            with_password(&mut settings, "hunter2");
+            settings.store_passwords(&secrets).unwrap();
+            settings
+        };
+        // like after startup when the keyring could not be read, the password is empty
+        let mut settings = settings_with_password.clone();
+        settings.profiles[0].password = String::new();
+        with_password(&mut settings, "");
+        settings.store_passwords(&secrets).unwrap();
+        assert_eq!(
+            stored_password(&settings, &secrets).as_deref(),
+            Some("hunter2")
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    uploader_client::MusicUploaderClientError,
+    zip_entry::{ZipEntry, ZipEntryReader},
+    Song,
+};
+
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// A song on disk, or inside a zip on disk, that is read lazily so that memory
+/// use while hashing or uploading stays bounded by the size of a single part
+/// instead of the file.
+pub struct SongFile {
+    name: String,
+    source: Source,
+    stamp: FileStamp,
+}
+
+/// What tells us a song changed without reading it again: its size, and when
+/// the file was last modified or, for a song in a zip, the CRC the zip keeps.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size_bytes: u64,
+    pub version: u64,
+}
+
+enum Source {
+    File(String),
+    // parts are read in order, so they share one reader instead of each
+    // decompressing the entry from its start
+    ZipEntry(ZipEntry, Mutex<Option<ZipEntryReader>>),
+}
+
+impl SongFile {
+    pub fn open(song: &Song) -> Result<Self, MusicUploaderClientError> {
+        match &song.entry {
+            Some(entry) => Self::open_entry(&song.path, entry),
+            None => Self::open_path(&song.path),
+        }
+    }
+
+    pub fn open_entry(
+        archive_path: &String,
+        entry: &String,
+    ) -> Result<Self, MusicUploaderClientError> {
+        let name = Song::key_for(archive_path, Some(entry));
+        let entry = ZipEntry::find(archive_path, entry).map_err(|e| read_error(&name, e))?;
+        Ok(Self {
+            name,
+            stamp: FileStamp {
+                size_bytes: entry.size(),
+                version: u64::from(entry.crc32()),
+            },
+            source: Source::ZipEntry(entry, Mutex::new(None)),
+        })
+    }
+
+    pub fn open_path(path: &String) -> Result<Self, MusicUploaderClientError> {
+        let metadata = fs::metadata(path).map_err(|e| read_error(path, e))?;
+        // without modification times only the size is left to go on
+        let modified_nanos = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(Self {
+            name: path.to_string(),
+            source: Source::File(path.to_string()),
+            stamp: FileStamp {
+                size_bytes: metadata.len(),
+                version: modified_nanos,
+            },
+        })
+    }
+
+    /// the path of the song, with the entry name appended for songs in a zip.
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.stamp.size_bytes
+    }
+
+    pub fn stamp(&self) -> FileStamp {
+        self.stamp
+    }
+
+    /// a reader over the whole song, positioned at its start.
+    pub fn reader(&self) -> io::Result<BufReader<Box<dyn ReadSeek>>> {
+        let reader: Box<dyn ReadSeek> = match &self.source {
+            Source::File(path) => Box::new(File::open(path)?),
+            Source::ZipEntry(entry, _) => Box::new(entry.reader()?),
+        };
+        Ok(BufReader::new(reader))
+    }
+
+    pub fn read_error(&self, e: io::Error) -> MusicUploaderClientError {
+        read_error(&self.name, e)
+    }
+
+    /// sha256 of the whole file, computed by streaming it through the hasher.
+    pub fn hash(&self) -> Result<String, MusicUploaderClientError> {
+        let mut hasher = Sha256::new();
+        self.reader()
+            .and_then(|mut reader| io::copy(&mut reader, &mut hasher))
+            .map_err(|e| self.read_error(e))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    pub fn read_all(&self) -> Result<Vec<u8>, MusicUploaderClientError> {
+        let mut data = Vec::with_capacity(self.size_bytes() as usize);
+        self.reader()
+            .and_then(|mut reader| reader.read_to_end(&mut data))
+            .map_err(|e| self.read_error(e))?;
+        Ok(data)
+    }
+
+    /// reads at most `len` bytes starting at `start`.
+    pub fn read_range(&self, start: u64, len: usize) -> Result<Vec<u8>, MusicUploaderClientError> {
+        let end = u64::min(self.size_bytes(), start + len as u64);
+        if end <= start {
+            return Ok(Vec::new());
+        }
+        let mut data = vec![0u8; (end - start) as usize];
+        let mut read = || -> io::Result<()> {
+            match &self.source {
+                Source::File(path) => {
+                    let mut f = File::open(path)?;
+                    f.seek(SeekFrom::Start(start))?;
+                    f.read_exact(&mut data)
+                }
+                Source::ZipEntry(entry, cursor) => {
+                    let mut cursor = cursor.lock().unwrap();
+                    let reader = match cursor.as_mut() {
+                        Some(reader) => reader,
+                        None => cursor.insert(entry.reader()?),
+                    };
+                    reader.seek(SeekFrom::Start(start))?;
+                    reader.read_exact(&mut data)
+                }
+            }
+        };
+        read().map_err(|e| self.read_error(e))?;
+        Ok(data)
+    }
+}
+
+/// Remembers song hashes for as long as the app runs, so checking albums
+/// against each other does not read every song again each time. A hash is
+/// only trusted while the song keeps the stamp it had when it was hashed.
+#[derive(Default)]
+pub struct SongHashes {
+    // song key -> stamp when hashed and the hash
+    hashes: Mutex<HashMap<String, (FileStamp, String)>>,
+}
+
+impl SongHashes {
+    pub fn hash(&self, file: &SongFile) -> Result<String, MusicUploaderClientError> {
+        if let Some((stamp, hash)) = self.hashes.lock().unwrap().get(file.name()) {
+            if *stamp == file.stamp() {
+                return Ok(hash.clone());
+            }
+        }
+        // hashed without holding the lock, it can take a while for big files
+        let hash = file.hash()?;
+        self.hashes
+            .lock()
+            .unwrap()
+            .insert(file.name().clone(), (file.stamp(), hash.clone()));
+        Ok(hash)
+    }
+}
+
+fn read_error(path: &String, e: std::io::Error) -> MusicUploaderClientError {
+    MusicUploaderClientError::FileReadError(path.to_string(), Box::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[test]
+    fn changed_songs_of_the_same_size_are_hashed_again() {
+        let path = std::env::temp_dir().join(format!(
+            "music_uploader_song_hashes_{}.flac",
+            std::process::id()
+        ));
+        let path_text = path.to_string_lossy().to_string();
+        let hashes = SongHashes::default();
+        let write = |content: &[u8], modified: SystemTime| {
+            fs::write(&path, content).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_modified(modified))
+                .unwrap();
+            hashes
+                .hash(&SongFile::open_path(&path_text).unwrap())
+                .unwrap()
+        };
+        let before = SystemTime::now() - Duration::from_secs(60);
+        let first = write(b"first", before);
+        assert_eq!(write(b"first", before), first);
+        assert_ne!(write(b"other", SystemTime::now()), first);
+        let _ = fs::remove_file(&path);
+    }
+}
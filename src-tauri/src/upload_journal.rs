@@ -0,0 +1,492 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::{cover_art::CoverArtSource, song_file::FileStamp, Song};
+
+const JOURNAL_FILE_NAME: &str = "UploadJournal.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JournalAlbum {
+    pub journal_id: String,
+    pub album_name: String,
+    pub artist: String,
+    pub songs: Vec<JournalSong>,
+    #[serde(default)]
+    pub cover_art: Option<CoverArtSource>,
+    // None uploads to whichever profile is active
+    #[serde(default)]
+    pub profile_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JournalSong {
+    pub song_name: String,
+    pub path: String,
+    #[serde(default)]
+    pub entry: Option<String>,
+    pub complete: bool,
+    pub hash: Option<String>,
+    // everything recorded about the song is about the file as it was at this stamp,
+    // once the file changes the song starts over
+    #[serde(default, alias = "hashed_stamp")]
+    pub stamp: Option<FileStamp>,
+    // the server's list of received parts decides what is sent again, these say how
+    // far the upload got and are dropped when the server hands out a new key
+    #[serde(default)]
+    pub multipart_key: Option<String>,
+    #[serde(default)]
+    pub part_size: Option<u32>,
+    #[serde(default)]
+    pub completed_parts: Vec<u32>,
+}
+
+impl JournalSong {
+    fn new(song_name: String, path: String, entry: Option<String>) -> Self {
+        Self {
+            song_name,
+            path,
+            entry,
+            complete: false,
+            hash: None,
+            stamp: None,
+            multipart_key: None,
+            part_size: None,
+            completed_parts: Vec::new(),
+        }
+    }
+
+    pub fn key(&self) -> String {
+        Song::key_for(&self.path, self.entry.as_ref())
+    }
+}
+
+/// Remembers every album upload that has not finished yet, so that it can be
+/// picked back up after the app is closed. Every change is written straight to
+/// disk since the app can go away at any moment.
+pub struct UploadJournal {
+    path: Option<PathBuf>,
+    albums: BTreeMap<String, JournalAlbum>,
+}
+
+impl UploadJournal {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let path = app
+            .path()
+            .resolve(JOURNAL_FILE_NAME, BaseDirectory::AppData)
+            .map_err(|e| e.to_string())?;
+        if !fs::exists(&path).unwrap_or(false) {
+            return Ok(Self {
+                path: Some(path),
+                albums: BTreeMap::new(),
+            });
+        }
+        let mut file_text = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut file_text))
+            .map_err(|e| format!("Failed to read upload journal: {e}"))?;
+        Ok(Self {
+            path: Some(path),
+            albums: parse(&file_text)?,
+        })
+    }
+
+    /// a journal that is never written anywhere, used when the real one cannot be loaded.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            albums: BTreeMap::new(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<JournalAlbum> {
+        self.albums.values().cloned().collect()
+    }
+
+    pub fn get(&self, journal_id: &str) -> Option<JournalAlbum> {
+        self.albums.get(journal_id).cloned()
+    }
+
+    /// starts tracking an album upload, returns the id it is tracked under. Uploading
+    /// the exact same album again continues the existing entry instead of adding another.
+    pub fn start_album(
+        &mut self,
+        album_name: &String,
+        artist: &String,
+        songs: &[Song],
+        cover_art: &Option<CoverArtSource>,
+        profile_id: &Option<String>,
+    ) -> String {
+        // parts sent to one server mean nothing to another, so the profile has to match too
+        let existing = self.albums.values_mut().find(|album| {
+            &album.album_name == album_name
+                && &album.artist == artist
+                && &album.profile_id == profile_id
+                && album.songs.len() == songs.len()
+                && songs
+                    .iter()
+                    .all(|song| album.songs.iter().any(|s| s.key() == song.key()))
+        });
+        if let Some(album) = existing {
+            // the cover is not part of what makes it the same album, the latest choice wins
+            album.cover_art = cover_art.clone();
+            let journal_id = album.journal_id.clone();
+            self.save();
+            return journal_id;
+        }
+        let journal_id = uuid::Uuid::new_v4().to_string();
+        let album = JournalAlbum {
+            journal_id: journal_id.clone(),
+            album_name: album_name.to_string(),
+            artist: artist.to_string(),
+            songs: songs
+                .iter()
+                .map(|song| {
+                    JournalSong::new(
+                        song.song_name.clone(),
+                        song.path.clone(),
+                        song.entry.clone(),
+                    )
+                })
+                .collect(),
+            cover_art: cover_art.clone(),
+            profile_id: profile_id.clone(),
+        };
+        self.albums.insert(journal_id.clone(), album);
+        self.save();
+        journal_id
+    }
+
+    pub fn finish_album(&mut self, journal_id: &str) {
+        if self.albums.remove(journal_id).is_some() {
+            self.save();
+        }
+    }
+
+    /// only while the song is still the file that was uploaded.
+    pub fn is_song_complete(&self, journal_id: &str, song_key: &String, stamp: FileStamp) -> bool {
+        self.get_song(journal_id, song_key, stamp)
+            .map(|song| song.complete)
+            .unwrap_or(false)
+    }
+
+    pub fn get_hash(
+        &self,
+        journal_id: &str,
+        song_key: &String,
+        stamp: FileStamp,
+    ) -> Option<String> {
+        self.get_song(journal_id, song_key, stamp)?.hash.clone()
+    }
+
+    pub fn record_hash(
+        &mut self,
+        journal_id: &str,
+        song_key: &String,
+        stamp: FileStamp,
+        hash: &String,
+    ) {
+        self.update_song(journal_id, song_key, stamp, |song| {
+            song.hash = Some(hash.to_string());
+        });
+    }
+
+    pub fn record_declared(
+        &mut self,
+        journal_id: &str,
+        song_key: &String,
+        stamp: FileStamp,
+        multipart_key: &String,
+        part_size: u32,
+        received_parts: &[u32],
+    ) {
+        self.update_song(journal_id, song_key, stamp, |song| {
+            if song.multipart_key.as_ref() != Some(multipart_key)
+                || song.part_size != Some(part_size)
+            {
+                song.completed_parts.clear();
+            }
+            song.multipart_key = Some(multipart_key.to_string());
+            song.part_size = Some(part_size);
+            for index in received_parts {
+                if !song.completed_parts.contains(index) {
+                    song.completed_parts.push(*index);
+                }
+            }
+        });
+    }
+
+    pub fn record_part(
+        &mut self,
+        journal_id: &str,
+        song_key: &String,
+        stamp: FileStamp,
+        index: u32,
+    ) {
+        self.update_song(journal_id, song_key, stamp, |song| {
+            if !song.completed_parts.contains(&index) {
+                song.completed_parts.push(index);
+            }
+        });
+    }
+
+    pub fn record_song_complete(&mut self, journal_id: &str, song_key: &String, stamp: FileStamp) {
+        self.update_song(journal_id, song_key, stamp, |song| song.complete = true);
+    }
+
+    /// the song's entry, unless the file has changed since it was written.
+    fn get_song(
+        &self,
+        journal_id: &str,
+        song_key: &String,
+        stamp: FileStamp,
+    ) -> Option<&JournalSong> {
+        self.albums
+            .get(journal_id)?
+            .songs
+            .iter()
+            .find(|song| &song.key() == song_key)
+            .filter(|song| song.stamp == Some(stamp))
+    }
+
+    fn update_song(
+        &mut self,
+        journal_id: &str,
+        song_key: &String,
+        stamp: FileStamp,
+        update: impl FnOnce(&mut JournalSong),
+    ) {
+        let song = self
+            .albums
+            .get_mut(journal_id)
+            .and_then(|album| album.songs.iter_mut().find(|song| &song.key() == song_key));
+        if let Some(song) = song {
+            if song.stamp != Some(stamp) {
+                let (song_name, path, entry) = (
+                    std::mem::take(&mut song.song_name),
+                    std::mem::take(&mut song.path),
+                    song.entry.take(),
+                );
+                *song = JournalSong {
+                    stamp: Some(stamp),
+                    ..JournalSong::new(song_name, path, entry)
+                };
+            }
+            update(song);
+            self.save();
+        }
+    }
+
+    // failing to write the journal only costs us the ability to resume, so it is not fatal.
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = self.try_save(path) {
+            println!("failed to save upload journal: {e}");
+        }
+    }
+
+    fn try_save(&self, path: &PathBuf) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(&self.albums).map_err(|e| e.to_string())?;
+        // write then rename so a crash mid write cannot leave a half written journal
+        let temp_path = path.with_extension("json.tmp");
+        let mut f = File::create(&temp_path).map_err(|e| e.to_string())?;
+        f.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+        fs::rename(&temp_path, path).map_err(|e| e.to_string())
+    }
+}
+
+// fields added since a journal was written are filled in with their defaults
+fn parse(text: &str) -> Result<BTreeMap<String, JournalAlbum>, String> {
+    serde_json::from_str(text).map_err(|e| format!("Failed to parse upload journal: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn songs() -> Vec<Song> {
+        ["one.flac", "two.flac"]
+            .iter()
+            .map(|name| Song {
+                song_name: name.to_string(),
+                path: format!("/music/{name}"),
+                entry: None,
+            })
+            .collect()
+    }
+
+    fn stamp(version: u64) -> FileStamp {
+        FileStamp {
+            size_bytes: 1000,
+            version,
+        }
+    }
+
+    fn start(journal: &mut UploadJournal, songs: &[Song], profile_id: Option<&str>) -> String {
+        let profile_id = profile_id.map(|id| id.to_string());
+        journal.start_album(
+            &"Album".to_string(),
+            &"Artist".to_string(),
+            songs,
+            &None,
+            &profile_id,
+        )
+    }
+
+    #[test]
+    fn the_same_album_continues_its_entry() {
+        let mut journal = UploadJournal::in_memory();
+        let songs = songs();
+        let journal_id = start(&mut journal, &songs, None);
+        let reversed: Vec<Song> = songs.iter().rev().cloned().collect();
+        assert_eq!(start(&mut journal, &reversed, None), journal_id);
+        assert_eq!(journal.list().len(), 1);
+    }
+
+    #[test]
+    fn other_songs_or_servers_are_other_albums() {
+        let mut journal = UploadJournal::in_memory();
+        let songs = songs();
+        let journal_id = start(&mut journal, &songs, None);
+        assert_ne!(start(&mut journal, &songs[..1], None), journal_id);
+        assert_ne!(start(&mut journal, &songs, Some("other")), journal_id);
+        assert_eq!(journal.list().len(), 3);
+    }
+
+    #[test]
+    fn hashes_are_only_reused_for_the_same_file() {
+        let mut journal = UploadJournal::in_memory();
+        let journal_id = start(&mut journal, &songs(), None);
+        let key = songs()[0].key();
+        journal.record_hash(&journal_id, &key, stamp(1), &"hash".to_string());
+        assert_eq!(
+            journal.get_hash(&journal_id, &key, stamp(1)).as_deref(),
+            Some("hash")
+        );
+        assert_eq!(journal.get_hash(&journal_id, &key, stamp(2)), None);
+        let resized = FileStamp {
+            size_bytes: 999,
+            version: 1,
+        };
+        assert_eq!(journal.get_hash(&journal_id, &key, resized), None);
+    }
+
+    #[test]
+    fn changed_files_start_over() {
+        let mut journal = UploadJournal::in_memory();
+        let journal_id = start(&mut journal, &songs(), None);
+        let key = songs()[0].key();
+        journal.record_hash(&journal_id, &key, stamp(1), &"hash".to_string());
+        journal.record_declared(&journal_id, &key, stamp(1), &"key".to_string(), 100, &[0]);
+        journal.record_part(&journal_id, &key, stamp(1), 1);
+        journal.record_song_complete(&journal_id, &key, stamp(1));
+        assert!(journal.is_song_complete(&journal_id, &key, stamp(1)));
+        assert!(!journal.is_song_complete(&journal_id, &key, stamp(2)));
+
+        journal.record_part(&journal_id, &key, stamp(2), 3);
+        let song = journal.get(&journal_id).unwrap().songs[0].clone();
+        assert!(!song.complete);
+        assert_eq!(song.hash, None);
+        assert_eq!(song.multipart_key, None);
+        assert_eq!(song.completed_parts, vec![3]);
+        assert!(!journal.is_song_complete(&journal_id, &key, stamp(1)));
+    }
+
+    #[test]
+    fn parts_are_kept_until_the_server_hands_out_a_new_key() {
+        let mut journal = UploadJournal::in_memory();
+        let journal_id = start(&mut journal, &songs(), None);
+        let key = songs()[0].key();
+        let parts = |journal: &UploadJournal| {
+            journal.get(&journal_id).unwrap().songs[0]
+                .completed_parts
+                .clone()
+        };
+        journal.record_declared(&journal_id, &key, stamp(1), &"a".to_string(), 100, &[0, 1]);
+        journal.record_part(&journal_id, &key, stamp(1), 2);
+        journal.record_part(&journal_id, &key, stamp(1), 2);
+        assert_eq!(parts(&journal), vec![0, 1, 2]);
+        journal.record_declared(&journal_id, &key, stamp(1), &"a".to_string(), 100, &[0]);
+        assert_eq!(parts(&journal), vec![0, 1, 2]);
+        journal.record_declared(&journal_id, &key, stamp(1), &"b".to_string(), 100, &[]);
+        assert!(parts(&journal).is_empty());
+    }
+
+    #[test]
+    fn completed_songs_stay_complete_until_the_album_finishes() {
+        let mut journal = UploadJournal::in_memory();
+        let songs = songs();
+        let journal_id = start(&mut journal, &songs, None);
+        let (first, second) = (songs[0].key(), songs[1].key());
+        journal.record_song_complete(&journal_id, &first, stamp(1));
+        assert!(journal.is_song_complete(&journal_id, &first, stamp(1)));
+        assert!(!journal.is_song_complete(&journal_id, &second, stamp(1)));
+        journal.finish_album(&journal_id);
+        assert!(journal.get(&journal_id).is_none());
+        assert!(!journal.is_song_complete(&journal_id, &first, stamp(1)));
+        assert_ne!(start(&mut journal, &songs, None), journal_id);
+    }
+
+    #[test]
+    fn journals_from_before_new_fields_load() {
+        let text = r#"{
+            "old": {
+                "journal_id": "old",
+                "album_name": "Album",
+                "artist": "Artist",
+                "songs": [{
+                    "song_name": "one.flac",
+                    "path": "/music/one.flac",
+                    "complete": true,
+                    "hash": "hash",
+                    "hashed_size_bytes": 1000
+                }]
+            }
+        }"#;
+        let albums = parse(text).unwrap();
+        let album = &albums["old"];
+        assert!(album.cover_art.is_none());
+        assert!(album.profile_id.is_none());
+        let song = &album.songs[0];
+        assert_eq!(song.entry, None);
+        assert!(song.stamp.is_none());
+        assert!(song.completed_parts.is_empty());
+
+        // nothing says the song is still the file that was uploaded, so it is checked again
+        let journal = UploadJournal { path: None, albums };
+        let key = songs()[0].key();
+        assert!(!journal.is_song_complete("old", &key, stamp(1)));
+        assert_eq!(journal.get_hash("old", &key, stamp(1)), None);
+    }
+
+    #[test]
+    fn hashed_stamps_load_as_the_song_stamp() {
+        let text = r#"{
+            "a": {
+                "journal_id": "a",
+                "album_name": "Album",
+                "artist": "Artist",
+                "songs": [{
+                    "song_name": "one.flac",
+                    "path": "/music/one.flac",
+                    "complete": false,
+                    "hash": "hash",
+                    "hashed_stamp": { "size_bytes": 1000, "version": 1 }
+                }]
+            }
+        }"#;
+        let albums = parse(text).unwrap();
+        assert!(albums["a"].songs[0].stamp == Some(stamp(1)));
+    }
+}
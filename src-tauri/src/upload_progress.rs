@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use reqwest::Body;
+
+use crate::{gui_logger::GuiLogger, uploader_client::RateLimiter};
+
+const PROGRESS_CHUNK_BYTES: usize = 64 * 1024;
+const MIN_EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Tracks how many bytes of a song have been handed to the http client and
+/// reports it to the gui at most every `MIN_EMIT_INTERVAL`.
+///
+/// Bytes are counted per slot (the part index, or 0 for single request uploads)
+/// so a retried part overwrites its own count instead of being counted twice.
+pub struct UploadProgress {
+    logger: GuiLogger,
+    album_id: String,
+    file: String,
+    total_bytes: u64,
+    started: Instant,
+    state: Mutex<ProgressState>,
+}
+
+struct ProgressState {
+    sent_by_slot: HashMap<u32, u64>,
+    already_uploaded_bytes: u64,
+    last_emit: Option<Instant>,
+}
+
+impl UploadProgress {
+    pub fn new(logger: GuiLogger, album_id: &String, file: &String, total_bytes: u64) -> Arc<Self> {
+        Arc::new(Self {
+            logger,
+            album_id: album_id.to_string(),
+            file: file.to_string(),
+            total_bytes,
+            started: Instant::now(),
+            state: Mutex::new(ProgressState {
+                sent_by_slot: HashMap::new(),
+                already_uploaded_bytes: 0,
+                last_emit: None,
+            }),
+        })
+    }
+
+    /// bytes the server already had before this upload started, they count towards
+    /// progress but not towards throughput.
+    pub fn add_already_uploaded(&self, bytes: u64) {
+        self.state.lock().unwrap().already_uploaded_bytes += bytes;
+    }
+
+    fn set_slot_sent(&self, slot: u32, sent: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.sent_by_slot.insert(slot, sent);
+        let now = Instant::now();
+        let should_emit = match state.last_emit {
+            Some(last_emit) => now.duration_since(last_emit) >= MIN_EMIT_INTERVAL,
+            None => true,
+        };
+        if should_emit {
+            state.last_emit = Some(now);
+            self.emit(&state);
+        }
+    }
+
+    /// always emits, used once the song is done so the gui does not get stuck below 100%.
+    pub fn finish(&self) {
+        let state = self.state.lock().unwrap();
+        self.emit(&state);
+    }
+
+    fn emit(&self, state: &ProgressState) {
+        let sent_this_session: u64 = state.sent_by_slot.values().sum();
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let bytes_per_second = match elapsed > 0.0 {
+            true => sent_this_session as f64 / elapsed,
+            false => 0.0,
+        };
+        self.logger.file_progress(
+            &self.album_id,
+            &self.file,
+            u64::min(
+                self.total_bytes,
+                state.already_uploaded_bytes + sent_this_session,
+            ),
+            self.total_bytes,
+            bytes_per_second,
+        );
+    }
+}
+
+/// wraps `data` in a request body that reports to `progress` as it is streamed out,
+/// and that only streams as fast as `rate_limiter` allows.
+pub fn tracked_body(
+    data: Bytes,
+    progress: Arc<UploadProgress>,
+    slot: u32,
+    rate_limiter: Arc<RateLimiter>,
+) -> Body {
+    let chunks = (0..data.len())
+        .step_by(PROGRESS_CHUNK_BYTES)
+        .map(move |start| data.slice(start..usize::min(data.len(), start + PROGRESS_CHUNK_BYTES)))
+        .collect::<Vec<_>>();
+    let mut sent = 0;
+    progress.set_slot_sent(slot, sent);
+    let body_stream = stream::iter(chunks)
+        .then(move |chunk| {
+            let rate_limiter = rate_limiter.clone();
+            async move {
+                rate_limiter.acquire(chunk.len() as u64).await;
+                chunk
+            }
+        })
+        .map(move |chunk| {
+            sent += chunk.len() as u64;
+            progress.set_slot_sent(slot, sent);
+            Ok::<Bytes, io::Error>(chunk)
+        });
+    Body::wrap_stream(body_stream)
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cover_art::CoverArtSource, Song};
+
+/// An album to upload, as the gui sends it to be uploaded or queued.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueuedAlbum {
+    pub album_id: String,
+    pub album_name: String,
+    pub artist: String,
+    pub songs: Vec<Song>,
+    // set when this continues an upload from the upload journal
+    pub journal_id: Option<String>,
+    pub cover_art: Option<CoverArtSource>,
+    pub profile_id: Option<String>,
+}
+
+/// what the gui needs to draw the queue, sent with every queue change.
+#[derive(Serialize, Clone)]
+pub struct UploadQueueSnapshot {
+    pub uploading: Option<QueuedAlbum>,
+    pub queued: Vec<QueuedAlbum>,
+}
+
+/// Albums waiting to be uploaded, in the order they will be uploaded, plus the
+/// album currently being uploaded. Albums are uploaded one at a time.
+#[derive(Default)]
+pub struct UploadQueue {
+    queued: VecDeque<QueuedAlbum>,
+    uploading: Option<QueuedAlbum>,
+}
+
+impl UploadQueue {
+    /// returns false if the album is already queued or uploading.
+    pub fn enqueue(&mut self, album: QueuedAlbum) -> bool {
+        if self.contains(&album.album_id) {
+            return false;
+        }
+        self.queued.push_back(album);
+        true
+    }
+
+    /// only albums that are still waiting can be removed, returns false otherwise.
+    pub fn remove(&mut self, album_id: &str) -> bool {
+        match self.position(album_id) {
+            Some(position) => {
+                self.queued.remove(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// moves a waiting album to `index` in the queue, clamped to the end of the queue.
+    pub fn move_to(&mut self, album_id: &str, index: usize) -> bool {
+        let Some(album) = self.position(album_id).and_then(|p| self.queued.remove(p)) else {
+            return false;
+        };
+        let index = usize::min(index, self.queued.len());
+        self.queued.insert(index, album);
+        true
+    }
+
+    pub fn start_next(&mut self) -> Option<QueuedAlbum> {
+        let next = self.queued.pop_front()?;
+        self.uploading = Some(next.clone());
+        Some(next)
+    }
+
+    pub fn finish_current(&mut self) {
+        self.uploading = None;
+    }
+
+    pub fn snapshot(&self) -> UploadQueueSnapshot {
+        UploadQueueSnapshot {
+            uploading: self.uploading.clone(),
+            queued: self.queued.iter().cloned().collect(),
+        }
+    }
+
+    fn contains(&self, album_id: &str) -> bool {
+        let is_uploading = self
+            .uploading
+            .as_ref()
+            .is_some_and(|album| album.album_id == album_id);
+        is_uploading || self.position(album_id).is_some()
+    }
+
+    fn position(&self, album_id: &str) -> Option<usize> {
+        self.queued
+            .iter()
+            .position(|album| album.album_id == album_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album(album_id: &str) -> QueuedAlbum {
+        QueuedAlbum {
+            album_id: album_id.to_string(),
+            album_name: format!("Album {album_id}"),
+            artist: "Artist".to_string(),
+            songs: Vec::new(),
+            journal_id: None,
+            cover_art: None,
+            profile_id: None,
+        }
+    }
+
+    fn queue(album_ids: &[&str]) -> UploadQueue {
+        let mut queue = UploadQueue::default();
+        for album_id in album_ids {
+            assert!(queue.enqueue(album(album_id)));
+        }
+        queue
+    }
+
+    fn queued_ids(queue: &UploadQueue) -> Vec<String> {
+        queue
+            .snapshot()
+            .queued
+            .into_iter()
+            .map(|album| album.album_id)
+            .collect()
+    }
+
+    fn uploading_id(queue: &UploadQueue) -> Option<String> {
+        queue.snapshot().uploading.map(|album| album.album_id)
+    }
+
+    #[test]
+    fn albums_are_uploaded_in_the_order_they_were_queued() {
+        let mut queue = queue(&["a", "b", "c"]);
+        assert_eq!(queued_ids(&queue), ["a", "b", "c"]);
+        assert_eq!(queue.start_next().unwrap().album_id, "a");
+        assert_eq!(uploading_id(&queue).as_deref(), Some("a"));
+        assert_eq!(queued_ids(&queue), ["b", "c"]);
+        queue.finish_current();
+        assert_eq!(uploading_id(&queue), None);
+        assert_eq!(queue.start_next().unwrap().album_id, "b");
+    }
+
+    #[test]
+    fn an_empty_queue_has_nothing_to_start() {
+        let mut queue = UploadQueue::default();
+        assert!(queue.start_next().is_none());
+        assert!(uploading_id(&queue).is_none());
+    }
+
+    #[test]
+    fn albums_are_only_queued_once() {
+        let mut queue = queue(&["a", "b"]);
+        assert!(!queue.enqueue(album("b")));
+        queue.start_next();
+        // still uploading, so queuing it again would upload it twice
+        assert!(!queue.enqueue(album("a")));
+        assert_eq!(queued_ids(&queue), ["b"]);
+        queue.finish_current();
+        assert!(queue.enqueue(album("a")));
+        assert_eq!(queued_ids(&queue), ["b", "a"]);
+    }
+
+    #[test]
+    fn only_waiting_albums_can_be_removed() {
+        let mut queue = queue(&["a", "b", "c"]);
+        queue.start_next();
+        assert!(!queue.remove("a"));
+        assert!(queue.remove("b"));
+        assert!(!queue.remove("b"));
+        assert!(!queue.remove("unknown"));
+        assert_eq!(queued_ids(&queue), ["c"]);
+        assert_eq!(uploading_id(&queue).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn albums_move_to_the_given_index() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        assert!(queue.move_to("d", 0));
+        assert_eq!(queued_ids(&queue), ["d", "a", "b", "c"]);
+        assert!(queue.move_to("d", 2));
+        assert_eq!(queued_ids(&queue), ["a", "b", "d", "c"]);
+        assert!(queue.move_to("a", 3));
+        assert_eq!(queued_ids(&queue), ["b", "d", "c", "a"]);
+    }
+
+    #[test]
+    fn moving_to_the_same_position_changes_nothing() {
+        let mut queue = queue(&["a", "b", "c"]);
+        assert!(queue.move_to("b", 1));
+        assert_eq!(queued_ids(&queue), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn moving_past_the_end_puts_the_album_last() {
+        let mut queue = queue(&["a", "b", "c"]);
+        assert!(queue.move_to("a", 3));
+        assert_eq!(queued_ids(&queue), ["b", "c", "a"]);
+        assert!(queue.move_to("b", usize::MAX));
+        assert_eq!(queued_ids(&queue), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn only_waiting_albums_can_be_moved() {
+        let mut queue = queue(&["a", "b"]);
+        queue.start_next();
+        assert!(!queue.move_to("a", 0));
+        assert!(!queue.move_to("unknown", 0));
+        assert_eq!(queued_ids(&queue), ["b"]);
+        assert_eq!(uploading_id(&queue).as_deref(), Some("a"));
+    }
+}
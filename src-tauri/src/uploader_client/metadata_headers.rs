@@ -0,0 +1,225 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{header::HeaderMap, RequestBuilder};
+
+// tells the server every metadata header of the request is percent encoded utf-8.
+// servers that decode it send it back on their connection check to say so
+pub const ENCODING_HEADER: &str = "metadataencoding";
+pub const PERCENT_ENCODING: &str = "percent";
+
+// everything but the unreserved characters of RFC 3986
+const ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Header values holding names people typed, like the album, artist and file
+/// name. Header values can only safely be visible ascii, so when any value is
+/// not, all of them are percent encoded and the request says so with
+/// `ENCODING_HEADER`. Plain ascii names are sent as they are, which keeps them
+/// working with servers that do not know about the encoding. Those servers
+/// would take encoded names literally, so encoded ones are only for servers
+/// that say they decode them.
+pub struct MetadataHeaders {
+    values: Vec<(&'static str, String)>,
+    // the first value that could not be sent as it is, when there is one
+    needs_encoding: Option<String>,
+}
+
+impl MetadataHeaders {
+    pub fn new(values: &[(&'static str, &str)]) -> Self {
+        let needs_encoding = values
+            .iter()
+            .find(|(_, value)| needs_encoding(value))
+            .map(|(_, value)| value.to_string());
+        let values = values
+            .iter()
+            .map(|(name, value)| {
+                let value = match needs_encoding.is_some() {
+                    true => utf8_percent_encode(value, ENCODE_SET).to_string(),
+                    false => value.to_string(),
+                };
+                (*name, value)
+            })
+            .collect();
+        Self {
+            values,
+            needs_encoding,
+        }
+    }
+
+    /// the value that made these headers need encoding, None when they are plain ascii.
+    pub fn needs_encoding(&self) -> Option<&String> {
+        self.needs_encoding.as_ref()
+    }
+
+    pub fn apply(&self, request_builder: RequestBuilder) -> RequestBuilder {
+        let request_builder = match self.needs_encoding.is_some() {
+            true => request_builder.header(ENCODING_HEADER, PERCENT_ENCODING),
+            false => request_builder,
+        };
+        self.values
+            .iter()
+            .fold(request_builder, |request_builder, (name, value)| {
+                request_builder.header(*name, value)
+            })
+    }
+}
+
+/// whether the headers of a server's response say it decodes percent encoded metadata.
+pub fn supports_encoding(response_headers: &HeaderMap) -> bool {
+    response_headers
+        .get_all(ENCODING_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|encoding| encoding.trim().eq_ignore_ascii_case(PERCENT_ENCODING))
+}
+
+// whitespace at either end would be stripped off the header on the way
+fn needs_encoding(value: &str) -> bool {
+    let is_visible_ascii = |c: char| c == ' ' || c.is_ascii_graphic();
+    !value.chars().all(is_visible_ascii) || value.starts_with(' ') || value.ends_with(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use percent_encoding::percent_decode_str;
+    use reqwest::{
+        header::{HeaderName, HeaderValue},
+        Client,
+    };
+
+    use super::*;
+
+    const MULTILINGUAL_NAMES: [&str; 10] = [
+        "東京事変 - 群青日和",
+        "ハイスイノナサ",
+        "방탄소년단",
+        "Кино - Группа крови",
+        "Αρχαία Ελληνικά",
+        "فيروز",
+        "Sigur Rós - ( )",
+        "Mötley Crüe",
+        // the same é, composed and decomposed
+        "Beyonc\u{e9}",
+        "Beyonce\u{301} 🐝",
+    ];
+
+    fn build(headers: &MetadataHeaders) -> reqwest::Request {
+        headers
+            .apply(Client::new().post("http://localhost/upload"))
+            .build()
+            .unwrap()
+    }
+
+    fn header<'a>(request: &'a reqwest::Request, name: &str) -> Option<&'a str> {
+        request
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    fn decode(value: &str) -> String {
+        percent_decode_str(value).decode_utf8().unwrap().to_string()
+    }
+
+    #[test]
+    fn multilingual_names_round_trip() {
+        for name in MULTILINGUAL_NAMES {
+            let file_name = format!("01 - {name}.flac");
+            let headers =
+                MetadataHeaders::new(&[("file", &file_name), ("album", name), ("artist", name)]);
+            let request = build(&headers);
+            assert_eq!(header(&request, ENCODING_HEADER), Some(PERCENT_ENCODING));
+            assert_eq!(decode(header(&request, "file").unwrap()), file_name);
+            assert_eq!(decode(header(&request, "album").unwrap()), name);
+            assert_eq!(decode(header(&request, "artist").unwrap()), name);
+        }
+    }
+
+    #[test]
+    fn encoded_values_are_valid_header_values() {
+        for name in MULTILINGUAL_NAMES {
+            let headers = MetadataHeaders::new(&[("album", name)]);
+            let (_, value) = &headers.values[0];
+            assert!(value.chars().all(|c| c.is_ascii_graphic()), "{value}");
+            assert!(HeaderValue::from_str(value).is_ok());
+        }
+    }
+
+    #[test]
+    fn ascii_names_are_sent_as_they_are() {
+        let headers = MetadataHeaders::new(&[
+            ("file", "01 - 100% Pure Love.mp3"),
+            ("album", "Greatest Hits (Remastered)"),
+            ("artist", "AC_DC"),
+        ]);
+        let request = build(&headers);
+        assert!(headers.needs_encoding().is_none());
+        assert_eq!(header(&request, ENCODING_HEADER), None);
+        assert_eq!(header(&request, "file"), Some("01 - 100% Pure Love.mp3"));
+        assert_eq!(
+            header(&request, "album"),
+            Some("Greatest Hits (Remastered)")
+        );
+        assert_eq!(header(&request, "artist"), Some("AC_DC"));
+    }
+
+    #[test]
+    fn one_non_ascii_value_encodes_all_of_them() {
+        let headers = MetadataHeaders::new(&[
+            ("file", "01 - 100% Pure Love.mp3"),
+            ("album", "Café Tacvba"),
+        ]);
+        let request = build(&headers);
+        assert_eq!(
+            headers.needs_encoding().map(String::as_str),
+            Some("Café Tacvba")
+        );
+        assert_eq!(header(&request, ENCODING_HEADER), Some(PERCENT_ENCODING));
+        assert_eq!(
+            header(&request, "file"),
+            Some("01%20-%20100%25%20Pure%20Love.mp3")
+        );
+        assert_eq!(
+            decode(header(&request, "file").unwrap()),
+            "01 - 100% Pure Love.mp3"
+        );
+        assert_eq!(decode(header(&request, "album").unwrap()), "Café Tacvba");
+    }
+
+    #[test]
+    fn surrounding_whitespace_survives() {
+        let headers = MetadataHeaders::new(&[("album", " padded ")]);
+        let request = build(&headers);
+        assert_eq!(header(&request, ENCODING_HEADER), Some(PERCENT_ENCODING));
+        assert_eq!(decode(header(&request, "album").unwrap()), " padded ");
+    }
+
+    #[test]
+    fn control_characters_are_encoded() {
+        let headers = MetadataHeaders::new(&[("album", "line\r\nbreak\ttab")]);
+        let request = build(&headers);
+        assert_eq!(header(&request, "album"), Some("line%0D%0Abreak%09tab"));
+    }
+
+    #[test]
+    fn servers_say_they_decode_names_with_the_encoding_header() {
+        let response_headers = |values: &[&str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append(
+                    HeaderName::from_static(ENCODING_HEADER),
+                    HeaderValue::from_str(value).unwrap(),
+                );
+            }
+            headers
+        };
+        assert!(supports_encoding(&response_headers(&["percent"])));
+        assert!(supports_encoding(&response_headers(&["base64, Percent"])));
+        assert!(supports_encoding(&response_headers(&["base64", "percent"])));
+        assert!(!supports_encoding(&response_headers(&[])));
+        assert!(!supports_encoding(&response_headers(&["base64"])));
+    }
+}
@@ -0,0 +1,612 @@
+mod metadata_headers;
+mod rate_limiter;
+mod retry;
+mod session;
+mod transport_security;
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use metadata_headers::{supports_encoding, MetadataHeaders};
+use music_uploader_server::model::{from_json, AlbumSearchResponse};
+pub use rate_limiter::RateLimiter;
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use retry::{classify, RetryPolicy, Retryability};
+use serde::{Deserialize, Serialize};
+use session::{Auth, Sessions};
+use thiserror::Error;
+use transport_security::{ClientKey, REQUEST_TIMEOUT};
+
+use crate::{
+    cover_art::CoverArt,
+    gui_logger::GuiLogger,
+    secret_store::SecretStore,
+    upload_progress::{tracked_body, UploadProgress},
+};
+
+pub struct MusicUploaderClientConfig {
+    pub user: String,
+    pub password: String,
+    pub server_url: String,
+    pub max_upload_part_size: u32,
+    pub part_concurrency: usize,
+    pub song_concurrency: usize,
+    // plain http is only used for this machine unless this is set
+    pub allow_insecure_http: bool,
+    // PEM file of extra CAs to trust on top of the system ones
+    pub ca_certificate: Option<String>,
+    // PEM or DER file of the only certificate the server may present
+    pub pinned_certificate: Option<String>,
+    // the login token is kept under this name in the secret store
+    pub token_secret: String,
+}
+
+impl MusicUploaderClientConfig {
+    fn build_url(&self, route: &str) -> String {
+        format!("{}/{}", self.server_url, route)
+    }
+
+    fn apply_auth(&self, auth: &Auth, request_builder: RequestBuilder) -> RequestBuilder {
+        match auth {
+            Auth::Token(token) => request_builder.bearer_auth(token),
+            Auth::Basic => self.apply_basic_auth(request_builder),
+        }
+    }
+
+    fn apply_basic_auth(&self, request_builder: RequestBuilder) -> RequestBuilder {
+        request_builder.basic_auth(self.user.clone(), Some(self.password.clone()))
+    }
+}
+
+pub struct MusicUploaderClient {
+    // one per way of connecting, so profiles with different certificates get their own
+    clients: Mutex<HashMap<ClientKey, Client>>,
+    logger: GuiLogger,
+    retry_policy: RetryPolicy,
+    // shared by every upload so the limit applies to all of them together
+    rate_limiter: Arc<RateLimiter>,
+    sessions: Sessions,
+    // server url -> whether it decodes percent encoded names, asked once per server
+    metadata_encodings: Mutex<HashMap<String, bool>>,
+}
+
+impl MusicUploaderClient {
+    pub fn new(logger: GuiLogger, secrets: Arc<dyn SecretStore>) -> Self {
+        MusicUploaderClient {
+            clients: Mutex::new(HashMap::new()),
+            sessions: Sessions::new(secrets),
+            logger,
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            metadata_encodings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_upload_rate_limit(&self, bytes_per_second: Option<u64>) {
+        self.rate_limiter.set_rate(bytes_per_second);
+    }
+
+    pub async fn check_conn(
+        &self,
+        config: &MusicUploaderClientConfig,
+    ) -> Result<String, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let result = self
+            .send_with_retry("connection check", || client.get(config.build_url("conn")))
+            .await;
+        handle_string_response(result).await
+    }
+
+    pub async fn check_auth(
+        &self,
+        config: &MusicUploaderClientConfig,
+    ) -> Result<String, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let result = self
+            .send_authorized("auth check", &client, config, || {
+                client.get(config.build_url("auth"))
+            })
+            .await?;
+        handle_string_response(result).await
+    }
+
+    pub async fn send_song(
+        &self,
+        config: &MusicUploaderClientConfig,
+        file: Vec<u8>,
+        artist: &str,
+        album: &str,
+        song_file_name: &String,
+        progress: &Arc<UploadProgress>,
+    ) -> Result<String, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let metadata = MetadataHeaders::new(&[
+            ("file", song_file_name),
+            ("album", album),
+            ("artist", artist),
+        ]);
+        self.check_names(&client, config, &metadata).await?;
+        self.log("hashing".to_string());
+        let song_hash = sha256::digest(&file);
+        let file = Bytes::from(file);
+        self.log("sending request".to_string());
+        let result = self
+            .send_authorized(
+                &format!("upload of {song_file_name}"),
+                &client,
+                config,
+                || {
+                    metadata
+                        .apply(client.post(config.build_url("upload")))
+                        .header("hash", &song_hash)
+                        .header(CONTENT_LENGTH, file.len())
+                        .timeout(self.upload_timeout(config, file.len()))
+                        .body(tracked_body(
+                            file.clone(),
+                            progress.clone(),
+                            0,
+                            self.rate_limiter.clone(),
+                        ))
+                },
+            )
+            .await?;
+        handle_string_response(result).await
+    }
+
+    /// sends the album's cover, the server saves it next to the songs where plex looks for it.
+    pub async fn upload_cover_art(
+        &self,
+        config: &MusicUploaderClientConfig,
+        cover_art: &CoverArt,
+        artist: &str,
+        album: &str,
+    ) -> Result<String, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let hash = sha256::digest(&cover_art.data);
+        let file_name = format!("cover.{}", cover_art.format.extension());
+        let image = Bytes::from(cover_art.data.clone());
+        let metadata =
+            MetadataHeaders::new(&[("file", &file_name), ("album", album), ("artist", artist)]);
+        self.check_names(&client, config, &metadata).await?;
+        let result = self
+            .send_authorized("cover art upload", &client, config, || {
+                metadata
+                    .apply(client.post(config.build_url("uploadcoverart")))
+                    .header("hash", &hash)
+                    .header(CONTENT_TYPE, cover_art.format.mime_type())
+                    .header(CONTENT_LENGTH, image.len())
+                    .body(image.clone())
+            })
+            .await?;
+        handle_string_response(result).await
+    }
+
+    pub async fn trigger_scan(
+        &self,
+        config: &MusicUploaderClientConfig,
+    ) -> Result<String, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let result = self
+            .send_authorized("scan trigger", &client, config, || {
+                client.post(config.build_url("triggerscan"))
+            })
+            .await?;
+        handle_string_response(result).await
+    }
+
+    pub async fn album_search(
+        &self,
+        config: &MusicUploaderClientConfig,
+        album: String,
+    ) -> Result<AlbumSearchResponse, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let metadata = MetadataHeaders::new(&[("album", &album)]);
+        self.check_names(&client, config, &metadata).await?;
+        let result = self
+            .send_authorized("album search", &client, config, || {
+                metadata.apply(client.get(config.build_url("albumsearch")))
+            })
+            .await?;
+        handle_response(result).await
+    }
+
+    /// asks the server which of `hashes` it already has a song for.
+    pub async fn check_hashes(
+        &self,
+        config: &MusicUploaderClientConfig,
+        hashes: &[String],
+    ) -> Result<Vec<String>, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let body = serde_json::to_string(&CheckHashesRequest { hashes })
+            .expect("a list of strings always serializes");
+        let result = self
+            .send_authorized("song check", &client, config, || {
+                client
+                    .post(config.build_url("checkhashes"))
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body.clone())
+            })
+            .await?;
+        handle_response::<CheckHashesResponse>(result)
+            .await
+            .map(|response| response.present)
+    }
+
+    pub async fn declare_upload(
+        &self,
+        config: &MusicUploaderClientConfig,
+        hash: &String,
+        artist: &str,
+        album: &str,
+        song_file_name: &String,
+        part_size_bytes: u32,
+        declared_size_bytes: u64,
+    ) -> Result<DeclaredUpload, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let metadata = MetadataHeaders::new(&[
+            ("file", song_file_name),
+            ("album", album),
+            ("artist", artist),
+        ]);
+        self.check_names(&client, config, &metadata).await?;
+        let result = self
+            .send_authorized(
+                &format!("upload declaration for {song_file_name}"),
+                &client,
+                config,
+                || {
+                    metadata
+                        .apply(client.post(config.build_url("declareupload")))
+                        .header("hash", hash)
+                        .header("partsize", part_size_bytes)
+                        .header("declaredsize", declared_size_bytes)
+                },
+            )
+            .await?;
+        handle_response::<DeclaredUpload>(result).await
+    }
+
+    pub async fn upload_part(
+        &self,
+        config: &MusicUploaderClientConfig,
+        key: &String,
+        index: u32,
+        file: Vec<u8>,
+        progress: &Arc<UploadProgress>,
+    ) -> Result<String, MusicUploaderClientError> {
+        let client = self.http_client(config)?;
+        let hash = sha256::digest(&file);
+        let file = Bytes::from(file);
+        let result = self
+            .send_authorized(&format!("upload of part {index}"), &client, config, || {
+                client
+                    .post(config.build_url("uploadpart"))
+                    .header("key", key)
+                    .header("hash", &hash)
+                    .header("index", index)
+                    .header(CONTENT_LENGTH, file.len())
+                    .timeout(self.upload_timeout(config, file.len()))
+                    .body(tracked_body(
+                        file.clone(),
+                        progress.clone(),
+                        index,
+                        self.rate_limiter.clone(),
+                    ))
+            })
+            .await?;
+        handle_string_response(result).await
+    }
+
+    /// the request timeout for sending `bytes`, stretched by how long the rate limit
+    /// holds them back. every song and part being sent at once shares the limit, so
+    /// this assumes all of them are going. Worked out again for every attempt so a
+    /// changed limit is picked up by retries.
+    fn upload_timeout(&self, config: &MusicUploaderClientConfig, bytes: usize) -> Duration {
+        let streams = (config.song_concurrency * config.part_concurrency).max(1) as u64;
+        REQUEST_TIMEOUT + self.rate_limiter.time_to_send(bytes as u64 * streams)
+    }
+
+    /// sends the request built by `build_request`, building and sending it again with
+    /// jittered exponential backoff for as long as the failure looks transient.
+    async fn send_with_retry(
+        &self,
+        description: &str,
+        build_request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        let max_attempts = self.retry_policy.max_attempts;
+        let mut attempt = 1;
+        loop {
+            let result = build_request().send().await;
+            match classify(&result) {
+                Retryability::Retry {
+                    reason,
+                    retry_after,
+                } if attempt < max_attempts => {
+                    let delay = retry_after.unwrap_or_else(|| self.retry_policy.backoff(attempt));
+                    self.log(format!(
+                        "{description} failed ({reason}), retrying in {:.1}s (attempt {} of {max_attempts})",
+                        delay.as_secs_f32(),
+                        attempt + 1,
+                    ));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    /// sends with however the server wants requests authenticated, logging in
+    /// again once when the token has stopped working.
+    async fn send_authorized(
+        &self,
+        description: &str,
+        client: &Client,
+        config: &MusicUploaderClientConfig,
+        build_request: impl Fn() -> RequestBuilder,
+    ) -> Result<Result<Response, reqwest::Error>, MusicUploaderClientError> {
+        let auth = match self.sessions.get(config) {
+            Some(auth) => auth,
+            None => self.login(client, config, None).await?,
+        };
+        let result = self
+            .send_with_retry(description, || config.apply_auth(&auth, build_request()))
+            .await;
+        let is_unauthorized = result
+            .as_ref()
+            .is_ok_and(|response| response.status() == StatusCode::UNAUTHORIZED);
+        if !is_unauthorized || auth == Auth::Basic {
+            return Ok(result);
+        }
+        self.log(format!(
+            "the login token was refused during {description}, logging in again"
+        ));
+        let auth = self.login(client, config, Some(&auth)).await?;
+        Ok(self
+            .send_with_retry(description, || config.apply_auth(&auth, build_request()))
+            .await)
+    }
+
+    /// trades the user and password for a token, or settles for sending them with
+    /// every request when the server has no logins. `refused` is the auth the
+    /// server just turned down, when another request already replaced it that
+    /// replacement is used instead of logging in again.
+    async fn login(
+        &self,
+        client: &Client,
+        config: &MusicUploaderClientConfig,
+        refused: Option<&Auth>,
+    ) -> Result<Auth, MusicUploaderClientError> {
+        let _login = self.sessions.lock_login().await;
+        match self.sessions.get(config) {
+            Some(auth) if Some(&auth) != refused => return Ok(auth),
+            Some(_) => self.sessions.forget(config),
+            None => {}
+        }
+        let result = self
+            .send_with_retry("login", || {
+                config.apply_basic_auth(client.post(config.build_url("login")))
+            })
+            .await;
+        // older servers do not have the route at all
+        let has_no_logins = result.as_ref().is_ok_and(|response| {
+            matches!(
+                response.status(),
+                StatusCode::NOT_FOUND
+                    | StatusCode::METHOD_NOT_ALLOWED
+                    | StatusCode::NOT_IMPLEMENTED
+            )
+        });
+        let auth = match has_no_logins {
+            true => {
+                self.log(format!(
+                    "{} does not hand out login tokens, the password is sent with every request",
+                    config.server_url
+                ));
+                Auth::Basic
+            }
+            false => Auth::Token(handle_response::<LoginResponse>(result).await?.token),
+        };
+        self.sessions.remember(config, &auth);
+        Ok(auth)
+    }
+
+    /// fails for names that need encoding when the server would not decode them,
+    /// it would otherwise save the song under the encoded name.
+    async fn check_names(
+        &self,
+        client: &Client,
+        config: &MusicUploaderClientConfig,
+        metadata: &MetadataHeaders,
+    ) -> Result<(), MusicUploaderClientError> {
+        let Some(name) = metadata.needs_encoding() else {
+            return Ok(());
+        };
+        match self.supports_metadata_encoding(client, config).await? {
+            true => Ok(()),
+            false => Err(MusicUploaderClientError::UnsupportedName(name.clone())),
+        }
+    }
+
+    async fn supports_metadata_encoding(
+        &self,
+        client: &Client,
+        config: &MusicUploaderClientConfig,
+    ) -> Result<bool, MusicUploaderClientError> {
+        let known = self
+            .metadata_encodings
+            .lock()
+            .unwrap()
+            .get(&config.server_url)
+            .copied();
+        if let Some(supported) = known {
+            return Ok(supported);
+        }
+        let response = self
+            .send_with_retry("connection check", || client.get(config.build_url("conn")))
+            .await
+            .map_err(|e| MusicUploaderClientError::ErrorFromServer(e.to_string()))?;
+        let supported = supports_encoding(response.headers());
+        self.metadata_encodings
+            .lock()
+            .unwrap()
+            .insert(config.server_url.clone(), supported);
+        Ok(supported)
+    }
+
+    /// the client to reach the config's server with, fails when the config would
+    /// send credentials somewhere they should not go. Clients for certificate files
+    /// that have changed since are dropped, so there is at most one per config.
+    fn http_client(
+        &self,
+        config: &MusicUploaderClientConfig,
+    ) -> Result<Client, MusicUploaderClientError> {
+        let key = ClientKey::for_config(config)?;
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = key.build_client()?;
+        clients.retain(|old_key, _| !old_key.is_replaced_by(&key));
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    fn log(&self, text: String) {
+        self.logger.log(text);
+    }
+}
+
+#[derive(Serialize)]
+struct CheckHashesRequest<'a> {
+    hashes: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct CheckHashesResponse {
+    // the requested hashes the server has a song for, the others it does not
+    present: Vec<String>,
+}
+
+/// The server's declare upload response, parsed here instead of into the server
+/// crate's type so sizes over 4 GB and part indices over 255 fit. Older servers
+/// send the same shape with narrower numbers, which parse into these just fine.
+#[derive(Deserialize)]
+pub enum DeclaredUpload {
+    Complete,
+    Incomplete {
+        key: String,
+        declared_size: u64,
+        part_size: u32,
+        received_parts: Vec<u32>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum MusicUploaderClientError {
+    #[error("unhappy response: ({0}) {1}")]
+    UnhappyResponse(u16, String),
+    #[error("Recieved an error from the server: {0}")]
+    ErrorFromServer(String),
+    #[error("Local settings is misconfigured: {0}")]
+    BadConfig(String),
+    #[error("Failed to read the file {0} because: {1}")]
+    FileReadError(String, Box<io::Error>),
+    #[error("Failed to upload album: {0}")]
+    AlbumUploadFailure(String),
+    #[error("Failed to parse server response: {0}")]
+    ParseServerResponseFailure(String),
+    #[error("Upload was cancelled")]
+    Cancelled,
+    #[error("{0} is not a valid audio file: {1}")]
+    InvalidAudioFile(String, String),
+    #[error("{0} cannot be used as cover art: {1}")]
+    InvalidCoverArt(String, String),
+    #[error("The server only takes plain ascii names and \"{0}\" is not one, rename it or update the server")]
+    UnsupportedName(String),
+}
+
+async fn handle_response<T: for<'a> Deserialize<'a>>(
+    result: Result<Response, reqwest::Error>,
+) -> Result<T, MusicUploaderClientError> {
+    handle_string_response(result).await.and_then(|s| {
+        from_json::<T>(&s)
+            .map_err(|e| MusicUploaderClientError::ParseServerResponseFailure(e.to_string()))
+    })
+}
+
+async fn handle_string_response(
+    result: Result<Response, reqwest::Error>,
+) -> Result<String, MusicUploaderClientError> {
+    match result {
+        Ok(response) => match response.status().is_success() {
+            true => Ok(get_body(response).await),
+            false => Err(MusicUploaderClientError::UnhappyResponse(
+                response.status().as_u16(),
+                get_body(response).await,
+            )),
+        },
+        Err(e) => {
+            println!("sending failed, if source error is disconnected this is likely an authorization issue");
+            println!("sending error: {:?}", e);
+            Err(MusicUploaderClientError::ErrorFromServer(e.to_string()))
+        }
+    }
+}
+
+async fn get_body(response: Response) -> String {
+    response
+        .text()
+        .await
+        .unwrap_or_else(|_| "<no body>".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declared_uploads_over_4gb_parse() {
+        let json = r#"{"Incomplete":{"key":"k","declared_size":6000000000,"part_size":5000000,"received_parts":[0,1,300]}}"#;
+        let Ok(DeclaredUpload::Incomplete {
+            declared_size,
+            received_parts,
+            ..
+        }) = from_json::<DeclaredUpload>(json)
+        else {
+            panic!("did not parse as incomplete");
+        };
+        assert_eq!(declared_size, 6_000_000_000);
+        assert_eq!(received_parts, vec![0, 1, 300]);
+    }
+
+    #[test]
+    fn narrow_declared_uploads_from_older_servers_parse() {
+        let json = r#"{"Incomplete":{"key":"k","declared_size":1000,"part_size":100,"received_parts":[3]}}"#;
+        assert!(matches!(
+            from_json::<DeclaredUpload>(json),
+            Ok(DeclaredUpload::Incomplete {
+                declared_size: 1000,
+                ..
+            })
+        ));
+        assert!(matches!(
+            from_json::<DeclaredUpload>(r#""Complete""#),
+            Ok(DeclaredUpload::Complete)
+        ));
+    }
+}
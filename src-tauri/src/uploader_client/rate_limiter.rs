@@ -0,0 +1,204 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// how often a waiting upload looks at the bucket again, which is also how quickly
+// a changed rate limit is picked up by uploads that are already waiting.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// Token bucket limiting how many bytes per second all uploads send in total.
+/// The bucket holds at most one second worth of bytes.
+pub struct RateLimiter {
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    bytes_per_second: Option<u64>,
+    // goes negative when a chunk bigger than what is available is let through,
+    // later chunks then wait until that debt is paid off.
+    available_bytes: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: Option<u64>) -> Self {
+        Self {
+            state: Mutex::new(BucketState {
+                bytes_per_second: bytes_per_second.filter(|rate| *rate > 0),
+                available_bytes: 0.0,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// `None` removes the limit. Takes effect for uploads that are already running.
+    pub fn set_rate(&self, bytes_per_second: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        state.set_rate(bytes_per_second.filter(|rate| *rate > 0), Instant::now());
+    }
+
+    /// how long `bytes` take to get through at the current limit, zero when there is none.
+    pub fn time_to_send(&self, bytes: u64) -> Duration {
+        match self.state.lock().unwrap().bytes_per_second {
+            Some(rate) => Duration::from_secs_f64(bytes as f64 / rate as f64),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// waits until `bytes` may be sent.
+    pub async fn acquire(&self, bytes: u64) {
+        loop {
+            let wait = self.state.lock().unwrap().take(bytes, Instant::now());
+            match wait {
+                None => return,
+                Some(wait) => {
+                    tokio::time::sleep(wait.clamp(Duration::from_millis(1), MAX_WAIT)).await
+                }
+            }
+        }
+    }
+}
+
+impl BucketState {
+    fn set_rate(&mut self, bytes_per_second: Option<u64>, now: Instant) {
+        self.refill(now);
+        self.bytes_per_second = bytes_per_second;
+        if let Some(rate) = bytes_per_second {
+            self.available_bytes = self.available_bytes.min(rate as f64);
+        }
+    }
+
+    /// takes `bytes` out of the bucket, or says how long until the debt is paid off.
+    fn take(&mut self, bytes: u64, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        match self.bytes_per_second {
+            None => None,
+            Some(_) if self.available_bytes > 0.0 => {
+                self.available_bytes -= bytes as f64;
+                None
+            }
+            Some(rate) => Some(Duration::from_secs_f64(-self.available_bytes / rate as f64)),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        if let Some(rate) = self.bytes_per_second {
+            let rate = rate as f64;
+            self.available_bytes = (self.available_bytes + elapsed * rate).min(rate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(bytes_per_second: Option<u64>, start: Instant) -> BucketState {
+        BucketState {
+            bytes_per_second,
+            available_bytes: 0.0,
+            last_refill: start,
+        }
+    }
+
+    fn millis(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn unlimited_never_waits() {
+        let start = Instant::now();
+        let mut state = bucket(None, start);
+        assert_eq!(state.take(u64::MAX, start), None);
+        assert_eq!(state.take(u64::MAX, start), None);
+    }
+
+    #[test]
+    fn refills_at_the_rate() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        state.refill(millis(start, 250));
+        assert_eq!(state.available_bytes, 250.0);
+        state.refill(millis(start, 500));
+        assert_eq!(state.available_bytes, 500.0);
+    }
+
+    #[test]
+    fn holds_at_most_one_second() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        state.refill(millis(start, 10_000));
+        assert_eq!(state.available_bytes, 1000.0);
+    }
+
+    #[test]
+    fn empty_bucket_waits_for_the_next_byte() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        assert_eq!(state.take(100, start), Some(Duration::ZERO));
+        assert_eq!(state.take(100, millis(start, 1)), None);
+    }
+
+    #[test]
+    fn oversized_chunk_goes_into_debt() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        state.refill(millis(start, 100));
+        assert_eq!(state.take(600, millis(start, 100)), None);
+        assert_eq!(state.available_bytes, -500.0);
+        assert_eq!(
+            state.take(1, millis(start, 100)),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            state.take(1, millis(start, 400)),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(state.take(1, millis(start, 601)), None);
+    }
+
+    #[test]
+    fn lowering_the_rate_drops_what_no_longer_fits() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        state.refill(millis(start, 1000));
+        state.set_rate(Some(100), millis(start, 1000));
+        assert_eq!(state.available_bytes, 100.0);
+    }
+
+    #[test]
+    fn rate_change_applies_to_debt_already_owed() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        state.refill(millis(start, 1));
+        state.take(1001, millis(start, 1));
+        state.set_rate(Some(100), millis(start, 1));
+        assert_eq!(
+            state.take(1, millis(start, 1)),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn removing_the_limit_lets_waiting_uploads_through() {
+        let start = Instant::now();
+        let mut state = bucket(Some(1000), start);
+        state.refill(millis(start, 1));
+        state.take(5000, millis(start, 1));
+        state.set_rate(None, millis(start, 1));
+        assert_eq!(state.take(1, millis(start, 1)), None);
+    }
+
+    #[test]
+    fn time_to_send_follows_the_current_rate() {
+        let limiter = RateLimiter::new(None);
+        assert_eq!(limiter.time_to_send(1_000_000), Duration::ZERO);
+        limiter.set_rate(Some(50_000));
+        assert_eq!(limiter.time_to_send(1_000_000), Duration::from_secs(20));
+        limiter.set_rate(Some(0));
+        assert_eq!(limiter.time_to_send(1_000_000), Duration::ZERO);
+    }
+}
@@ -0,0 +1,295 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    Response, StatusCode,
+};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+// a server asking us to wait longer than this is treated as if it asked for this.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// exponential backoff with full jitter. `attempt` starts at 1 for the first failure.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        let ceiling_ms = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::random_range(0..=ceiling_ms))
+    }
+}
+
+pub enum Retryability {
+    DoNotRetry,
+    Retry {
+        reason: String,
+        retry_after: Option<Duration>,
+    },
+}
+
+/// decides whether a request is worth sending again. connection problems, timeouts,
+/// server errors and rate limiting are transient, everything else (bad credentials,
+/// requests the server considers invalid) will fail the same way next time.
+pub fn classify(result: &Result<Response, reqwest::Error>) -> Retryability {
+    match result {
+        Ok(response) => classify_status(response.status(), response.headers()),
+        Err(e) if e.is_timeout() => retry(format!("timed out: {e}")),
+        Err(e) if e.is_connect() => retry(format!("could not connect: {e}")),
+        Err(e) if e.is_request() || e.is_body() => retry(format!("connection failed: {e}")),
+        Err(_) => Retryability::DoNotRetry,
+    }
+}
+
+fn classify_status(status: StatusCode, headers: &HeaderMap) -> Retryability {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Retryability::Retry {
+            reason: "server is rate limiting".to_string(),
+            retry_after: get_retry_after(headers, SystemTime::now()),
+        };
+    }
+    if status.is_server_error() {
+        return Retryability::Retry {
+            reason: format!("server error {}", status.as_u16()),
+            retry_after: get_retry_after(headers, SystemTime::now()),
+        };
+    }
+    Retryability::DoNotRetry
+}
+
+fn retry(reason: String) -> Retryability {
+    Retryability::Retry {
+        reason,
+        retry_after: None,
+    }
+}
+
+// either a number of seconds or an http date, anything else falls back to normal backoff.
+fn get_retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        // a date that has already passed means go right away
+        Err(_) => parse_http_date(value)?
+            .duration_since(now)
+            .unwrap_or(Duration::ZERO),
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// the IMF-fixdate form every server sends these days, "Sun, 06 Nov 1994 08:49:37 GMT".
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let parts: [&str; 6] = value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .try_into()
+        .ok()?;
+    let [_weekday, day, month, year, time, "GMT"] = parts else {
+        return None;
+    };
+    let day: u64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    let year: u64 = year.parse().ok()?;
+    let [hours, minutes, seconds] = time
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?
+        .try_into()
+        .ok()?;
+    if year < 1970 || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let seconds =
+        days_since_epoch(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+// days from 1970-01-01 to the given date, Howard Hinnant's days_from_civil
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use reqwest::{header::HeaderValue, Client};
+
+    use super::*;
+
+    fn retry_after(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn is_retry(retryability: &Retryability) -> bool {
+        matches!(retryability, Retryability::Retry { .. })
+    }
+
+    fn delay_after(status: u16, headers: &HeaderMap) -> Option<Duration> {
+        match classify_status(StatusCode::from_u16(status).unwrap(), headers) {
+            Retryability::Retry { retry_after, .. } => retry_after,
+            Retryability::DoNotRetry => panic!("{status} should be retried"),
+        }
+    }
+
+    #[test]
+    fn server_errors_and_rate_limiting_are_retried() {
+        for status in [429, 500, 502, 503, 504] {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert!(
+                is_retry(&classify_status(status, &HeaderMap::new())),
+                "{status}"
+            );
+        }
+    }
+
+    #[test]
+    fn client_errors_and_successes_are_not_retried() {
+        for status in [200, 204, 301, 400, 401, 403, 404, 405, 409, 413] {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert!(
+                !is_retry(&classify_status(status, &HeaderMap::new())),
+                "{status}"
+            );
+        }
+    }
+
+    #[test]
+    fn retry_after_seconds_are_followed_up_to_the_cap() {
+        assert_eq!(
+            delay_after(429, &retry_after("12")),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(delay_after(503, &retry_after(" 0 ")), Some(Duration::ZERO));
+        assert_eq!(
+            delay_after(503, &retry_after("86400")),
+            Some(MAX_RETRY_AFTER)
+        );
+        assert_eq!(delay_after(429, &HeaderMap::new()), None);
+        assert_eq!(delay_after(429, &retry_after("soon")), None);
+        assert_eq!(delay_after(429, &retry_after("-5")), None);
+    }
+
+    #[test]
+    fn retry_after_dates_are_counted_from_now() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let at = parse_http_date(date).unwrap();
+        assert_eq!(at, UNIX_EPOCH + Duration::from_secs(784111777));
+        let headers = retry_after(date);
+        let before = |seconds| at - Duration::from_secs(seconds);
+        assert_eq!(
+            get_retry_after(&headers, before(30)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            get_retry_after(&headers, before(3600)),
+            Some(MAX_RETRY_AFTER)
+        );
+        // already passed
+        assert_eq!(
+            get_retry_after(&headers, at + Duration::from_secs(5)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn http_dates_are_checked() {
+        let leap_day = parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT").unwrap();
+        assert_eq!(leap_day, UNIX_EPOCH + Duration::from_secs(1709164800));
+        let epoch = parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT").unwrap();
+        assert_eq!(epoch, UNIX_EPOCH);
+        for value in [
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Nov 1994 08:49:37 PST",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+        ] {
+            assert!(parse_http_date(value).is_none(), "{value}");
+        }
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..=40 {
+            let ceiling = DEFAULT_BASE_DELAY
+                .saturating_mul(1 << (attempt - 1).min(16))
+                .min(DEFAULT_MAX_DELAY);
+            for _ in 0..20 {
+                assert!(policy.backoff(attempt) <= ceiling, "{attempt}");
+            }
+        }
+        assert!(policy.backoff(0) <= DEFAULT_BASE_DELAY);
+        assert!(policy.backoff(u32::MAX) <= DEFAULT_MAX_DELAY);
+    }
+
+    #[test]
+    fn backoff_is_jittered() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<Duration> = (0..20).map(|_| policy.backoff(10)).collect();
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[tokio::test]
+    async fn refused_connections_are_retried() {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        // the listener is gone, so nothing answers on that port
+        let result = Client::new().get(format!("http://{address}")).send().await;
+        assert!(is_retry(&classify(&result)));
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_retried() {
+        // accepts connections into its backlog but never answers them
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let result = Client::new()
+            .get(format!("http://{address}"))
+            .timeout(Duration::from_millis(50))
+            .send()
+            .await;
+        assert!(result.as_ref().is_err_and(|e| e.is_timeout()));
+        assert!(is_retry(&classify(&result)));
+    }
+
+    #[tokio::test]
+    async fn requests_that_cannot_be_built_are_not_retried() {
+        let result = Client::new().get("not a url").send().await;
+        assert!(!is_retry(&classify(&result)));
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::MutexGuard;
+
+use crate::secret_store::SecretStore;
+
+use super::MusicUploaderClientConfig;
+
+/// How requests say who is sending them.
+#[derive(Clone, PartialEq)]
+pub enum Auth {
+    // from logging in, sent instead of the password
+    Token(String),
+    // the server has no logins, so the password goes with every request
+    Basic,
+}
+
+/// a token is only good for the account and server it was issued for.
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    account: String,
+    token: String,
+}
+
+/// What each profile authenticates with. Tokens are also kept in the secret
+/// store so starting the app does not mean logging in again, servers without
+/// tokens are only remembered until the app closes in case they get them.
+pub struct Sessions {
+    secrets: Arc<dyn SecretStore>,
+    // token secret -> the account it is for and how it authenticates
+    auths: Mutex<HashMap<String, (String, Auth)>>,
+    // parallel uploads all see an expired token at once, only one of them should log in
+    login_lock: tokio::sync::Mutex<()>,
+}
+
+impl Sessions {
+    pub fn new(secrets: Arc<dyn SecretStore>) -> Self {
+        Self {
+            secrets,
+            auths: Mutex::new(HashMap::new()),
+            login_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// None when the config has to log in first.
+    pub fn get(&self, config: &MusicUploaderClientConfig) -> Option<Auth> {
+        let account = account_of(config);
+        let mut auths = self.auths.lock().unwrap();
+        if let Some((auth_account, auth)) = auths.get(&config.token_secret) {
+            if *auth_account == account {
+                return Some(auth.clone());
+            }
+        }
+        let stored = self
+            .secrets
+            .get(&config.token_secret)
+            .map_err(|e| println!("failed to read the login token: {e}"))
+            .ok()
+            .flatten()
+            .and_then(|text| serde_json::from_str::<StoredToken>(&text).ok())
+            .filter(|stored| stored.account == account)?;
+        let auth = Auth::Token(stored.token);
+        auths.insert(config.token_secret.clone(), (account, auth.clone()));
+        Some(auth)
+    }
+
+    pub fn remember(&self, config: &MusicUploaderClientConfig, auth: &Auth) {
+        let account = account_of(config);
+        if let Auth::Token(token) = auth {
+            let stored = StoredToken {
+                account: account.clone(),
+                token: token.clone(),
+            };
+            let text = serde_json::to_string(&stored).expect("two strings always serialize");
+            // still works without it, the app just logs in again next time
+            if let Err(e) = self.secrets.set(&config.token_secret, &text) {
+                println!("failed to store the login token: {e}");
+            }
+        }
+        self.auths
+            .lock()
+            .unwrap()
+            .insert(config.token_secret.clone(), (account, auth.clone()));
+    }
+
+    pub fn forget(&self, config: &MusicUploaderClientConfig) {
+        self.auths.lock().unwrap().remove(&config.token_secret);
+        if let Err(e) = self.secrets.delete(&config.token_secret) {
+            println!("failed to forget the login token: {e}");
+        }
+    }
+
+    pub async fn lock_login(&self) -> MutexGuard<'_, ()> {
+        self.login_lock.lock().await
+    }
+}
+
+fn account_of(config: &MusicUploaderClientConfig) -> String {
+    format!("{}@{}", config.user, config.server_url)
+}
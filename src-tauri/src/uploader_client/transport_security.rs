@@ -0,0 +1,361 @@
+use std::{
+    fs,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{Certificate, Client, Url};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+
+use super::{MusicUploaderClientConfig, MusicUploaderClientError};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// how long a request may take on top of the time its body spends held back
+/// by the upload rate limit.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Everything about a config that changes how its client connects. Clients
+/// are shared by every config that ends up with the same one. Certificate files
+/// are only read when a client is built, they are told apart by when they were
+/// last changed so editing them still takes effect without a restart.
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct ClientKey {
+    https_only: bool,
+    ca_certificates: Option<CertificateFile>,
+    pinned_certificate: Option<CertificateFile>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct CertificateFile {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+impl CertificateFile {
+    fn at(path: &String) -> Result<Self, MusicUploaderClientError> {
+        let metadata = fs::metadata(path).map_err(|e| read_error(path, e))?;
+        Ok(Self {
+            path: path.clone(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+impl ClientKey {
+    /// refuses to send credentials over plain http to anything but this machine,
+    /// unless the profile explicitly allows it.
+    pub fn for_config(
+        config: &MusicUploaderClientConfig,
+    ) -> Result<Self, MusicUploaderClientError> {
+        let url = Url::parse(&config.server_url)
+            .map_err(|e| bad_config(format!("{} is not a valid url: {e}", config.server_url)))?;
+        let https_only = match url.scheme() {
+            "https" => true,
+            "http" if is_loopback(&url) || config.allow_insecure_http => false,
+            "http" => {
+                return Err(bad_config(format!(
+                    "{} uses plain http, which would send your password unencrypted. Use https or allow insecure http for this profile",
+                    config.server_url
+                )))
+            }
+            scheme => return Err(bad_config(format!("{scheme} urls are not supported"))),
+        };
+        let ca_certificates = config
+            .ca_certificate
+            .as_ref()
+            .map(CertificateFile::at)
+            .transpose()?;
+        let pinned_certificate = config
+            .pinned_certificate
+            .as_ref()
+            .map(CertificateFile::at)
+            .transpose()?;
+        Ok(Self {
+            https_only,
+            ca_certificates,
+            pinned_certificate,
+        })
+    }
+
+    pub fn build_client(&self) -> Result<Client, MusicUploaderClientError> {
+        let mut builder = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            // also stops a redirect from quietly dropping down to http
+            .https_only(self.https_only);
+        if let Some(file) = &self.ca_certificates {
+            let pem = read_certificate_file(&file.path)?;
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| bad_config(format!("the CA certificates are not valid PEM: {e}")))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(file) = &self.pinned_certificate {
+            let hash = pinned_certificate_hash(&file.path)?;
+            builder = builder.use_preconfigured_tls(pinned_tls_config(hash)?);
+        }
+        builder
+            .build()
+            .map_err(|e| bad_config(format!("failed to set up the http client: {e}")))
+    }
+
+    /// the same files as `newer`, from before they were last changed.
+    pub fn is_replaced_by(&self, newer: &ClientKey) -> bool {
+        let same_path = |a: &Option<CertificateFile>, b: &Option<CertificateFile>| {
+            a.as_ref().map(|file| &file.path) == b.as_ref().map(|file| &file.path)
+        };
+        self != newer
+            && self.https_only == newer.https_only
+            && same_path(&self.ca_certificates, &newer.ca_certificates)
+            && same_path(&self.pinned_certificate, &newer.pinned_certificate)
+    }
+}
+
+fn is_loopback(url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    // ipv6 hosts keep their brackets
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback(),
+        Err(_) => {
+            let host = host.to_ascii_lowercase();
+            host == "localhost" || host.ends_with(".localhost")
+        }
+    }
+}
+
+fn read_certificate_file(path: &String) -> Result<Vec<u8>, MusicUploaderClientError> {
+    fs::read(path).map_err(|e| read_error(path, e))
+}
+
+fn read_error(path: &str, e: std::io::Error) -> MusicUploaderClientError {
+    MusicUploaderClientError::FileReadError(path.to_string(), Box::new(e))
+}
+
+/// the sha256 of the server's own certificate, given as a PEM or DER file.
+fn pinned_certificate_hash(path: &String) -> Result<[u8; 32], MusicUploaderClientError> {
+    let data = read_certificate_file(path)?;
+    let der = match CertificateDer::from_pem_slice(&data) {
+        Ok(certificate) => certificate.to_vec(),
+        // no PEM header, so hopefully the raw certificate
+        Err(_) => data,
+    };
+    Ok(Sha256::digest(der).into())
+}
+
+/// rustls that trusts exactly one certificate, for servers with a self signed one.
+fn pinned_tls_config(hash: [u8; 32]) -> Result<ClientConfig, MusicUploaderClientError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedCertificateVerifier {
+        hash,
+        provider: provider.clone(),
+    };
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| bad_config(format!("failed to set up tls: {e}")))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Ok(config)
+}
+
+/// Accepts the server when it presents the pinned certificate, whatever signed
+/// it and whatever name it is for. The handshake signatures are still checked,
+/// so only whoever has the certificate's private key can pass.
+#[derive(Debug)]
+struct PinnedCertificateVerifier {
+    hash: [u8; 32],
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let hash: [u8; 32] = Sha256::digest(end_entity.as_ref()).into();
+        match hash == self.hash {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::General(
+                "the server's certificate is not the pinned one".to_string(),
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn bad_config(message: String) -> MusicUploaderClientError {
+    MusicUploaderClientError::BadConfig(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(server_url: &str, allow_insecure_http: bool) -> MusicUploaderClientConfig {
+        MusicUploaderClientConfig {
+            user: "user".to_string(),
+            password: "password".to_string(),
+            server_url: server_url.to_string(),
+            max_upload_part_size: 1,
+            part_concurrency: 1,
+            song_concurrency: 1,
+            allow_insecure_http,
+            ca_certificate: None,
+            pinned_certificate: None,
+            token_secret: "token".to_string(),
+        }
+    }
+
+    fn https_only(
+        server_url: &str,
+        allow_insecure_http: bool,
+    ) -> Result<bool, MusicUploaderClientError> {
+        ClientKey::for_config(&config(server_url, allow_insecure_http)).map(|key| key.https_only)
+    }
+
+    #[test]
+    fn https_is_always_allowed() {
+        assert!(https_only("https://music.example.com/api", false).unwrap());
+        assert!(https_only("https://localhost/api", false).unwrap());
+    }
+
+    #[test]
+    fn http_is_allowed_on_loopback() {
+        for url in [
+            "http://localhost:5046/api",
+            "http://LOCALHOST/api",
+            "http://music.localhost/api",
+            "http://127.0.0.1:5046/api",
+            "http://127.1.2.3/api",
+            "http://[::1]:5046/api",
+        ] {
+            assert!(!https_only(url, false).unwrap(), "{url}");
+        }
+    }
+
+    #[test]
+    fn http_is_refused_elsewhere() {
+        for url in [
+            "http://music.example.com/api",
+            "http://192.168.1.20:5046/api",
+            "http://localhost.example.com/api",
+            "http://[2001:db8::1]/api",
+        ] {
+            assert!(
+                matches!(
+                    https_only(url, false),
+                    Err(MusicUploaderClientError::BadConfig(_))
+                ),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn http_can_be_allowed_explicitly() {
+        assert!(!https_only("http://192.168.1.20:5046/api", true).unwrap());
+    }
+
+    fn temp_certificate(test_name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "music_uploader_certificate_{test_name}_{}.der",
+            std::process::id()
+        ));
+        // not a real certificate, pinning only hashes it
+        fs::write(&path, [7; 32]).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn pinned_key(path: &str) -> ClientKey {
+        let mut config = config("https://music.example.com/api", false);
+        config.pinned_certificate = Some(path.to_string());
+        ClientKey::for_config(&config).unwrap()
+    }
+
+    // reqwest only takes the rustls config when its rustls is the one we build it with
+    #[test]
+    fn pinned_clients_can_be_built() {
+        let key = pinned_key(&temp_certificate("build"));
+        assert!(key.build_client().is_ok());
+    }
+
+    #[test]
+    fn changed_certificates_replace_the_old_key() {
+        let path = temp_certificate("replace");
+        let old = pinned_key(&path);
+        assert!(pinned_key(&path) == old);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(SystemTime::now() + Duration::from_secs(60)))
+            .unwrap();
+        let new = pinned_key(&path);
+        assert!(old.is_replaced_by(&new));
+        assert!(!new.is_replaced_by(&new));
+        assert!(!old.is_replaced_by(&pinned_key(&temp_certificate("other"))));
+    }
+
+    #[test]
+    fn missing_certificates_are_errors() {
+        let mut config = config("https://music.example.com/api", false);
+        config.ca_certificate = Some("/no/such/ca.pem".to_string());
+        assert!(matches!(
+            ClientKey::for_config(&config),
+            Err(MusicUploaderClientError::FileReadError(..))
+        ));
+    }
+
+    #[test]
+    fn other_urls_are_refused() {
+        assert!(https_only("ftp://music.example.com", true).is_err());
+        assert!(https_only("music.example.com/api", true).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+};
+
+use flate2::read::DeflateDecoder;
+use zip::{CompressionMethod, ZipArchive};
+
+// how far back a deflated entry can be seeked without decompressing it from the start again
+const REWIND_WINDOW_BYTES: usize = 256 * 1024;
+
+/// A file inside a zip archive. The archive is only opened to find out where the
+/// entry's data lives, reading it goes straight to the file, so an entry can be
+/// read from any offset without keeping the archive around.
+#[derive(Clone)]
+pub struct ZipEntry {
+    archive_path: String,
+    data_start: u64,
+    compressed_size: u64,
+    size: u64,
+    crc32: u32,
+    deflated: bool,
+}
+
+impl ZipEntry {
+    pub fn find(archive_path: &String, name: &str) -> io::Result<Self> {
+        let mut archive = open_archive(archive_path)?;
+        let index = archive.index_for_name(name).ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{name} is not in the archive"),
+        ))?;
+        // raw so that zip does not try to decompress it, we do that ourselves
+        let entry = archive.by_index_raw(index).map_err(zip_error)?;
+        if entry.encrypted() {
+            return Err(unsupported(format!("{name} is encrypted")));
+        }
+        let deflated = match entry.compression() {
+            CompressionMethod::Stored => false,
+            CompressionMethod::Deflated => true,
+            method => {
+                return Err(unsupported(format!(
+                "{name} is compressed with {method}, only stored and deflated files are supported"
+            )))
+            }
+        };
+        Ok(Self {
+            archive_path: archive_path.to_string(),
+            data_start: entry.data_start(),
+            compressed_size: entry.compressed_size(),
+            size: entry.size(),
+            crc32: entry.crc32(),
+            deflated,
+        })
+    }
+
+    /// names of all the files in the archive, folders are left out.
+    pub fn list(archive_path: &String) -> io::Result<Vec<String>> {
+        let archive = open_archive(archive_path)?;
+        Ok(archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    /// uncompressed size of the entry.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// the CRC32 the archive records for the uncompressed entry.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    pub fn reader(&self) -> io::Result<ZipEntryReader> {
+        Ok(ZipEntryReader {
+            entry: self.clone(),
+            inner: self.read_from(0)?,
+            inner_position: 0,
+            position: 0,
+            recent: Vec::new(),
+        })
+    }
+
+    /// the content of the entry from `offset` on. A stored entry is a plain slice of
+    /// the archive, a deflated one has to be decompressed up to `offset` first, so
+    /// reading a deflated entry in pieces should go through one `reader` instead.
+    fn read_from(&self, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+        let mut file = File::open(&self.archive_path)?;
+        let offset = u64::min(offset, self.size);
+        let remaining = self.size - offset;
+        if self.deflated {
+            file.seek(SeekFrom::Start(self.data_start))?;
+            let compressed = BufReader::new(file.take(self.compressed_size));
+            let mut reader = DeflateDecoder::new(compressed).take(self.size);
+            io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+            Ok(Box::new(reader))
+        } else {
+            file.seek(SeekFrom::Start(self.data_start + offset))?;
+            Ok(Box::new(file.take(remaining)))
+        }
+    }
+}
+
+/// Reads and seeks around a zip entry like it was a file of its own. Deflate
+/// only goes forwards, so seeking ahead skips over what is in between and the
+/// last bytes read are kept around for seeking back a little, like a
+/// `BufReader` does after reading ahead. Only seeking further back than that
+/// decompresses from the start again.
+pub struct ZipEntryReader {
+    entry: ZipEntry,
+    inner: Box<dyn Read + Send>,
+    // where `inner` is, reads come from `recent` while `position` is behind it
+    inner_position: u64,
+    position: u64,
+    // the bytes just before `inner_position`, only kept for deflated entries
+    recent: Vec<u8>,
+}
+
+impl ZipEntryReader {
+    fn recent_start(&self) -> u64 {
+        self.inner_position - self.recent.len() as u64
+    }
+
+    fn remember(&mut self, data: &[u8]) {
+        self.recent.extend_from_slice(data);
+        // trimmed in bulk so every read does not shift the whole window
+        if self.recent.len() > 2 * REWIND_WINDOW_BYTES {
+            let excess = self.recent.len() - REWIND_WINDOW_BYTES;
+            self.recent.drain(..excess);
+        }
+    }
+}
+
+impl Read for ZipEntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.inner_position {
+            let from = (self.position - self.recent_start()) as usize;
+            let len = usize::min(buf.len(), self.recent.len() - from);
+            buf[..len].copy_from_slice(&self.recent[from..from + len]);
+            self.position += len as u64;
+            return Ok(len);
+        }
+        let read = self.inner.read(buf)?;
+        self.inner_position += read as u64;
+        self.position = self.inner_position;
+        if self.entry.deflated {
+            self.remember(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+impl Seek for ZipEntryReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.entry.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot seek to before the start of the entry",
+        ))?;
+        if (self.recent_start()..=self.inner_position).contains(&target) {
+            self.position = target;
+        } else if self.entry.deflated && target > self.inner_position {
+            self.position = self.inner_position;
+            let skip = target - self.inner_position;
+            io::copy(&mut self.take(skip), &mut io::sink())?;
+        } else {
+            self.inner = self.entry.read_from(target)?;
+            self.inner_position = target;
+            self.position = target;
+            self.recent.clear();
+        }
+        Ok(target)
+    }
+}
+
+fn open_archive(archive_path: &String) -> io::Result<ZipArchive<BufReader<File>>> {
+    let file = File::open(archive_path)?;
+    ZipArchive::new(BufReader::new(file)).map_err(zip_error)
+}
+
+fn zip_error(e: zip::result::ZipError) -> io::Error {
+    match e {
+        zip::result::ZipError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+fn unsupported(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    // compressible but not repetitive enough to hide an off by one
+    fn content() -> Vec<u8> {
+        (0..3 * REWIND_WINDOW_BYTES as u32)
+            .flat_map(|i| (i / 7).to_le_bytes())
+            .collect()
+    }
+
+    fn temp_entry(test_name: &str, method: CompressionMethod) -> ZipEntry {
+        let path = std::env::temp_dir().join(format!(
+            "music_uploader_zip_{test_name}_{}.zip",
+            std::process::id()
+        ));
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default().compression_method(method);
+        writer.start_file("song.flac", options).unwrap();
+        writer.write_all(&content()).unwrap();
+        writer.finish().unwrap();
+        ZipEntry::find(&path.to_string_lossy().to_string(), "song.flac").unwrap()
+    }
+
+    fn read_at(reader: &mut ZipEntryReader, pos: SeekFrom, len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        reader.seek(pos).unwrap();
+        reader.read_exact(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn seeks_read_the_right_bytes() {
+        let content = content();
+        for (name, method) in [
+            ("stored", CompressionMethod::Stored),
+            ("deflated", CompressionMethod::Deflated),
+        ] {
+            let entry = temp_entry(name, method);
+            assert_eq!(entry.size(), content.len() as u64);
+            let mut reader = entry.reader().unwrap();
+            let seeks = [
+                (SeekFrom::Start(100), 50),
+                // back inside what was just read
+                (SeekFrom::Current(-120), 500),
+                // ahead, then back into the window
+                (SeekFrom::Start(REWIND_WINDOW_BYTES as u64 * 3), 1000),
+                (SeekFrom::Current(-50_000), 10),
+                // back past the window, so from the start again
+                (SeekFrom::Start(10), 10),
+                (SeekFrom::End(-100), 100),
+            ];
+            let mut expected_position = 0u64;
+            for (pos, len) in seeks {
+                let start = match pos {
+                    SeekFrom::Start(offset) => offset,
+                    SeekFrom::Current(offset) => {
+                        expected_position.checked_add_signed(offset).unwrap()
+                    }
+                    SeekFrom::End(offset) => {
+                        (content.len() as u64).checked_add_signed(offset).unwrap()
+                    }
+                } as usize;
+                assert_eq!(
+                    read_at(&mut reader, pos, len),
+                    content[start..start + len],
+                    "{name} at {start}"
+                );
+                expected_position = (start + len) as u64;
+            }
+        }
+    }
+
+    #[test]
+    fn reading_in_order_does_not_start_over() {
+        let entry = temp_entry("in_order", CompressionMethod::Deflated);
+        let mut reader = entry.reader().unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, content());
+        assert!(reader.recent.len() <= 2 * REWIND_WINDOW_BYTES);
+        // the end is still in the window, so nothing has to be decompressed again
+        let inner_position = reader.inner_position;
+        read_at(&mut reader, SeekFrom::End(-1000), 1000);
+        assert_eq!(reader.inner_position, inner_position);
+    }
+}
@@ -1,12 +1,62 @@
 
 import { invoke } from "@tauri-apps/api/core";
 
-export async function upload_album(album, songs) {
-    return await invoke("upload_album", {
-        albumName: album.name,
-        albumId: album.id.toString(),
+// the album as the backend's QueuedAlbum
+function album_to_upload(album, songs, journalId) {
+    return {
+        album_id: album.id.toString(),
+        album_name: album.name,
         artist: album.artist,
         songs,
+        journal_id: journalId ?? null,
+        cover_art: album.coverArt ?? null,
+        profile_id: album.profileId ?? null,
+    };
+}
+
+export async function upload_album(album, songs) {
+    return await invoke("upload_album", {
+        album: album_to_upload(album, songs),
+    });
+}
+
+export async function list_resumable_uploads() {
+    return await invoke("list_resumable_uploads");
+}
+
+export async function resume_upload(journalId, albumId) {
+    return await invoke("resume_upload", {
+        journalId,
+        albumId: albumId.toString(),
+    });
+}
+
+export async function enqueue_album(album, songs, journalId) {
+    return await invoke("enqueue_album", {
+        album: album_to_upload(album, songs, journalId),
+    });
+}
+
+export async function list_upload_queue() {
+    return await invoke("list_upload_queue");
+}
+
+export async function reorder_upload_queue(albumId, index) {
+    return await invoke("reorder_upload_queue", {
+        albumId: albumId.toString(),
+        index,
+    });
+}
+
+export async function remove_from_upload_queue(albumId) {
+    return await invoke("remove_from_upload_queue", {
+        albumId: albumId.toString(),
+    });
+}
+
+export async function cancel_upload(albumId) {
+    return await invoke("cancel_upload", {
+        albumId: albumId.toString(),
     });
 }
 
@@ -34,12 +84,9 @@ export async function get_settings() {
     return await invoke("get_settings");
 }
 
-export async function save_settings(user, password, url, maxPartSizeMb) {
+export async function save_settings(settings) {
     return await invoke("save_settings", {
-        user,
-        password,
-        url,
-        maxPartSizeMb,
+        settings,
     });
 }
 
@@ -47,4 +94,73 @@ export async function album_search(album) {
     return await invoke("album_search", {
         album,
     });
-}
\ No newline at end of file
+}
+
+export async function read_song_metadata(songs) {
+    return await invoke("read_song_metadata", {
+        songs,
+    });
+}
+
+export async function validate_album(albumId, songs) {
+    return await invoke("validate_album", {
+        albumId: albumId.toString(),
+        songs,
+    });
+}
+
+export async function scan_directory(path) {
+    return await invoke("scan_directory", {
+        path,
+    });
+}
+
+export async function find_cover_art(songs) {
+    return await invoke("find_cover_art", {
+        songs,
+    });
+}
+
+export async function preview_upload_names(albumName, artist, songs) {
+    return await invoke("preview_upload_names", {
+        albumName,
+        artist,
+        songs,
+    });
+}
+
+export async function check_songs_on_server(songs, profileId) {
+    return await invoke("check_songs_on_server", {
+        songs,
+        profileId: profileId ?? null,
+    });
+}
+
+export async function list_profiles() {
+    return await invoke("list_profiles");
+}
+
+export async function create_profile(name) {
+    return await invoke("create_profile", {
+        name,
+    });
+}
+
+export async function rename_profile(profileId, name) {
+    return await invoke("rename_profile", {
+        profileId,
+        name,
+    });
+}
+
+export async function delete_profile(profileId) {
+    return await invoke("delete_profile", {
+        profileId,
+    });
+}
+
+export async function switch_profile(profileId) {
+    return await invoke("switch_profile", {
+        profileId,
+    });
+}
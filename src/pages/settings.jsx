@@ -1,17 +1,69 @@
 import { useEffect, useState } from "react";
-import { get_settings, run_settings_checks, save_settings } from "../backendApi"
+import {
+    create_profile,
+    delete_profile,
+    get_settings,
+    list_profiles,
+    rename_profile,
+    run_settings_checks,
+    save_settings,
+    switch_profile,
+} from "../backendApi"
 import { PageChooser } from "../bits/pageChooser";
 
 export function Settings({guiLog, pageState}) {
     const [user, setUser] = useState("");
     const [password, setPassword] = useState("");
     const [url, setUrl] = useState("");
+    const [allowInsecureHttp, setAllowInsecureHttp] = useState(false);
+    const [caCertificate, setCaCertificate] = useState("");
+    const [pinnedCertificate, setPinnedCertificate] = useState("");
     const [maxPartSizeMb, setMaxPartSizeMb] = useState(1);
+    const [partConcurrency, setPartConcurrency] = useState(1);
+    const [songConcurrency, setSongConcurrency] = useState(1);
+    const [uploadRateLimitKbps, setUploadRateLimitKbps] = useState(0);
+    const [nameSongsFromTags, setNameSongsFromTags] = useState(false);
+    const [profiles, setProfiles] = useState([]);
+    const [activeProfile, setActiveProfile] = useState("");
+    const [profileName, setProfileName] = useState("");
 
     useEffect(() => {
         loadSettings();
+        loadProfiles();
     }, []);
 
+    const handleProfileList = (profileList) => {
+        setProfiles(profileList.profiles);
+        setActiveProfile(profileList.active_profile);
+    }
+
+    const loadProfiles = () => {
+        list_profiles()
+            .then(handleProfileList)
+            .catch(e => guiLog(e));
+    }
+
+    // the account fields on this page belong to the active profile, so they are loaded again
+    const changeProfiles = (change) => {
+        change
+            .then(profileList => {
+                handleProfileList(profileList);
+                setProfileName("");
+                loadSettings();
+            })
+            .catch(e => guiLog(e));
+    }
+
+    const handleCreateProfile = () => {
+        create_profile(profileName)
+            .then(profileList => {
+                let created = profileList.profiles.find(profile => profile.name == profileName.trim());
+                changeProfiles(created ? switch_profile(created.id) : Promise.resolve(profileList));
+            })
+            .catch(e => guiLog(e));
+    }
+
+
     const handleGetSettingsResult = (result) => {
         if (!result.success) {
             guiLog("getting settings failed");
@@ -21,14 +73,33 @@ export function Settings({guiLog, pageState}) {
         setUser(settings.user);
         setPassword(settings.password);
         setUrl(settings.server_url);
+        setAllowInsecureHttp(settings.allow_insecure_http);
+        setCaCertificate(settings.ca_certificate);
+        setPinnedCertificate(settings.pinned_certificate);
         setMaxPartSizeMb(settings.max_part_size_mb);
+        setPartConcurrency(settings.part_concurrency);
+        setSongConcurrency(settings.song_concurrency);
+        setUploadRateLimitKbps(settings.upload_rate_limit_kbps);
+        setNameSongsFromTags(settings.name_songs_from_tags);
     }
 
     const handleSave = () => {
         if (!validateUserInput()) {
             guiLog("settings are not being saved, please fix issues");
         }
-        save_settings(user, password, url, maxPartSizeMb)
+        save_settings({
+            user,
+            password,
+            server_url: url,
+            allow_insecure_http: allowInsecureHttp,
+            ca_certificate: caCertificate,
+            pinned_certificate: pinnedCertificate,
+            max_part_size_mb: maxPartSizeMb,
+            part_concurrency: partConcurrency,
+            song_concurrency: songConcurrency,
+            upload_rate_limit_kbps: uploadRateLimitKbps,
+            name_songs_from_tags: nameSongsFromTags,
+        })
             .then(result => {
                 guiLog(result);
                 validateAndLoadSettings();
@@ -43,6 +114,18 @@ export function Settings({guiLog, pageState}) {
             guiLog("Error: max part size must be greater than 1");
             return false;
         }
+        if (partConcurrency < 1) {
+            guiLog("Error: parallel part uploads must be at least 1");
+            return false;
+        }
+        if (songConcurrency < 1) {
+            guiLog("Error: parallel song uploads must be at least 1");
+            return false;
+        }
+        if (uploadRateLimitKbps < 0) {
+            guiLog("Error: upload limit cannot be negative");
+            return false;
+        }
         return true;
     }
 
@@ -77,6 +160,48 @@ export function Settings({guiLog, pageState}) {
                     handleSave();
                 }}
             >
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            profile
+                        </span>
+                        <select
+                            className="interactable"
+                            onChange={(e) => changeProfiles(switch_profile(e.currentTarget.value))}
+                            value={activeProfile}
+                        >
+                            {profiles.map(profile =>
+                                <option key={profile.id} value={profile.id}>{profile.name}</option>
+                            )}
+                        </select>
+                        <button type="button" onClick={() => changeProfiles(delete_profile(activeProfile))} disabled={profiles.length < 2}>
+                            delete
+                        </button>
+                    </span>
+                </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            profile name
+                        </span>
+                        <input
+                            className="interactable"
+                            onChange={(e) => setProfileName(e.currentTarget.value)}
+                            placeholder="enter profile name..."
+                            value={profileName}
+                        />
+                        <button type="button" onClick={handleCreateProfile} disabled={!profileName.trim()}>
+                            new
+                        </button>
+                        <button
+                            type="button"
+                            onClick={() => changeProfiles(rename_profile(activeProfile, profileName))}
+                            disabled={!profileName.trim()}
+                        >
+                            rename
+                        </button>
+                    </span>
+                </div>
                 <div className="row buttspace">
                     <span>
                         <span className="settingsLabel">
@@ -118,6 +243,45 @@ export function Settings({guiLog, pageState}) {
                         />
                     </span>
                 </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            allow insecure http (sends your password unencrypted)
+                        </span>
+                        <input
+                            className="interactable"
+                            type="checkbox"
+                            onChange={(e) => setAllowInsecureHttp(e.currentTarget.checked)}
+                            checked={allowInsecureHttp}
+                        />
+                    </span>
+                </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            trusted CA file (PEM)
+                        </span>
+                        <input
+                            className="interactable"
+                            onChange={(e) => setCaCertificate(e.currentTarget.value)}
+                            placeholder="path, empty for none..."
+                            value={caCertificate}
+                        />
+                    </span>
+                </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            pinned server certificate
+                        </span>
+                        <input
+                            className="interactable"
+                            onChange={(e) => setPinnedCertificate(e.currentTarget.value)}
+                            placeholder="path, empty for none..."
+                            value={pinnedCertificate}
+                        />
+                    </span>
+                </div>
                 <div className="row buttspace">
                     <span>
                         <span className="settingsLabel">
@@ -131,6 +295,58 @@ export function Settings({guiLog, pageState}) {
                         />
                     </span>
                 </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            parallel part uploads
+                        </span>
+                        <input
+                            className="interactable"
+                            onChange={(e) => setPartConcurrency(handleUserInt(e.currentTarget.value))}
+                            placeholder="parts"
+                            value={partConcurrency}
+                        />
+                    </span>
+                </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            parallel song uploads
+                        </span>
+                        <input
+                            className="interactable"
+                            onChange={(e) => setSongConcurrency(handleUserInt(e.currentTarget.value))}
+                            placeholder="songs"
+                            value={songConcurrency}
+                        />
+                    </span>
+                </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            upload limit (kB/s, 0 = unlimited)
+                        </span>
+                        <input
+                            className="interactable"
+                            onChange={(e) => setUploadRateLimitKbps(handleUserInt(e.currentTarget.value))}
+                            placeholder="kB/s"
+                            value={uploadRateLimitKbps}
+                        />
+                    </span>
+                </div>
+                <div className="row buttspace">
+                    <span>
+                        <span className="settingsLabel">
+                            name songs "NN - Title" from tags
+                        </span>
+                        <input
+                            className="interactable"
+                            type="checkbox"
+                            onChange={(e) => setNameSongsFromTags(e.currentTarget.checked)}
+                            checked={nameSongsFromTags}
+                        />
+                    </span>
+                </div>
                 <div className="buttspace">
                     <button type="submit">
                         save
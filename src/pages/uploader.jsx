@@ -1,11 +1,20 @@
 import { useState, useEffect } from "react";
 import { listen } from "@tauri-apps/api/event";
 import { open } from '@tauri-apps/plugin-dialog';
-import { fs_failed_upload, fs_not_uploaded, fs_uploaded, fs_uploading, s_in_queue, s_selecting, s_upload_fail, s_upload_success, s_uploading } from "../state/uploaderState";
+import { fs_cancelled, fs_failed_upload, fs_not_uploaded, fs_uploaded, fs_uploading, s_cancelled, s_in_queue, s_resumable, s_selecting, s_upload_fail, s_upload_success, s_uploading } from "../state/uploaderState";
 import { PageChooser } from "../bits/pageChooser";
+import { list_profiles } from "../backendApi";
 
 export function Uploader({uploaderState, pageState}) {
     const [filesHovering, setFilesHovering] = useState(false);
+    const [profileList, setProfileList] = useState({ active_profile: "", profiles: [] });
+
+    // the settings page can change the profiles, it is reloaded whenever this page opens
+    useEffect(() => {
+        list_profiles()
+            .then(setProfileList)
+            .catch(e => console.log(e));
+    }, []);
 
     useEffect(() => {
         const unlistenFileDropHover = listen("tauri://drag-enter", (event) => {
@@ -44,10 +53,20 @@ export function Uploader({uploaderState, pageState}) {
             case fs_uploading: return "😤";
             case fs_uploaded: return "✅";
             case fs_failed_upload: return "❌";
+            case fs_cancelled: return "🛑";
         }
         return "error💀";
     }
 
+    const renderFileProgress = (file) => {
+        if (file.state != fs_uploading || file.progress === undefined || file.progress.totalBytes == 0) {
+            return null;
+        }
+        let percent = Math.floor(100 * file.progress.bytesSent / file.progress.totalBytes);
+        let megabytesPerSecond = (file.progress.bytesPerSecond / 1_000_000).toFixed(1);
+        return <span className="fineprint"> {percent}% ({megabytesPerSecond} MB/s)</span>;
+    }
+
     const renderAlbumStatus = (status) => {
         switch (status) {
             case s_selecting: return "⚪️";
@@ -55,6 +74,8 @@ export function Uploader({uploaderState, pageState}) {
             case s_uploading: return "😤";
             case s_upload_success: return "✅";
             case s_upload_fail: return "❌";
+            case s_cancelled: return "🛑";
+            case s_resumable: return "⏸️";
         }
         return "error💀";
     }
@@ -109,10 +130,15 @@ export function Uploader({uploaderState, pageState}) {
                                 <p>
                                 drop zone
                                 </p>
-                                <p className="fineprint">(or click to browse)</p>
+                                <p className="fineprint">(or click to browse, drop a folder or zip to add every album in it)</p>
                             </div>
                         </div>
                         <button className="interactable" disabled={!canPressUpload()} type="submit">{(canPressUpload() ? "upload" : "yeehaw")}</button>
+                        {canPressUpload() ? null : <button
+                            className="interactable"
+                            type="button"
+                            onClick={() => uploaderState.cancelAlbumUpload(uploaderState.selectedAlbumKey)}
+                        >cancel</button>}
                     </form>
                 </main>
             </div>
@@ -130,10 +156,120 @@ export function Uploader({uploaderState, pageState}) {
             <div className="rightColumn">
                 <h3 className="songItem">files to upload</h3>
                 {(files.length == 0 ? <p>looks a bit empty</p> : <div/>)}
+                {renderAlbumUploadNames()}
                 {files.map(f =>
                     <div className="songItem" key={f.id}>
-                        {renderFileStatus(f.state)} {f.name}
+                        {renderFileStatus(f.state)} {f.name}{renderUploadName(f)}{renderDuplicate(f)}{renderServerStatus(f)}{renderFileProgress(f)}
                     </div>)}
+                {renderDropDuplicates()}
+                {renderProfileChooser()}
+                {renderServerCheck()}
+                {renderCoverArtChooser()}
+            </div>
+        );
+    }
+
+    // only shown when the server gets something other than what was typed
+    const renderUploadName = (file) => {
+        let name = uploaderState.getUploadName(file);
+        return name === null ? null : <span className="fineprint"> → {name}</span>;
+    }
+
+    const renderDuplicate = (file) => {
+        return uploaderState.isDuplicate(file) ? <span className="fineprint"> (duplicate)</span> : null;
+    }
+
+    const renderDropDuplicates = () => {
+        let count = uploaderState.duplicateCount;
+        if (count == 0 || !canPressUpload()) {
+            return null;
+        }
+        return (
+            <div className="songItem">
+                <button className="interactable" type="button" onClick={() => uploaderState.dropDuplicates()}>
+                    drop {count} duplicate{count == 1 ? "" : "s"}
+                </button>
+            </div>
+        );
+    }
+
+    const renderServerStatus = (file) => {
+        return uploaderState.getServerStatus(file) == "present" ? <span className="fineprint"> (already on server)</span> : null;
+    }
+
+    const renderServerCheck = () => {
+        if (uploaderState.files.length == 0 || !canPressUpload()) {
+            return null;
+        }
+        let count = uploaderState.onServerCount;
+        return (
+            <div className="songItem">
+                <button className="interactable" type="button" onClick={() => uploaderState.checkSongsOnServer()}>
+                    check server
+                </button>
+                {count == 0 ? null : <button className="interactable" type="button" onClick={() => uploaderState.skipSongsOnServer()}>
+                    skip {count} already on server
+                </button>}
+            </div>
+        );
+    }
+
+    const renderAlbumUploadNames = () => {
+        let names = uploaderState.uploadNames;
+        if (names === null || uploaderState.album === "" || uploaderState.artist === "") {
+            return null;
+        }
+        if (names.album == uploaderState.album && names.artist == uploaderState.artist) {
+            return null;
+        }
+        return <p className="fineprint songItem">uploads as {names.artist} / {names.album}</p>;
+    }
+
+    const renderCoverArtChooser = () => {
+        let candidates = uploaderState.coverArtCandidates;
+        if (candidates.length == 0) {
+            return null;
+        }
+        return (
+            <div className="songItem">
+                🖼️ <select
+                    className="interactable"
+                    disabled={!canPressUpload()}
+                    value={uploaderState.coverArtIndex}
+                    onChange={(e) => uploaderState.setCoverArt(parseInt(e.currentTarget.value))}
+                >
+                    <option value={-1}>no cover art</option>
+                    {candidates.map((candidate, index) =>
+                        <option key={index} value={index}>
+                            {candidate.name} ({candidate.width}x{candidate.height} {candidate.format})
+                        </option>)}
+                </select>
+            </div>
+        );
+    }
+
+    // nothing to choose with a single profile, albums then always use the active one.
+    // a resumable upload has to finish on the server it was started on.
+    const renderProfileChooser = () => {
+        let profiles = profileList.profiles;
+        if (profiles.length < 2) {
+            return null;
+        }
+        let active = profiles.find(profile => profile.id == profileList.active_profile);
+        return (
+            <div className="songItem">
+                🌐 <select
+                    className="interactable"
+                    disabled={!canPressUpload() || uploaderState.state == s_resumable}
+                    value={uploaderState.profileId ?? ""}
+                    onChange={(e) => uploaderState.setProfileId(e.currentTarget.value == "" ? null : e.currentTarget.value)}
+                >
+                    <option value="">active profile{active === undefined ? "" : " (" + active.name + ")"}</option>
+                    {profiles.map(profile =>
+                        <option key={profile.id} value={profile.id}>
+                            {profile.name} ({profile.server_url})
+                        </option>)}
+                </select>
             </div>
         );
     }
@@ -155,13 +291,31 @@ export function Uploader({uploaderState, pageState}) {
                 className={className}
                 onClick={()=>uploaderState.selectAlbumId(album.id)}
             >
-                {renderAlbumStatus(album.state)} {(album.name == "") ? "New Album" : album.name} {renderCloseAlbum(album.id)}
+                {renderAlbumStatus(album.state)}{renderQueuePosition(album)} {(album.name == "") ? "New Album" : album.name} {renderCloseAlbum(album.id)}
             </span>)
         }
         output.push(renderNewTabButton());
         return output;
     }
 
+    const renderQueuePosition = (album) => {
+        let position = uploaderState.getQueuePosition(album.id);
+        if (position === null) {
+            return null;
+        }
+        if (position == 1) {
+            return <span className="fineprint">{position}</span>;
+        }
+        return <span
+            className="fineprint"
+            title="upload next"
+            onClick={(e) => {
+                e.stopPropagation();
+                uploaderState.moveAlbumToFrontOfQueue(album.id);
+            }}
+        >{position}⏫</span>;
+    }
+
     const renderCloseAlbum = (albumId) => {
         return <span
             className="uploaderTabExit"
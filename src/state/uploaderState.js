@@ -1,17 +1,20 @@
-import { useState, useEffect } from "react";
+import { useState, useEffect, useRef } from "react";
 import { listen } from "@tauri-apps/api/event";
-import { generate_guid, get_valid_extensions, upload_album } from "../backendApi";
+import { cancel_upload, check_songs_on_server, enqueue_album, find_cover_art, generate_guid, get_valid_extensions, list_resumable_uploads, list_upload_queue, preview_upload_names, read_song_metadata, reorder_upload_queue, scan_directory, validate_album } from "../backendApi";
 
 export const s_selecting = 69;
 export const s_in_queue = 419;
 export const s_uploading = 420;
 export const s_upload_success = 8008;
 export const s_upload_fail = 1134;
+export const s_cancelled = 86;
+export const s_resumable = 77;
 
 export const fs_not_uploaded = 69;
 export const fs_uploading = 123;
 export const fs_uploaded = 420;
 export const fs_failed_upload = 333;
+export const fs_cancelled = 86;
 
 
 function newAlbumWithIndex(index) {
@@ -21,6 +24,15 @@ function newAlbumWithIndex(index) {
         artist: "",
         files: [],
         state: s_selecting,
+        // undefined until the user picks one (or none, which is null)
+        coverArt: undefined,
+        coverArtCandidates: [],
+        // songs the last check found to be duplicates
+        duplicates: [],
+        // song key -> whether the server already has it
+        serverStatus: {},
+        // null uploads with whichever profile is active
+        profileId: null,
     }
 }
 const defaultAlbumKey = 0;
@@ -32,6 +44,15 @@ const defaultAlbumsState = {
         artist: "",
         files: [],
         state: s_selecting,
+        // undefined until the user picks one (or none, which is null)
+        coverArt: undefined,
+        coverArtCandidates: [],
+        // songs the last check found to be duplicates
+        duplicates: [],
+        // song key -> whether the server already has it
+        serverStatus: {},
+        // null uploads with whichever profile is active
+        profileId: null,
     }
 }
 
@@ -39,13 +60,20 @@ export function useUploaderState(guiLog) {
     const [albums, setAlbums] = useState(defaultAlbumsState);
     const [selectedAlbumKey, setSelectedAlbumKey] = useState(defaultAlbumKey);
     const [nextAlbumIndex, setNextAlbumIndex] = useState(1);
-    const [uploadQueueKeys, setUploadQueueKeys] = useState([]);
-    const [currentlyUploading, setCurrentlyUploading] = useState(false);
+    // album ids waiting to upload, in order. the queue itself lives in the backend.
+    const [uploadQueue, setUploadQueue] = useState([]);
+    // what the selected album's names turn into on the server
+    const [uploadNames, setUploadNames] = useState(null);
 
-    function findFileIndexWithPath(files, path) {
+    // same as Song::key in the backend, events and warnings name files by this
+    function songKey(file) {
+        return file.entry ? file.path + "!/" + file.entry : file.path;
+    }
+
+    function findFileIndexWithKey(files, key) {
         for (let i in files) {
             let file = files[i];
-            if (file.path == path) {
+            if (songKey(file) == key) {
                 return i;
             }
         }
@@ -77,31 +105,142 @@ export function useUploaderState(guiLog) {
             let payload = JSON.parse(event.payload);
             setFileState(payload.album_id, payload.file, fs_uploading);
         });
+        const unlistenAlbumCancelled = listen("music_uploader://album_cancelled", (event) => {
+            setAlbumState(event.payload, s_cancelled);
+            guiLog("album upload cancelled");
+        });
+        const unlistenFileCancelled = listen("music_uploader://file_cancelled", (event) => {
+            let payload = JSON.parse(event.payload);
+            setFileState(payload.album_id, payload.file, fs_cancelled);
+        });
+        const unlistenFileProgress = listen("music_uploader://file_progress", (event) => {
+            let payload = JSON.parse(event.payload);
+            setFileProgress(payload.album_id, payload.file, {
+                bytesSent: payload.bytes_sent,
+                totalBytes: payload.total_bytes,
+                bytesPerSecond: payload.bytes_per_second,
+            });
+        });
+        const unlistenQueueChanged = listen("music_uploader://queue_changed", (event) => {
+            applyQueueSnapshot(JSON.parse(event.payload));
+        });
+        list_upload_queue()
+            .then(snapshot => applyQueueSnapshot(snapshot))
+            .catch(e => guiLog(e));
         return () => {
             unlistenFileReport.then(f => f());
             unlistenAlbumReport.then(f => f());
             unlistenAlbumIsUploading.then(f => f());
             unlistenFileIsUploading.then(f => f());
+            unlistenFileProgress.then(f => f());
+            unlistenAlbumCancelled.then(f => f());
+            unlistenFileCancelled.then(f => f());
+            unlistenQueueChanged.then(f => f());
         };
     }, []);
 
+    const selectedAlbum = albums[selectedAlbumKey];
     useEffect(() => {
-        if (!currentlyUploading && uploadQueueKeys.length > 0) {
-            setCurrentlyUploading(true);
-            let nextUploadKey = uploadQueueKeys[0];
-            setUploadQueueKeys(prevKeys => {
-                let mutableKeys = clone(prevKeys);
-                mutableKeys.shift();
-                return mutableKeys;
-            });
-            let album = albums[nextUploadKey];
-            let songs = album.files.map(file => fileToSong(file));
-            upload_album(album, songs).then(result => {
-                setCurrentlyUploading(false);
-                guiLog(result);
-            });
+        if (selectedAlbum === undefined) {
+            setUploadNames(null);
+            return;
+        }
+        // a slower preview for an older name must not overwrite a newer one
+        let outdated = false;
+        preview_upload_names(selectedAlbum.name, selectedAlbum.artist, selectedAlbum.files.map(file => fileToSong(file)))
+            .then(names => {
+                if (!outdated) {
+                    setUploadNames(names);
+                }
+            })
+            .catch(e => guiLog(e));
+        return () => {
+            outdated = true;
+        };
+    }, [selectedAlbumKey, selectedAlbum?.name, selectedAlbum?.artist, selectedAlbum?.files.length]);
+
+    // the backend queue outlives the webview, so albums it knows about may be missing here after a reload.
+    function applyQueueSnapshot(snapshot) {
+        let queuedAlbums = snapshot.queued;
+        let allAlbums = snapshot.uploading === null ? queuedAlbums : [snapshot.uploading, ...queuedAlbums];
+        setUploadQueue(queuedAlbums.map(queuedAlbum => queuedAlbum.album_id));
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            for (let queuedAlbum of allAlbums) {
+                if (mutableAlbums[queuedAlbum.album_id] === undefined) {
+                    mutableAlbums[queuedAlbum.album_id] = queuedAlbumToAlbum(queuedAlbum);
+                }
+                mutableAlbums[queuedAlbum.album_id].state = queuedAlbum === snapshot.uploading ? s_uploading : s_in_queue;
+            }
+            return mutableAlbums;
+        });
+        let highestId = Math.max(-1, ...allAlbums.map(queuedAlbum => parseInt(queuedAlbum.album_id)));
+        setNextAlbumIndex(prevIndex => Math.max(prevIndex, highestId + 1));
+    }
+
+    function queuedAlbumToAlbum(queuedAlbum) {
+        let id = parseInt(queuedAlbum.album_id);
+        return {
+            ...newAlbumWithIndex(id),
+            name: queuedAlbum.album_name,
+            artist: queuedAlbum.artist,
+            files: queuedAlbum.songs.map(song => ({
+                id: songKey(song),
+                name: song.song_name,
+                path: song.path,
+                entry: song.entry,
+                state: fs_not_uploaded,
+            })),
+            resumeId: queuedAlbum.journal_id === null ? undefined : queuedAlbum.journal_id,
+            coverArt: queuedAlbum.cover_art,
+            profileId: queuedAlbum.profile_id,
+        };
+    }
+
+    // strict mode runs effects twice in dev, which would restore every album twice
+    const loadedResumableUploads = useRef(false);
+    useEffect(() => {
+        if (loadedResumableUploads.current) {
+            return;
         }
-    }, [currentlyUploading, uploadQueueKeys])
+        loadedResumableUploads.current = true;
+        loadResumableUploads();
+    }, []);
+
+    async function loadResumableUploads() {
+        let resumable = await list_resumable_uploads();
+        if (resumable.length == 0) {
+            return;
+        }
+        guiLog("found " + resumable.length + " unfinished upload(s), press upload on them to continue");
+        let restoredAlbums = {};
+        let index = nextAlbumIndex;
+        for (let journalAlbum of resumable) {
+            let files = [];
+            for (let song of journalAlbum.songs) {
+                let file = await pathToFilePathState(song.path, song.entry);
+                file.name = song.song_name;
+                file.state = song.complete ? fs_uploaded : fs_not_uploaded;
+                files.push(file);
+            }
+            restoredAlbums[index] = {
+                ...newAlbumWithIndex(index),
+                name: journalAlbum.album_name,
+                artist: journalAlbum.artist,
+                files,
+                state: s_resumable,
+                resumeId: journalAlbum.journal_id,
+                coverArt: journalAlbum.cover_art,
+                profileId: journalAlbum.profile_id,
+            };
+            index += 1;
+        }
+        setAlbums(prevAlbums => ({...clone(prevAlbums), ...restoredAlbums}));
+        setNextAlbumIndex(index);
+        for (let albumId in restoredAlbums) {
+            refreshCoverArt(albumId, restoredAlbums[albumId].files);
+        }
+    }
 
     function createAndSelectNewAlbum() {
         setAlbums(prevAlbums => {
@@ -135,12 +274,170 @@ export function useUploaderState(guiLog) {
         if (failToUpload) {
             return;
         }
-        setAlbumState(albumId, s_in_queue);
-        setUploadQueueKeys(prevKeys => {
-            let mutableKeys = clone(prevKeys);
-            mutableKeys.push(albumId);
-            return mutableKeys;
+        let songs = album.files.map(file => fileToSong(file));
+        if (!album.warningsShown) {
+            let warnings = await validate_album(albumId, songs).catch(e => {
+                guiLog(e);
+                return [];
+            });
+            if (warnings.length > 0) {
+                for (let warning of warnings) {
+                    guiLog("Warning: " + describeWarning(warning));
+                }
+                guiLog("press upload again to upload anyway");
+                let duplicates = duplicateSongKeys(warnings);
+                setAlbums(prevAlbums => {
+                    let mutableAlbums = clone(prevAlbums);
+                    mutableAlbums[albumId].warningsShown = true;
+                    mutableAlbums[albumId].duplicates = duplicates;
+                    return mutableAlbums;
+                });
+                return;
+            }
+        }
+        enqueue_album(album, songs, album.resumeId === undefined ? null : album.resumeId)
+            .then(result => guiLog(result))
+            .catch(e => guiLog(e));
+    }
+
+    function describeWarning(warning) {
+        let fileNames = (paths) => paths.map(path => getFileName(path)).join(", ");
+        let describeGroups = (groups) => groups
+            .map(group => "\"" + group.value + "\" (" + fileNames(group.paths) + ")")
+            .join(" vs ");
+        let discPrefix = warning.disc === null || warning.disc === undefined ? "" : "disc " + warning.disc + " ";
+        switch (warning.kind) {
+            case "mismatched_album_tags":
+                return "songs are tagged with different albums: " + describeGroups(warning.values);
+            case "mismatched_artist_tags":
+                return "songs are tagged with different artists: " + describeGroups(warning.values);
+            case "duplicate_track_number":
+                return discPrefix + "track " + warning.track + " appears more than once: " + fileNames(warning.paths);
+            case "missing_tracks":
+                return discPrefix + "is missing track(s) " + warning.missing.join(", ");
+            case "mixed_formats":
+                return "songs are in different formats: " + describeGroups(warning.values);
+            case "invalid_audio_file":
+                return warning.error;
+            case "unreadable_tags":
+                return "could not read tags of " + getFileName(warning.path) + ": " + warning.error;
+            case "duplicate_songs":
+                return "these are the same song: " + fileNames(warning.paths);
+            case "queued_in_another_album":
+                return getFileName(warning.path) + " is the same song as " + getFileName(warning.other_path)
+                    + " which is already queued in " + (warning.album_name === "" ? "another album" : warning.album_name);
+            default:
+                return JSON.stringify(warning);
+        }
+    }
+
+    // every copy but the first of a song in the album, and songs some other queued album already has
+    function duplicateSongKeys(warnings) {
+        let keys = [];
+        for (let warning of warnings) {
+            if (warning.kind == "duplicate_songs") {
+                keys.push(...warning.paths.slice(1));
+            } else if (warning.kind == "queued_in_another_album") {
+                keys.push(warning.path);
+            }
+        }
+        return [...new Set(keys)];
+    }
+
+    function dropDuplicates() {
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[selectedAlbumKey];
+            let duplicates = mutableAlbum.duplicates ?? [];
+            mutableAlbum.files = mutableAlbum.files.filter(file => !duplicates.includes(songKey(file)));
+            mutableAlbum.duplicates = [];
+            // what is left gets checked again
+            mutableAlbum.warningsShown = false;
+            return mutableAlbums;
+        });
+    }
+
+    async function checkSongsOnServer() {
+        let albumId = selectedAlbumKey;
+        let songs = albums[albumId].files.map(file => fileToSong(file));
+        guiLog("checking which songs the server already has");
+        let result = await check_songs_on_server(songs, albums[albumId].profileId).catch(e => {
+            guiLog(e);
+            return null;
         });
+        if (result === null) {
+            return;
+        }
+        if (!result.supported) {
+            guiLog("the server does not support checking songs before uploading, every song will be uploaded");
+            return;
+        }
+        let checks = result.songs;
+        if (checks.length == 0) {
+            return;
+        }
+        let present = checks.filter(check => check.status == "present").length;
+        let unknown = checks.filter(check => check.status == "unknown").length;
+        guiLog(present + " of " + checks.length + " song(s) are already on the server, "
+            + (checks.length - present - unknown) + " will be uploaded"
+            + (unknown > 0 ? ", " + unknown + " could not be checked" : ""));
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[albumId];
+            if (mutableAlbum === undefined) {
+                return prevAlbums;
+            }
+            for (let check of checks) {
+                mutableAlbum.serverStatus[check.key] = check.status;
+            }
+            return mutableAlbums;
+        });
+    }
+
+    function skipSongsOnServer() {
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[selectedAlbumKey];
+            mutableAlbum.files = mutableAlbum.files.filter(file => mutableAlbum.serverStatus[songKey(file)] != "present");
+            return mutableAlbums;
+        });
+    }
+
+    // "present", "missing" or "unknown", undefined until checked
+    function getServerStatus(file) {
+        return getAlbumKey("serverStatus", {})[songKey(file)];
+    }
+
+    function getOnServerCount() {
+        let files = getAlbumKey("files", []);
+        return files.filter(file => getServerStatus(file) == "present").length;
+    }
+
+    function isDuplicate(file) {
+        return getAlbumKey("duplicates", []).includes(songKey(file));
+    }
+
+    function moveAlbumToFrontOfQueue(albumId) {
+        reorder_upload_queue(albumId, 0)
+            .then(result => guiLog(result))
+            .catch(e => guiLog(e));
+    }
+
+    function getQueuePosition(albumId) {
+        let position = uploadQueue.indexOf(albumId.toString());
+        return position == -1 ? null : position + 1;
+    }
+
+    function cancelAlbumUpload(albumId) {
+        let album = albums[albumId];
+        if (album === undefined) {
+            return;
+        }
+        if (album.state == s_in_queue || album.state == s_uploading) {
+            cancel_upload(albumId)
+                .then(result => guiLog(result))
+                .catch(e => guiLog(e));
+        }
     }
 
     function setAlbum(albumName) {
@@ -171,7 +468,7 @@ export function useUploaderState(guiLog) {
         setAlbums(prevAlbums => {
             let mutableAlbums = clone(prevAlbums);
             let mutableAlbum = mutableAlbums[albumId];
-            let fileIndex = findFileIndexWithPath(mutableAlbum.files, filePath);
+            let fileIndex = findFileIndexWithKey(mutableAlbum.files, filePath);
             if (fileIndex !== null) {
                 mutableAlbum.files[fileIndex].state = state;
             }
@@ -179,6 +476,21 @@ export function useUploaderState(guiLog) {
         });
     }
 
+    function setFileProgress(albumId, filePath, progress) {
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[albumId];
+            if (mutableAlbum === undefined) {
+                return prevAlbums;
+            }
+            let fileIndex = findFileIndexWithKey(mutableAlbum.files, filePath);
+            if (fileIndex !== null) {
+                mutableAlbum.files[fileIndex].progress = progress;
+            }
+            return mutableAlbums;
+        });
+    }
+
     function clone(thing) {
         return JSON.parse(JSON.stringify(thing));
     }
@@ -187,6 +499,7 @@ export function useUploaderState(guiLog) {
         return {
             song_name: fileState.name,
             path: fileState.path,
+            entry: fileState.entry,
         };
     }
 
@@ -194,31 +507,202 @@ export function useUploaderState(guiLog) {
         const validExtensions = await get_valid_extensions();
         if (paths && paths.length > 0) {
             let newFiles = [];
+            let otherPaths = [];
             for (let path of paths) {
                 if (!validExtensions.includes(getExtension(path))) {
-                    guiLog("skipping " + path + " because it does not have a valid extension");
+                    otherPaths.push(path);
                     continue;
                 }
-                if (findFileIndexWithPath(albums[selectedAlbumKey].files, path) != null) {
+                if (findFileIndexWithKey(albums[selectedAlbumKey].files, path) != null) {
                     guiLog("skipping " + path + " becasue it is already listed");
                     continue;
                 }
                 newFiles.push(await pathToFilePathState(path));
             }
+            for (let path of await addAlbumsFromDirectories(otherPaths)) {
+                guiLog("skipping " + path + " because it does not have a valid extension");
+            }
+            if (newFiles.length == 0) {
+                return;
+            }
+            let tags = await readTags(newFiles);
             setAlbums(prevAlbums => {
                 let mutableAlbums = clone(prevAlbums);
-                let prevFiles = mutableAlbums[selectedAlbumKey].files;
-                mutableAlbums[selectedAlbumKey].files = [...prevFiles, ...newFiles];
+                let mutableAlbum = mutableAlbums[selectedAlbumKey];
+                mutableAlbum.files = [...mutableAlbum.files, ...newFiles];
+                // new songs can bring new problems
+                mutableAlbum.warningsShown = false;
+                mutableAlbum.duplicates = [];
+                // only fill in what the user has not typed themselves
+                if (mutableAlbum.name === "" && tags.album !== null) {
+                    mutableAlbum.name = tags.album;
+                }
+                if (mutableAlbum.artist === "" && tags.artist !== null) {
+                    mutableAlbum.artist = tags.artist;
+                }
                 return mutableAlbums;
             })
+            refreshCoverArt(selectedAlbumKey, [...albums[selectedAlbumKey].files, ...newFiles]);
+        }
+    }
+
+    // looks for cover art among the album's files, picking the most likely one unless the user already chose
+    async function refreshCoverArt(albumId, files) {
+        let candidates = await find_cover_art(files.map(file => fileToSong(file)))
+            .catch(e => {
+                guiLog(e);
+                return [];
+            });
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[albumId];
+            if (mutableAlbum === undefined) {
+                return prevAlbums;
+            }
+            mutableAlbum.coverArtCandidates = candidates;
+            if (mutableAlbum.coverArt === undefined && candidates.length > 0) {
+                mutableAlbum.coverArt = candidates[0].source;
+            }
+            return mutableAlbums;
+        });
+    }
+
+    // index into the candidates, -1 for no cover art
+    function setCoverArt(index) {
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[selectedAlbumKey];
+            mutableAlbum.coverArt = index == -1 ? null : mutableAlbum.coverArtCandidates[index].source;
+            return mutableAlbums;
+        });
+    }
+
+    // what the server has is only known for the server that was asked
+    function setProfileId(profileId) {
+        setAlbums(prevAlbums => {
+            let mutableAlbums = clone(prevAlbums);
+            let mutableAlbum = mutableAlbums[selectedAlbumKey];
+            mutableAlbum.profileId = profileId;
+            mutableAlbum.serverStatus = {};
+            return mutableAlbums;
+        });
+    }
+
+    // null when the name is sent as is
+    function getUploadName(file) {
+        if (uploadNames === null) {
+            return null;
+        }
+        let song = uploadNames.songs.find(song => song.key == songKey(file));
+        return song === undefined || song.name == file.name ? null : song.name;
+    }
+
+    function getCoverArtIndex() {
+        let album = albums[selectedAlbumKey];
+        if (album === undefined || album.coverArt === undefined || album.coverArt === null) {
+            return -1;
+        }
+        let chosen = JSON.stringify(album.coverArt);
+        return album.coverArtCandidates.findIndex(candidate => JSON.stringify(candidate.source) == chosen);
+    }
+
+    // opens a tab for every album found under the dropped folders and zips, gives back the paths that were neither
+    const addAlbumsFromDirectories = async (paths) => {
+        let notDirectories = [];
+        let proposedAlbums = [];
+        for (let path of paths) {
+            try {
+                let scan = await scan_directory(path);
+                for (let warning of scan.warnings) {
+                    guiLog(warning);
+                }
+                guiLog("found " + scan.albums.length + " album(s) in " + path);
+                proposedAlbums.push(...scan.albums);
+            } catch (e) {
+                notDirectories.push(path);
+            }
+        }
+        if (proposedAlbums.length == 0) {
+            return notDirectories;
+        }
+        let newAlbums = {};
+        let index = nextAlbumIndex;
+        for (let proposedAlbum of proposedAlbums) {
+            let files = [];
+            for (let song of proposedAlbum.songs) {
+                let file = await pathToFilePathState(song.path, song.entry);
+                file.name = song.song_name;
+                files.push(file);
+            }
+            newAlbums[index] = {
+                ...newAlbumWithIndex(index),
+                name: proposedAlbum.album_name,
+                artist: proposedAlbum.artist,
+                files,
+            };
+            index += 1;
+        }
+        setAlbums(prevAlbums => ({...clone(prevAlbums), ...newAlbums}));
+        setSelectedAlbumKey(nextAlbumIndex);
+        setNextAlbumIndex(index);
+        for (let albumId in newAlbums) {
+            refreshCoverArt(albumId, newAlbums[albumId].files);
+        }
+        return notDirectories;
+    }
+
+    // names files after their title tag and works out which album and artist most of them agree on
+    const readTags = async (files) => {
+        let albumCounts = {};
+        let artistCounts = {};
+        let results = await read_song_metadata(files.map(file => fileToSong(file)))
+            .catch(e => {
+                guiLog(e);
+                return [];
+            });
+        for (let result of results) {
+            if (result.metadata === null) {
+                guiLog("could not read tags of " + result.path + ": " + result.error);
+                continue;
+            }
+            let metadata = result.metadata;
+            let file = files.find(file => file.path == result.path && file.entry == result.entry);
+            file.metadata = metadata;
+            if (metadata.title !== null) {
+                file.name = metadata.title + "." + getExtension(file.entry !== null ? file.entry : file.path);
+            }
+            countValue(albumCounts, metadata.album);
+            countValue(artistCounts, metadata.album_artist !== null ? metadata.album_artist : metadata.artist);
+        }
+        return {
+            album: mostCommon(albumCounts),
+            artist: mostCommon(artistCounts),
+        };
+    }
+
+    const countValue = (counts, value) => {
+        if (value !== null) {
+            counts[value] = (counts[value] || 0) + 1;
+        }
+    }
+
+    const mostCommon = (counts) => {
+        let best = null;
+        for (let value in counts) {
+            if (best === null || counts[value] > counts[best]) {
+                best = value;
+            }
         }
+        return best;
     }
 
-    const pathToFilePathState = async (path) => {
+    // entry is the name of the song inside the zip at path, if it is in one
+    const pathToFilePathState = async (path, entry = null) => {
         return {
             id: await generate_guid(),
-            name: getFileName(path),
+            name: getFileName(entry !== null ? entry : path),
             path: path,
+            entry: entry,
             state: fs_not_uploaded,
         }
     }
@@ -276,14 +760,31 @@ export function useUploaderState(guiLog) {
         album: getAlbumKey("name", ""),
         artist: getAlbumKey("artist", ""),
         files: getAlbumKey("files", []),
+        coverArtCandidates: getAlbumKey("coverArtCandidates", []),
+        coverArtIndex: getCoverArtIndex(),
+        profileId: getAlbumKey("profileId", null),
+        uploadNames,
+        duplicateCount: getAlbumKey("duplicates", []).length,
+        onServerCount: getOnServerCount(),
 
         albums,
         selectedAlbumKey,
 
         uploadAlbum,
+        cancelAlbumUpload,
+        moveAlbumToFrontOfQueue,
+        getQueuePosition,
         addSongPaths,
         setAlbum,
         setArtist,
+        setCoverArt,
+        setProfileId,
+        getUploadName,
+        isDuplicate,
+        dropDuplicates,
+        checkSongsOnServer,
+        skipSongsOnServer,
+        getServerStatus,
 
         selectAlbumId,
         closeAlbumId,